@@ -1,40 +1,182 @@
+use crate::diagnostics::Span;
+
+/// A syntax tree node: what it is, and where in the source it came from.
 #[derive(Debug, Clone, PartialEq)]
-pub enum AstNode {
+pub struct AstNode {
+    pub kind: NodeKind,
+    pub span: Span,
+}
+
+impl AstNode {
+    pub fn new(kind: NodeKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// The expression a place like `p.xz` or `a[i].f` is rooted in: usually
+    /// the variable being written.
+    pub fn root(&self) -> &AstNode {
+        let mut root = self;
+        while let NodeKind::MemberAccess { base, .. } | NodeKind::Swizzle { base, .. } | NodeKind::SubscriptAccess { base, .. } = &root.kind {
+            root = base;
+        }
+        root
+    }
+
+    /// The direct child nodes, in source order.
+    pub fn children(&self) -> Vec<&AstNode> {
+        match &self.kind {
+            NodeKind::Program(nodes) | NodeKind::Block(nodes) => nodes.iter().collect(),
+            NodeKind::FunctionDecl { body, .. } => vec![body],
+            NodeKind::StructDecl { .. } | NodeKind::Import { .. } => vec![],
+            NodeKind::ImplBlock { methods, .. } => methods.iter().collect(),
+            NodeKind::VarDecl { value, .. } => value.iter().map(|v| v.as_ref()).collect(),
+            NodeKind::ArrayDecl { value, .. } => value.iter().map(|v| v.as_ref()).collect(),
+            NodeKind::Assignment { target, value } | NodeKind::CompoundAssignment { target, value, .. } => vec![target, value],
+            NodeKind::ReturnStmt(expr) => vec![expr],
+            NodeKind::IfStmt { condition, then_branch, else_branch } => {
+                let mut c = vec![condition.as_ref(), then_branch.as_ref()];
+                c.extend(else_branch.as_deref());
+                c
+            },
+            NodeKind::ForStmt { init, condition, increment, body } => vec![init, condition, increment, body],
+            NodeKind::WhileStmt { condition, body } => vec![condition, body],
+            NodeKind::DoWhileStmt { body, condition } => vec![body, condition],
+            NodeKind::LoopStmt(body) => vec![body],
+            NodeKind::SwitchStmt { selector, cases, default } => {
+                let mut c = vec![selector.as_ref()];
+                c.extend(cases.iter().map(|case| &case.body));
+                c.extend(default.as_deref());
+                c
+            },
+            NodeKind::BreakStmt | NodeKind::ContinueStmt => vec![],
+            NodeKind::BinaryOp { left, right, .. } => vec![left, right],
+            NodeKind::UnaryOp { right, .. } => vec![right],
+            NodeKind::Ternary { condition, then_expr, else_expr, .. } => vec![condition, then_expr, else_expr],
+            NodeKind::Call { args, .. } | NodeKind::ArrayLiteral(args) => args.iter().collect(),
+            NodeKind::StructLiteral { fields, .. } => fields.iter().map(|(_, v)| v).collect(),
+            NodeKind::MethodCall { receiver, args, .. } => std::iter::once(receiver.as_ref()).chain(args).collect(),
+            NodeKind::SubscriptAccess { base, index } => vec![base, index],
+            NodeKind::MemberAccess { base, .. } | NodeKind::Swizzle { base, .. } => vec![base],
+            NodeKind::LiteralFloat { .. } | NodeKind::LiteralInt(_) | NodeKind::LiteralUint(_)
+            | NodeKind::LiteralBool(_) | NodeKind::Variable(_) => vec![],
+        }
+    }
+
+    /// Mutable access to the direct child nodes, for passes that rewrite the tree.
+    pub fn children_mut(&mut self) -> Vec<&mut AstNode> {
+        match &mut self.kind {
+            NodeKind::Program(nodes) | NodeKind::Block(nodes) => nodes.iter_mut().collect(),
+            NodeKind::FunctionDecl { body, .. } => vec![body],
+            NodeKind::StructDecl { .. } | NodeKind::Import { .. } => vec![],
+            NodeKind::ImplBlock { methods, .. } => methods.iter_mut().collect(),
+            NodeKind::VarDecl { value, .. } | NodeKind::ArrayDecl { value, .. } => value.iter_mut().map(|v| v.as_mut()).collect(),
+            NodeKind::Assignment { target, value } | NodeKind::CompoundAssignment { target, value, .. } => vec![target, value],
+            NodeKind::ReturnStmt(expr) => vec![expr],
+            NodeKind::IfStmt { condition, then_branch, else_branch } => {
+                let mut c = vec![condition.as_mut(), then_branch.as_mut()];
+                c.extend(else_branch.as_deref_mut());
+                c
+            },
+            NodeKind::ForStmt { init, condition, increment, body } => vec![init, condition, increment, body],
+            NodeKind::WhileStmt { condition, body } => vec![condition, body],
+            NodeKind::DoWhileStmt { body, condition } => vec![body, condition],
+            NodeKind::LoopStmt(body) => vec![body],
+            NodeKind::SwitchStmt { selector, cases, default } => {
+                let mut c = vec![selector.as_mut()];
+                c.extend(cases.iter_mut().map(|case| &mut case.body));
+                c.extend(default.as_deref_mut());
+                c
+            },
+            NodeKind::BreakStmt | NodeKind::ContinueStmt => vec![],
+            NodeKind::BinaryOp { left, right, .. } => vec![left, right],
+            NodeKind::UnaryOp { right, .. } => vec![right],
+            NodeKind::Ternary { condition, then_expr, else_expr, .. } => vec![condition, then_expr, else_expr],
+            NodeKind::Call { args, .. } | NodeKind::ArrayLiteral(args) => args.iter_mut().collect(),
+            NodeKind::StructLiteral { fields, .. } => fields.iter_mut().map(|(_, v)| v).collect(),
+            NodeKind::MethodCall { receiver, args, .. } => std::iter::once(receiver.as_mut()).chain(args).collect(),
+            NodeKind::SubscriptAccess { base, index } => vec![base, index],
+            NodeKind::MemberAccess { base, .. } | NodeKind::Swizzle { base, .. } => vec![base],
+            NodeKind::LiteralFloat { .. } | NodeKind::LiteralInt(_) | NodeKind::LiteralUint(_)
+            | NodeKind::LiteralBool(_) | NodeKind::Variable(_) => vec![],
+        }
+    }
+
+    /// Visits this node and all of its descendants, parents first.
+    pub fn walk(&self, f: &mut dyn FnMut(&AstNode)) {
+        f(self);
+        for child in self.children() {
+            child.walk(f);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeKind {
     Program(Vec<AstNode>),
-    
+
     // Declarations
-    FunctionDecl { 
-        return_type: String, 
-        name: String, 
-        args: Vec<(String, String)>, 
-        body: Box<AstNode>, 
-        doc_string: Option<String> 
-    },
-    StructDecl { 
-        name: String, 
-        fields: Vec<(String, String)>, 
-        doc_string: Option<String> 
-    },
-    VarDecl { 
-        type_name: String, 
-        name: String, 
-        value: Option<Box<AstNode>> 
-    },
-    ArrayDecl { 
-        type_name: String, 
-        name: String, 
-        size: usize, 
-        values: Option<Vec<AstNode>> 
+    FunctionDecl {
+        return_type: String,
+        name: String,
+        args: Vec<Param>,
+        body: Box<AstNode>,
+        doc_string: Option<String>,
+        /// Unique symbol for this overload when `name` is overloaded (set by
+        /// name resolution), e.g. `mix_vec2_vec2_vec2`.
+        overload: Option<String>,
+        /// Declared `pub`: visible to modules that import this one.
+        public: bool,
+    },
+    StructDecl {
+        name: String,
+        fields: Vec<Field>,
+        doc_string: Option<String>,
+        public: bool,
+    },
+    /// `impl Ray { fn at(self, t: float) vec3 { ... } }`; each method is a
+    /// `FunctionDecl` whose first parameter is `self: Ray`.
+    ImplBlock {
+        type_name: String,
+        methods: Vec<AstNode>
+    },
+    VarDecl {
+        binding: BindingKind,
+        type_name: String,
+        name: String,
+        value: Option<Box<AstNode>>,
+        /// Only module-scope constants carry docs, and can be `pub`.
+        doc_string: Option<String>,
+        public: bool,
+    },
+    /// `var w: array<float, 5> = [..]` or C-style `float w[5] = {..}`.
+    /// `type_name` is the element type.
+    ArrayDecl {
+        binding: BindingKind,
+        type_name: String,
+        name: String,
+        size: usize,
+        value: Option<Box<AstNode>>,
+        doc_string: Option<String>,
+        public: bool,
+    },
+    /// `import sdf`, `import shapes.sdf` or `import sdf.{sdBox, sdSphere}`.
+    /// Replaced by the imported items when modules are linked.
+    Import {
+        path: Vec<String>,
+        /// The names brought into scope unqualified, if any.
+        items: Option<Vec<String>>,
     },
 
     // Statements
     Block(Vec<AstNode>),
     Assignment { target: Box<AstNode>, value: Box<AstNode> },
+    /// `target op= value`
+    CompoundAssignment { target: Box<AstNode>, op: BinaryOperator, value: Box<AstNode> },
     ReturnStmt(Box<AstNode>),
-    IfStmt { 
-        condition: Box<AstNode>, 
-        then_branch: Box<AstNode>, 
-        else_branch: Option<Box<AstNode>> 
+    IfStmt {
+        condition: Box<AstNode>,
+        then_branch: Box<AstNode>,
+        else_branch: Option<Box<AstNode>>
     },
     ForStmt {
         init: Box<AstNode>,
@@ -42,47 +184,227 @@ pub enum AstNode {
         increment: Box<AstNode>,
         body: Box<AstNode>,
     },
+    WhileStmt {
+        condition: Box<AstNode>,
+        body: Box<AstNode>,
+    },
+    DoWhileStmt {
+        body: Box<AstNode>,
+        condition: Box<AstNode>,
+    },
+    /// `loop { ... }`: runs until a `break` or `return`.
+    LoopStmt(Box<AstNode>),
+    /// `switch (selector) { case 1, 2..4: ... default: ... }`; no fallthrough.
+    SwitchStmt {
+        selector: Box<AstNode>,
+        cases: Vec<SwitchCase>,
+        default: Option<Box<AstNode>>,
+    },
     BreakStmt,
+    ContinueStmt,
 
     // Expressions
-    BinaryOp { 
-        left: Box<AstNode>, 
-        op: BinaryOperator, 
-        right: Box<AstNode> 
+    BinaryOp {
+        left: Box<AstNode>,
+        op: BinaryOperator,
+        right: Box<AstNode>
+    },
+    UnaryOp {
+        op: UnaryOperator,
+        right: Box<AstNode>
+    },
+    /// `condition ? then_expr : else_expr`
+    Ternary {
+        condition: Box<AstNode>,
+        then_expr: Box<AstNode>,
+        else_expr: Box<AstNode>,
+        /// The type of the result, filled in by name resolution: WGSL's
+        /// `select` only chooses between scalars and vectors.
+        result_type: String
+    },
+    Call {
+        func_name: String,
+        args: Vec<AstNode>,
+        /// The chosen overload's symbol, when `func_name` is overloaded.
+        overload: Option<String>,
+        /// The argument types of a call to a built-in or a matrix constructor,
+        /// filled in by name resolution for backends that lower it to a
+        /// helper of their own.
+        arg_types: Vec<String>
+    },
+    /// `receiver.method(args)`; `receiver_type` is filled in by name resolution.
+    MethodCall {
+        receiver: Box<AstNode>,
+        method: String,
+        args: Vec<AstNode>,
+        receiver_type: String
     },
-    UnaryOp { 
-        op: UnaryOperator, 
-        right: Box<AstNode> 
+    /// `[a, b, c]` (or `{a, b, c}` in a C-style array initializer)
+    ArrayLiteral(Vec<AstNode>),
+    /// `Material(color: c, rough: 0.2)` or positional `Material(c, 0.2)`. After
+    /// parsing, `fields` are complete and in declaration order.
+    StructLiteral {
+        name: String,
+        fields: Vec<(String, AstNode)>
     },
-    Call { 
-        func_name: String, 
-        args: Vec<AstNode> 
+    SubscriptAccess {
+        base: Box<AstNode>,
+        index: Box<AstNode>
     },
-    SubscriptAccess { 
-        base: Box<AstNode>, 
-        index: Box<AstNode> 
+    MemberAccess {
+        base: Box<AstNode>,
+        member: String
     },
-    MemberAccess { 
-        base: Box<AstNode>, 
-        member: String 
+    /// `v.xzy`: parsed as a `MemberAccess`, and told apart by name resolution
+    /// once the base is known to be a vector.
+    Swizzle {
+        base: Box<AstNode>,
+        components: String
     },
-    
+
     // Literals
-    LiteralFloat(f64),
+    /// `text` is the canonical spelling (no suffix, always with `.` or an
+    /// exponent) so backends can emit it without a lossy round-trip.
+    LiteralFloat { value: f64, text: String },
     LiteralInt(i64),
+    LiteralUint(u64),
+    LiteralBool(bool),
     Variable(String),
 }
 
+/// Spells the array type `array<element, size>`; this is how array types are
+/// stored in declarations, parameters and return types.
+pub fn array_type(element: &str, size: usize) -> String {
+    format!("array<{}, {}>", element, size)
+}
+
+/// The free function a method is lowered to: `Ray.at` becomes `Ray_at`.
+pub fn method_name(type_name: &str, method: &str) -> String {
+    format!("{}_{}", type_name, method)
+}
+
+/// Splits an `array<element, size>` type into its element type and size.
+pub fn split_array_type(type_name: &str) -> Option<(&str, usize)> {
+    let inner = type_name.strip_prefix("array<")?.strip_suffix('>')?;
+    let (element, size) = inner.rsplit_once(',')?;
+    Some((element.trim(), size.trim().parse().ok()?))
+}
+
+/// A function parameter, with any `///` docs written just before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub type_name: String,
+    pub name: String,
+    pub qualifier: ParamQualifier,
+    pub doc_string: Option<String>,
+}
+
+/// How an argument is passed. `out` and `inout` parameters write back to the caller's variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParamQualifier {
+    #[default]
+    In,    // by value (the default; also written `in`)
+    Out,   // written by the callee, the caller's value is not read
+    InOut, // read and written
+}
+
+impl ParamQualifier {
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword {
+            "in" => Some(ParamQualifier::In),
+            "out" => Some(ParamQualifier::Out),
+            "inout" => Some(ParamQualifier::InOut),
+            _ => None,
+        }
+    }
+
+    pub fn keyword(self) -> &'static str {
+        match self {
+            ParamQualifier::In => "in",
+            ParamQualifier::Out => "out",
+            ParamQualifier::InOut => "inout",
+        }
+    }
+
+    /// Whether the callee writes back to the argument, which must then be a variable.
+    pub fn is_reference(self) -> bool {
+        self != ParamQualifier::In
+    }
+}
+
+/// A struct field, with any `///` docs written just before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub type_name: String,
+    pub name: String,
+    pub doc_string: Option<String>,
+}
+
+/// How a variable was introduced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingKind {
+    Var,   // var x: T / C-style `T x`
+    Let,   // immutable local
+    Const, // compile-time constant (also allowed at module scope)
+}
+
+impl BindingKind {
+    pub fn keyword(self) -> &'static str {
+        match self { BindingKind::Var => "var", BindingKind::Let => "let", BindingKind::Const => "const" }
+    }
+}
+
+/// One `case` arm: its selector values (ranges already expanded) and body block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwitchCase {
+    pub values: Vec<i64>,
+    pub body: AstNode,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum BinaryOperator {
     Add, Sub, Mul, Div,
+    Mod,          // % (integer remainder, as in GLSL; floats use mod())
     Equal, Less, Greater,
+    NotEqual,     // !=
     LessEqual,    // <=
     GreaterEqual, // >=
+    And,          // &&
+    Or,           // ||
+    BitAnd,       // &
+    BitOr,        // |
+    BitXor,       // ^
+    ShiftLeft,    // <<
+    ShiftRight,   // >>
+    /// `*` of a matrix by a matrix or vector, as name resolution marks it for
+    /// backends that write these products differently (HLSL's `mul`).
+    MatrixMul,
+}
+
+impl BinaryOperator {
+    /// The operator as written in S2L, for messages.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOperator::Add => "+", BinaryOperator::Sub => "-", BinaryOperator::Mul | BinaryOperator::MatrixMul => "*", BinaryOperator::Div => "/",
+            BinaryOperator::Mod => "%", BinaryOperator::Equal => "==", BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<", BinaryOperator::Greater => ">", BinaryOperator::LessEqual => "<=",
+            BinaryOperator::GreaterEqual => ">=", BinaryOperator::And => "&&", BinaryOperator::Or => "||",
+            BinaryOperator::BitAnd => "&", BinaryOperator::BitOr => "|", BinaryOperator::BitXor => "^",
+            BinaryOperator::ShiftLeft => "<<", BinaryOperator::ShiftRight => ">>",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum UnaryOperator {
     Negate, // -x
     Not,    // !x
+    BitNot, // ~x
+}
+
+impl UnaryOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self { UnaryOperator::Negate => "-", UnaryOperator::Not => "!", UnaryOperator::BitNot => "~" }
+    }
 }
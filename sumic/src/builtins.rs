@@ -0,0 +1,348 @@
+use crate::types::{promotes, Scalar, Type};
+
+/// A language the generators write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Wgsl,
+    Metal,
+    /// Desktop GLSL and GLSL ES 3.00.
+    Glsl,
+    /// GLSL ES 1.00, as WebGL 1 takes it.
+    Glsl100,
+    Hlsl,
+}
+
+/// How a backend writes a call to a built-in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spelling {
+    /// The S2L call as is.
+    Same,
+    /// The same arguments under another name (`inverseSqrt`).
+    Renamed(&'static str),
+    /// A component-wise binary operator: `lessThan(a, b)` is `(a < b)`.
+    Operator(&'static str),
+    /// A prefix operator: `not(v)` is `(!v)`.
+    Prefix(&'static str),
+    /// A product with a constant: `degrees(x)` is `(x * 57.29…)`.
+    Times(&'static str),
+    /// GLSL's floored `mod`, expanded since `%` and `fmod` truncate.
+    FlooredMod,
+    /// Not available; the generator's `validate` reports calls.
+    Unavailable,
+}
+
+/// How a built-in's parameter relates to its generic type `T`.
+#[derive(Clone, Copy)]
+enum Shape {
+    /// The generic type itself.
+    T,
+    /// `T`, or a scalar of `T`'s element type that every backend takes as
+    /// is (`mix(a, b, 0.5)`).
+    TOrScalar,
+    /// `T`, or a scalar that is splatted to `T` (`clamp(v, 0.0, 1.0)`).
+    TOrSplat,
+    /// A scalar of `T`'s element type.
+    Scalar,
+}
+
+/// What a built-in returns.
+#[derive(Clone, Copy)]
+enum Returns {
+    T,
+    Scalar,
+}
+
+/// The argument types a built-in accepts.
+#[derive(Clone, Copy)]
+enum Signature {
+    /// Component-wise, as in GLSL: `T` is a float scalar or vector, or with
+    /// `integers` any int or uint one as well.
+    Generic { params: &'static [Shape], returns: Returns, integers: bool },
+    /// Checked by a function of the name and argument types, which also
+    /// words the error. Arguments are never converted.
+    Fixed { arity: usize, check: fn(&str, &[Type]) -> Result<Type, String> },
+}
+
+/// One form of a built-in function, and how each backend writes it.
+struct Builtin {
+    name: &'static str,
+    signature: Signature,
+    wgsl: Spelling,
+    metal: Spelling,
+    glsl100: Spelling,
+    hlsl: Spelling,
+}
+
+impl Builtin {
+    const fn wgsl(self, wgsl: Spelling) -> Self {
+        Builtin { wgsl, ..self }
+    }
+
+    const fn metal(self, metal: Spelling) -> Self {
+        Builtin { metal, ..self }
+    }
+
+    const fn glsl100(self, glsl100: Spelling) -> Self {
+        Builtin { glsl100, ..self }
+    }
+
+    const fn hlsl(self, hlsl: Spelling) -> Self {
+        Builtin { hlsl, ..self }
+    }
+
+    fn arity(&self) -> usize {
+        match self.signature {
+            Signature::Generic { params, .. } => params.len(),
+            Signature::Fixed { arity, .. } => arity,
+        }
+    }
+
+    fn spelling(&self, backend: Backend) -> Spelling {
+        match backend {
+            Backend::Wgsl => self.wgsl,
+            Backend::Metal => self.metal,
+            // S2L names its built-ins as GLSL does.
+            Backend::Glsl => Spelling::Same,
+            Backend::Glsl100 => self.glsl100,
+            Backend::Hlsl => self.hlsl,
+        }
+    }
+}
+
+/// A form every backend calls as is, until overridden.
+const fn form(name: &'static str, signature: Signature) -> Builtin {
+    Builtin { name, signature, wgsl: Spelling::Same, metal: Spelling::Same, glsl100: Spelling::Same, hlsl: Spelling::Same }
+}
+
+const fn generic(name: &'static str, params: &'static [Shape], returns: Returns, integers: bool) -> Builtin {
+    form(name, Signature::Generic { params, returns, integers })
+}
+
+const fn fixed(name: &'static str, arity: usize, check: fn(&str, &[Type]) -> Result<Type, String>) -> Builtin {
+    form(name, Signature::Fixed { arity, check })
+}
+
+fn cross(_: &str, args: &[Type]) -> Result<Type, String> {
+    match args {
+        [a, b] if *a == Type::Vector(Scalar::Float, 3) && a == b => Ok(a.clone()),
+        _ => Err("`cross` takes two `vec3`s".into()),
+    }
+}
+
+fn matrix(name: &str, args: &[Type]) -> Result<Type, String> {
+    match (name, args) {
+        ("determinant", [Type::Matrix(_)]) => Ok(Type::FLOAT),
+        (_, [m @ Type::Matrix(_)]) => Ok(m.clone()),
+        _ => Err(format!("`{}` takes a matrix", name)),
+    }
+}
+
+/// `lessThan` and the other vector comparisons: a `bvec` of the width of
+/// the two vectors compared.
+fn compare(name: &str, args: &[Type]) -> Result<Type, String> {
+    let equality = matches!(name, "equal" | "notEqual");
+    match args {
+        [a @ Type::Vector(s, n), b] if a == b && (s.is_numeric() || equality) => Ok(Type::Vector(Scalar::Bool, *n)),
+        _ if equality => Err(format!("`{}` takes two vectors of the same type", name)),
+        _ => Err(format!("`{}` takes two numeric vectors of the same type", name)),
+    }
+}
+
+/// `any`, `all` and `not`, on a `bvec`.
+fn logical(name: &str, args: &[Type]) -> Result<Type, String> {
+    match (name, args) {
+        ("not", [v @ Type::Vector(Scalar::Bool, _)]) => Ok(v.clone()),
+        (_, [Type::Vector(Scalar::Bool, _)]) if name != "not" => Ok(Type::BOOL),
+        _ => Err(format!("`{}` takes a `bvec`", name)),
+    }
+}
+
+/// Every form of every built-in, with the GLSL name S2L uses.
+const BUILTINS: &[Builtin] = {
+    use Shape::*;
+    use Spelling::*;
+    const UNARY: &[Shape] = &[T];
+    const BINARY: &[Shape] = &[T, T];
+    &[
+        generic("abs", UNARY, Returns::T, true),
+        generic("acos", UNARY, Returns::T, false),
+        fixed("all", 1, logical),
+        fixed("any", 1, logical),
+        generic("asin", UNARY, Returns::T, false),
+        generic("atan", UNARY, Returns::T, false),
+        generic("atan", BINARY, Returns::T, false).wgsl(Renamed("atan2")).metal(Renamed("atan2")).hlsl(Renamed("atan2")),
+        generic("ceil", UNARY, Returns::T, false),
+        generic("clamp", &[T, TOrSplat, TOrSplat], Returns::T, true),
+        generic("cos", UNARY, Returns::T, false),
+        generic("cosh", UNARY, Returns::T, false).glsl100(Unavailable),
+        fixed("cross", 2, cross),
+        generic("degrees", UNARY, Returns::T, false).metal(Times("57.29577951308232")),
+        fixed("determinant", 1, matrix).glsl100(Unavailable),
+        generic("dFdx", UNARY, Returns::T, false).wgsl(Renamed("dpdx")).metal(Renamed("dfdx")).glsl100(Unavailable).hlsl(Renamed("ddx")),
+        generic("dFdy", UNARY, Returns::T, false).wgsl(Renamed("dpdy")).metal(Renamed("dfdy")).glsl100(Unavailable).hlsl(Renamed("ddy")),
+        generic("distance", BINARY, Returns::Scalar, false),
+        generic("dot", BINARY, Returns::Scalar, false),
+        fixed("equal", 2, compare).wgsl(Operator("==")).metal(Operator("==")).hlsl(Operator("==")),
+        generic("exp", UNARY, Returns::T, false),
+        generic("exp2", UNARY, Returns::T, false),
+        generic("faceforward", &[T, T, T], Returns::T, false).wgsl(Renamed("faceForward")),
+        generic("floor", UNARY, Returns::T, false),
+        generic("fract", UNARY, Returns::T, false).hlsl(Renamed("frac")),
+        generic("fwidth", UNARY, Returns::T, false).glsl100(Unavailable),
+        fixed("greaterThan", 2, compare).wgsl(Operator(">")).metal(Operator(">")).hlsl(Operator(">")),
+        fixed("greaterThanEqual", 2, compare).wgsl(Operator(">=")).metal(Operator(">=")).hlsl(Operator(">=")),
+        fixed("inverse", 1, matrix).wgsl(Unavailable).metal(Unavailable).glsl100(Unavailable).hlsl(Unavailable),
+        generic("inversesqrt", UNARY, Returns::T, false).wgsl(Renamed("inverseSqrt")).metal(Renamed("rsqrt")).hlsl(Renamed("rsqrt")),
+        generic("length", UNARY, Returns::Scalar, false),
+        fixed("lessThan", 2, compare).wgsl(Operator("<")).metal(Operator("<")).hlsl(Operator("<")),
+        fixed("lessThanEqual", 2, compare).wgsl(Operator("<=")).metal(Operator("<=")).hlsl(Operator("<=")),
+        generic("log", UNARY, Returns::T, false),
+        generic("log2", UNARY, Returns::T, false),
+        generic("max", &[T, TOrSplat], Returns::T, true),
+        generic("min", &[T, TOrSplat], Returns::T, true),
+        generic("mix", &[T, T, TOrScalar], Returns::T, false).hlsl(Renamed("lerp")),
+        generic("mod", &[T, TOrScalar], Returns::T, false).wgsl(FlooredMod).metal(FlooredMod).hlsl(FlooredMod),
+        generic("normalize", UNARY, Returns::T, false),
+        fixed("not", 1, logical).wgsl(Prefix("!")).metal(Prefix("!")).hlsl(Prefix("!")),
+        fixed("notEqual", 2, compare).wgsl(Operator("!=")).metal(Operator("!=")).hlsl(Operator("!=")),
+        generic("pow", &[T, TOrSplat], Returns::T, false),
+        generic("radians", UNARY, Returns::T, false).metal(Times("0.017453292519943295")),
+        generic("reflect", BINARY, Returns::T, false),
+        generic("refract", &[T, T, Scalar], Returns::T, false),
+        generic("round", UNARY, Returns::T, false).glsl100(Unavailable),
+        generic("sign", UNARY, Returns::T, true),
+        generic("sin", UNARY, Returns::T, false),
+        generic("sinh", UNARY, Returns::T, false).glsl100(Unavailable),
+        generic("smoothstep", &[TOrSplat, TOrSplat, T], Returns::T, false),
+        generic("sqrt", UNARY, Returns::T, false),
+        generic("step", &[TOrSplat, T], Returns::T, false),
+        generic("tan", UNARY, Returns::T, false),
+        generic("tanh", UNARY, Returns::T, false).glsl100(Unavailable),
+        fixed("transpose", 1, matrix).glsl100(Unavailable),
+        generic("trunc", UNARY, Returns::T, false).glsl100(Unavailable),
+    ]
+};
+
+/// Functions provided by every backend. A user function may add overloads to
+/// one of these; calls that match none of them go to the built-in.
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.iter().any(|b| b.name == name)
+}
+
+/// The name of every built-in, once per form.
+pub fn names<'a>() -> impl Iterator<Item = &'a str> {
+    BUILTINS.iter().map(|b| b.name)
+}
+
+/// How `backend` writes a call to the built-in `name` with `arity`
+/// arguments. `None` if there is no such built-in.
+pub fn spelling(name: &str, arity: usize, backend: Backend) -> Option<Spelling> {
+    let mut forms = BUILTINS.iter().filter(|b| b.name == name);
+    let first = forms.clone().next()?;
+    Some(forms.find(|b| b.arity() == arity).unwrap_or(first).spelling(backend))
+}
+
+/// Checks a call to a built-in function and returns its result type. The
+/// error names the forms the built-in does accept.
+pub fn result(name: &str, args: &[Type]) -> Result<Type, String> {
+    let forms: Vec<_> = BUILTINS.iter().filter(|b| b.name == name).collect();
+    if let [Builtin { signature: Signature::Fixed { check, .. }, .. }] = forms.as_slice() {
+        return check(name, args);
+    }
+
+    for form in &forms {
+        let Signature::Generic { params, returns, integers } = form.signature else { continue };
+        if params.len() != args.len() { continue; }
+        let Some(t) = params.iter().zip(args).find(|(p, _)| matches!(p, Shape::T)).map(|(_, a)| a) else { continue };
+        let generic = t.is_float_like() || (integers && matches!(t, Type::Scalar(s) | Type::Vector(s, _) if s.is_integer()));
+        let Some(element) = t.scalar().filter(|_| generic) else { continue };
+        let fits = params.iter().zip(args).all(|(p, a)| match p {
+            Shape::T => a == t,
+            Shape::TOrScalar | Shape::TOrSplat => a == t || *a == Type::Scalar(element),
+            Shape::Scalar => *a == Type::Scalar(element),
+        });
+        if fits {
+            return Ok(match returns { Returns::T => t.clone(), Returns::Scalar => Type::Scalar(element) });
+        }
+    }
+
+    let shown: Vec<_> = forms.iter().filter_map(|form| match form.signature {
+        Signature::Generic { params, .. } => Some(params),
+        Signature::Fixed { .. } => None,
+    }).map(|params| {
+        let params: Vec<_> = params.iter().map(|p| match p {
+            Shape::T => "T",
+            Shape::TOrScalar | Shape::TOrSplat => "T or float",
+            Shape::Scalar => "float",
+        }).collect();
+        format!("`{}({})`", name, params.join(", "))
+    }).collect();
+    let integers = match forms.iter().any(|f| matches!(f.signature, Signature::Generic { integers: true, .. })) {
+        true => ", or an int or uint type",
+        false => "",
+    };
+    Err(format!("expected {} where T is `float` or a `vec`{}", shown.join(" or "), integers))
+}
+
+/// The types to convert a built-in call's arguments to so that they fit one
+/// of its forms exactly: integers become floats, and scalars become vectors
+/// where the form splats them. `None` if no form fits even then.
+pub fn params(name: &str, args: &[Type]) -> Option<Vec<Type>> {
+    let any_float = args.iter().any(|a| a.scalar() == Some(Scalar::Float));
+    for form in BUILTINS.iter().filter(|b| b.name == name) {
+        let Signature::Generic { params, integers, .. } = form.signature else { continue };
+        if params.len() != args.len() { continue; }
+        let Some(t) = params.iter().zip(args).find(|(p, _)| matches!(p, Shape::T)).map(|(_, a)| a) else { continue };
+        let element = match t {
+            Type::Scalar(s) | Type::Vector(s, _) if s.is_integer() && integers && !any_float => *s,
+            Type::Scalar(s) | Type::Vector(s, _) if s.is_numeric() => Scalar::Float,
+            _ => continue,
+        };
+        let t = t.with_element(element);
+        let targets: Vec<_> = params.iter().zip(args).map(|(p, a)| match p {
+            Shape::TOrScalar | Shape::Scalar if matches!(a, Type::Scalar(_)) => Type::Scalar(element),
+            Shape::Scalar => Type::Scalar(element),
+            _ => t.clone(),
+        }).collect();
+        let fits = params.iter().zip(args).zip(&targets).all(|((p, a), target)| {
+            let splats = matches!(p, Shape::TOrSplat) && matches!(a, Type::Scalar(_))
+                && (a.scalar() == Some(element) || promotes(a, &Type::Scalar(element)));
+            a == target || promotes(a, target) || splats
+        });
+        if fits {
+            return Some(targets);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let vec3 = Type::parse("vec3");
+        assert_eq!(params("clamp", &[vec3.clone(), Type::FLOAT, Type::INT]), Some(vec![vec3.clone(), vec3.clone(), vec3.clone()]));
+        assert_eq!(params("mix", &[vec3.clone(), vec3.clone(), Type::INT]), Some(vec![vec3.clone(), vec3.clone(), Type::FLOAT]));
+        assert_eq!(params("max", &[Type::INT, Type::INT]), Some(vec![Type::INT, Type::INT]));
+        assert_eq!(params("dot", &[vec3.clone(), Type::FLOAT]), None);
+    }
+
+    #[test]
+    fn test_results_and_spellings() {
+        let (vec3, ivec2) = (Type::parse("vec3"), Type::parse("ivec2"));
+        assert_eq!(result("lessThan", &[ivec2.clone(), ivec2.clone()]), Ok(Type::parse("bvec2")));
+        assert_eq!(result("any", &[Type::parse("bvec3")]), Ok(Type::BOOL));
+        assert_eq!(result("not", &[Type::FLOAT]).unwrap_err(), "`not` takes a `bvec`");
+        assert_eq!(result("step", &[vec3]).unwrap_err(), "expected `step(T or float, T)` where T is `float` or a `vec`");
+        assert_eq!(spelling("atan", 1, Backend::Wgsl), Some(Spelling::Same));
+        assert_eq!(spelling("atan", 2, Backend::Wgsl), Some(Spelling::Renamed("atan2")));
+        assert_eq!(spelling("inversesqrt", 1, Backend::Metal), Some(Spelling::Renamed("rsqrt")));
+        assert_eq!(spelling("vec3", 3, Backend::Wgsl), None);
+        assert_eq!(spelling("dFdx", 1, Backend::Hlsl), Some(Spelling::Renamed("ddx")));
+        assert_eq!(spelling("inverse", 1, Backend::Wgsl), Some(Spelling::Unavailable));
+        assert_eq!(result("inverse", &[Type::Matrix(3)]), Ok(Type::Matrix(3)));
+        assert_eq!(result("fwidth", &[Type::parse("vec2")]), Ok(Type::parse("vec2")));
+    }
+}
@@ -0,0 +1,703 @@
+use std::collections::HashMap;
+
+use crate::ast::{array_type, method_name, AstNode, BindingKind, Field, NodeKind, Param, ParamQualifier};
+use crate::builtins;
+use crate::diagnostics::{Diagnostic, Span};
+use crate::resolve::{HOST_FUNCTIONS, UNIFORMS};
+use crate::types::{self, adapt_literals, is_int_literal, Type};
+
+/// Runs the semantic checks that sit between parsing and code generation:
+/// every name must be declared, and every expression gets a type that the
+/// statement or operator using it must accept.
+pub fn check(ast: &AstNode) -> Vec<Diagnostic> {
+    let mut checker = Checker::default();
+    checker.visit(ast);
+    checker.errors
+}
+
+struct Binding {
+    kind: BindingKind,
+    /// `None` if the declared type was invalid (and already reported).
+    ty: Option<Type>,
+    span: Span,
+}
+
+/// One declaration of a user function or method.
+struct Signature {
+    params: Vec<Param>,
+    return_type: Type,
+    /// The overload symbol chosen by name resolution, if overloaded.
+    symbol: Option<String>,
+}
+
+#[derive(Default)]
+struct Checker {
+    scopes: Vec<HashMap<String, Binding>>,
+    structs: HashMap<String, Vec<Field>>,
+    /// Keyed by name; methods by their lowered name (`Ray_at`).
+    functions: HashMap<String, Vec<Signature>>,
+    /// The return type of the function being checked.
+    return_type: Option<Type>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Checker {
+    fn collect(&mut self, items: &[AstNode]) {
+        let signature = |args: &[Param], return_type: &str, overload: &Option<String>| Signature {
+            params: args.to_vec(),
+            return_type: Type::parse(return_type),
+            symbol: overload.clone(),
+        };
+        for item in items {
+            match &item.kind {
+                NodeKind::StructDecl { name, fields, .. } => { self.structs.insert(name.clone(), fields.clone()); },
+                NodeKind::FunctionDecl { name, args, return_type, overload, .. } => {
+                    self.functions.entry(name.clone()).or_default().push(signature(args, return_type, overload));
+                },
+                NodeKind::ImplBlock { type_name, methods } => {
+                    for method in methods {
+                        if let NodeKind::FunctionDecl { name, args, return_type, overload, .. } = &method.kind {
+                            self.functions.insert(method_name(type_name, name), vec![signature(args, return_type, overload)]);
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+        for (name, params) in HOST_FUNCTIONS {
+            let params = params.iter().map(|p| Param { type_name: "float".into(), name: p.to_string(), qualifier: ParamQualifier::In, doc_string: None }).collect();
+            let host = Signature { params, return_type: Type::FLOAT, symbol: None };
+            self.functions.entry(name.to_string()).or_insert_with(|| vec![host]);
+        }
+    }
+
+    fn declare(&mut self, name: &str, kind: BindingKind, ty: Option<Type>, span: Span) {
+        let Some(scope) = self.scopes.last_mut() else { return };
+        if let Some(previous) = scope.get(name) {
+            let diag = Diagnostic::error(format!("`{}` is already declared in this scope", name), span)
+                .with_label("declared again here")
+                .with_hint(format!("the first declaration is on line {}; use another name, or assign with `{} = ..`", previous.span.line, name));
+            self.errors.push(diag);
+            return;
+        }
+        scope.insert(name.to_string(), Binding { kind, ty, span });
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|s| s.get(name))
+    }
+
+    fn scoped(&mut self, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashMap::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    /// The type named in a declaration, reporting unknown struct names.
+    fn declared_type(&mut self, type_name: &str, span: Span) -> Option<Type> {
+        let ty = Type::parse(type_name);
+        let mut named = &ty;
+        while let Type::Array(element, _) = named {
+            named = element;
+        }
+        if let Type::Struct(name) = named {
+            if !self.structs.contains_key(name) {
+                self.errors.push(Diagnostic::error(format!("unknown type `{}`", name), span)
+                    .with_label("not a built-in type or struct")
+                    .with_hint("declare it with `struct`, or check the spelling"));
+                return None;
+            }
+        }
+        Some(ty)
+    }
+
+    fn visit(&mut self, node: &AstNode) {
+        match &node.kind {
+            NodeKind::Program(items) => {
+                self.collect(items);
+                self.scoped(|c| items.iter().for_each(|i| c.visit(i)));
+            },
+            NodeKind::StructDecl { fields, .. } => {
+                for field in fields {
+                    self.declared_type(&field.type_name, node.span);
+                }
+            },
+            NodeKind::ImplBlock { methods, .. } => methods.iter().for_each(|m| self.visit(m)),
+            NodeKind::FunctionDecl { args, body, return_type, .. } => {
+                self.return_type = self.declared_type(return_type, node.span);
+                self.scoped(|c| {
+                    for arg in args {
+                        let ty = c.declared_type(&arg.type_name, node.span);
+                        c.declare(&arg.name, BindingKind::Var, ty, node.span);
+                    }
+                    // Parameters and the body's own locals share a scope.
+                    match &body.kind {
+                        NodeKind::Block(statements) => statements.iter().for_each(|s| c.visit(s)),
+                        _ => c.visit(body),
+                    }
+                });
+            },
+            NodeKind::Block(_) => self.scoped(|c| {
+                node.children().into_iter().for_each(|child| c.visit(child));
+            }),
+            NodeKind::VarDecl { binding, name, value, type_name, .. } | NodeKind::ArrayDecl { binding, name, value, type_name, .. } => {
+                let ty = match &node.kind {
+                    NodeKind::ArrayDecl { size, .. } => self.declared_type(&array_type(type_name, *size), node.span),
+                    // Name resolution infers the type of `let x = value`; it
+                    // is left empty only when the value has a problem, which `type_of` reports.
+                    NodeKind::VarDecl { value: Some(v), .. } if type_name.is_empty() => {
+                        if self.type_of(v).is_some() {
+                            self.errors.push(Diagnostic::error(format!("cannot infer the type of `{}`", name), node.span)
+                                .with_label("type unknown")
+                                .with_hint(format!("write the type out, e.g. `{} {}: float = ..`", binding.keyword(), name)));
+                        }
+                        self.declare(name, *binding, None, node.span);
+                        return;
+                    },
+                    _ => self.declared_type(type_name, node.span),
+                };
+                if let Some(v) = value {
+                    self.expect(v, ty.as_ref(), &format!("`{}` is declared as `{}`", name, ty.as_ref().map_or(String::new(), Type::to_string)));
+                    if *binding == BindingKind::Const {
+                        self.check_const_expr(v);
+                    }
+                }
+                self.declare(name, *binding, ty, node.span);
+            },
+            NodeKind::Assignment { target, value } => {
+                self.check_assignable(target);
+                self.check_swizzle_store(target);
+                let ty = self.type_of(target);
+                self.expect(value, ty.as_ref(), "assigned to a value of this type");
+            },
+            NodeKind::CompoundAssignment { target, op, value } => {
+                self.check_assignable(target);
+                self.check_swizzle_store(target);
+                let (Some(t), Some(v)) = (self.type_of(target), self.type_of(value)) else { return };
+                let mut operands = [t.clone(), v];
+                adapt_literals(&[target, value], &mut operands);
+                if types::binary_result(op, &operands[0], &operands[1]).as_ref() != Some(&t) {
+                    let [t, v] = operands;
+                    self.errors.push(Diagnostic::error(format!("cannot apply `{}=` to `{}` and `{}`", op.symbol(), t, v), node.span)
+                        .with_label(format!("the result must stay a `{}`", t)));
+                }
+            },
+            NodeKind::ReturnStmt(value) => match self.return_type.clone() {
+                Some(Type::Void) => {
+                    self.type_of(value);
+                    self.errors.push(Diagnostic::error("cannot return a value from a function without a return type", value.span)
+                        .with_label("unexpected value")
+                        .with_hint("declare the return type after the parameters, e.g. `fn f() float { .. }`"));
+                },
+                ret => self.expect(value, ret.as_ref(), &format!("the function returns `{}`", ret.as_ref().map_or(String::new(), Type::to_string))),
+            },
+            NodeKind::IfStmt { condition, then_branch, else_branch } => {
+                self.condition(condition);
+                self.visit(then_branch);
+                if let Some(e) = else_branch { self.visit(e); }
+            },
+            NodeKind::WhileStmt { condition, body } | NodeKind::DoWhileStmt { body, condition } => {
+                self.condition(condition);
+                self.visit(body);
+            },
+            NodeKind::ForStmt { init, condition, increment, body } => self.scoped(|c| {
+                c.visit(init);
+                c.condition(condition);
+                c.visit(increment);
+                c.visit(body);
+            }),
+            NodeKind::LoopStmt(body) => self.visit(body),
+            NodeKind::SwitchStmt { selector, cases, default } => {
+                if let Some(t) = self.type_of(selector) {
+                    if !matches!(t, Type::Scalar(s) if s.is_integer()) {
+                        self.errors.push(Diagnostic::error(format!("expected `int` or `uint`, found `{}`", t), selector.span)
+                            .with_label("switch selectors must be integers"));
+                    }
+                }
+                cases.iter().for_each(|case| self.visit(&case.body));
+                if let Some(d) = default { self.visit(d); }
+            },
+            NodeKind::BreakStmt | NodeKind::ContinueStmt => {},
+            _ => { self.type_of(node); },
+        }
+    }
+
+    fn condition(&mut self, condition: &AstNode) {
+        if let Some(t) = self.type_of(condition) {
+            if t != Type::BOOL {
+                self.errors.push(Diagnostic::error(format!("expected `bool`, found `{}`", t), condition.span)
+                    .with_label("conditions must be a `bool`")
+                    .with_hint("compare explicitly, e.g. `x != 0.0`"));
+            }
+        }
+    }
+
+    /// Checks that `value` has the `expected` type; `why` says where the
+    /// expectation comes from.
+    fn expect(&mut self, value: &AstNode, expected: Option<&Type>, why: &str) {
+        if let (NodeKind::ArrayLiteral(items), Some(Type::Array(element, size))) = (&value.kind, expected) {
+            if items.len() != *size {
+                self.errors.push(Diagnostic::error(format!("expected {} elements, found {}", size, items.len()), value.span)
+                    .with_label(why.to_string()));
+            }
+            for item in items {
+                self.expect(item, Some(element), &format!("elements are `{}`", element));
+            }
+            return;
+        }
+        let Some(found) = self.type_of(value) else { return };
+        if let Some(expected) = expected {
+            self.check_compatible(value, &found, expected, why);
+        }
+    }
+
+    fn check_compatible(&mut self, value: &AstNode, found: &Type, expected: &Type, why: &str) {
+        if found == expected || (*expected == Type::FLOAT && is_int_literal(value)) {
+            return;
+        }
+        self.errors.push(Diagnostic::error(format!("mismatched types: expected `{}`, found `{}`", expected, found), value.span)
+            .with_label(why.to_string()));
+    }
+
+    /// Checks the arguments of a call to a user function or method against
+    /// its parameters.
+    fn check_args(&mut self, name: &str, params: &[Param], args: &[AstNode], types: &[Option<Type>], span: Span) {
+        if params.len() != args.len() {
+            let plural = |n: usize| if n == 1 { "" } else { "s" };
+            let declared = params.iter().map(|p| format!("{}: {}", p.name, p.type_name)).collect::<Vec<_>>().join(", ");
+            self.errors.push(Diagnostic::error(format!("`{}` takes {} argument{} but {} {} given", name, params.len(), plural(params.len()),
+                    args.len(), if args.len() == 1 { "was" } else { "were" }), span)
+                .with_label(format!("expected {} argument{}", params.len(), plural(params.len())))
+                .with_hint(format!("declared as `{}({})`", name, declared)));
+            return;
+        }
+        for ((param, arg), ty) in params.iter().zip(args).zip(types) {
+            if param.qualifier.is_reference() {
+                self.check_reference_arg(name, param, arg, ty.as_ref());
+            } else if let Some(ty) = ty {
+                self.check_compatible(arg, ty, &Type::parse(&param.type_name), &format!("parameter `{}` of `{}`", param.name, name));
+            }
+        }
+    }
+
+    /// An `out` or `inout` argument is written back, so it must be a mutable
+    /// variable (or a field, element or component of one) of exactly the
+    /// parameter's type.
+    fn check_reference_arg(&mut self, name: &str, param: &Param, arg: &AstNode, ty: Option<&Type>) {
+        let root = arg.root();
+        if !matches!(root.kind, NodeKind::Variable(_)) {
+            self.errors.push(Diagnostic::error(format!("`{}` parameter `{}` of `{}` needs a variable", param.qualifier.keyword(), param.name, name), arg.span)
+                .with_label("not a variable")
+                .with_hint("store the value in a `var` first and pass that"));
+            return;
+        }
+        self.check_assignable(arg);
+        self.check_swizzle_store(arg);
+        let expected = Type::parse(&param.type_name);
+        if let Some(found) = ty.filter(|found| **found != expected) {
+            self.errors.push(Diagnostic::error(format!("mismatched types: expected `{}`, found `{}`", expected, found), arg.span)
+                .with_label(format!("`{}` parameter `{}` of `{}`", param.qualifier.keyword(), param.name, name))
+                .with_hint("values passed to `out`/`inout` parameters are not converted"));
+        }
+    }
+
+    /// The type of an expression. Errors inside it are reported here; `None`
+    /// means the type is unknown because of one, and no more should be said.
+    fn type_of(&mut self, node: &AstNode) -> Option<Type> {
+        let span = node.span;
+        match &node.kind {
+            NodeKind::LiteralFloat { .. } => Some(Type::FLOAT),
+            NodeKind::LiteralInt(_) => Some(Type::INT),
+            NodeKind::LiteralUint(_) => Some(Type::UINT),
+            NodeKind::LiteralBool(_) => Some(Type::BOOL),
+            NodeKind::Variable(name) => {
+                if let Some(binding) = self.lookup(name) {
+                    return binding.ty.clone();
+                }
+                if let Some((_, t)) = UNIFORMS.iter().find(|(u, _)| u == name) {
+                    return Some(Type::parse(t));
+                }
+                self.errors.push(Diagnostic::error(format!("cannot find `{}` in this scope", name), span)
+                    .with_label("not declared")
+                    .with_hint("declare it first with `let`, `var` or `const`"));
+                None
+            },
+            NodeKind::BinaryOp { left, op, right } => {
+                let (l, r) = (self.type_of(left), self.type_of(right));
+                let mut operands = [l?, r?];
+                adapt_literals(&[left, right], &mut operands);
+                let [l, r] = operands;
+                let result = types::binary_result(op, &l, &r);
+                if result.is_none() {
+                    let mut diag = Diagnostic::error(format!("cannot apply `{}` to `{}` and `{}`", op.symbol(), l, r), span)
+                        .with_label(format!("`{} {} {}` is not defined", l, op.symbol(), r));
+                    if l.scalar() != r.scalar() {
+                        diag = diag.with_hint("convert one side first, e.g. `float(i)`");
+                    } else if matches!(op, crate::ast::BinaryOperator::Mod) {
+                        diag = diag.with_hint("`%` is for integers; use `mod(x, y)` for floats");
+                    }
+                    self.errors.push(diag);
+                }
+                result
+            },
+            NodeKind::UnaryOp { op, right } => {
+                let t = self.type_of(right)?;
+                let result = types::unary_result(op, &t);
+                if result.is_none() {
+                    self.errors.push(Diagnostic::error(format!("cannot apply `{}` to `{}`", op.symbol(), t), span)
+                        .with_label("unsupported operand"));
+                }
+                result
+            },
+            NodeKind::Ternary { condition, then_expr, else_expr, .. } => {
+                self.condition(condition);
+                let (t, e) = (self.type_of(then_expr), self.type_of(else_expr));
+                let mut branches = [t?, e?];
+                adapt_literals(&[then_expr, else_expr], &mut branches);
+                let [t, e] = branches;
+                if t != e {
+                    self.errors.push(Diagnostic::error(format!("`?` branches have different types: `{}` and `{}`", t, e), span)
+                        .with_label("both branches must have the same type"));
+                    return None;
+                }
+                Some(t)
+            },
+            NodeKind::Call { func_name, args, overload, .. } => {
+                let types: Vec<_> = args.iter().map(|a| self.type_of(a)).collect();
+                let user = self.functions.get(func_name.as_str()).and_then(|signatures| match overload {
+                    Some(symbol) => signatures.iter().position(|s| s.symbol.as_ref() == Some(symbol)),
+                    None if signatures.iter().all(|s| s.symbol.is_none()) => Some(0),
+                    None => None,
+                });
+                if let Some(i) = user {
+                    let signature = &self.functions[func_name.as_str()][i];
+                    let (params, return_type) = (signature.params.clone(), signature.return_type.clone());
+                    self.check_args(func_name, &params, args, &types, span);
+                    return Some(return_type);
+                }
+                // Overloaded, and name resolution already reported the mismatch.
+                if self.functions.contains_key(func_name.as_str()) && !builtins::is_builtin(func_name) {
+                    return None;
+                }
+                let target = Type::parse(func_name);
+                let is_builtin = builtins::is_builtin(func_name);
+                if !is_builtin && matches!(target, Type::Struct(_) | Type::Void) {
+                    let known = builtins::names().chain(self.functions.keys().map(String::as_str));
+                    let hint = match closest_name(func_name, known) {
+                        Some(similar) => format!("a function with a similar name exists: `{}`", similar),
+                        None => format!("a `pub fn` from another module needs `import module.{{{}}}`", func_name),
+                    };
+                    self.errors.push(Diagnostic::error(format!("cannot find function `{}`", func_name), span)
+                        .with_label("not declared")
+                        .with_hint(hint));
+                    return None;
+                }
+                let mut types = types.into_iter().collect::<Option<Vec<_>>>()?;
+                let result = if is_builtin {
+                    adapt_literals(&args.iter().collect::<Vec<_>>(), &mut types);
+                    builtins::result(func_name, &types)
+                } else {
+                    types::constructor_result(&target, &types)
+                };
+                result.map_err(|hint| {
+                    let described = types.iter().map(Type::to_string).collect::<Vec<_>>().join(", ");
+                    self.errors.push(Diagnostic::error(format!("`{}` cannot take ({})", func_name, described), span)
+                        .with_label("no matching form")
+                        .with_hint(hint));
+                }).ok()
+            },
+            NodeKind::MethodCall { receiver, method, args, receiver_type } => {
+                self.type_of(receiver);
+                let types: Vec<_> = args.iter().map(|a| self.type_of(a)).collect();
+                // Name resolution reports methods it could not find.
+                let signature = self.functions.get(&method_name(receiver_type, method))?.first()?;
+                let (params, return_type) = (signature.params[1..].to_vec(), signature.return_type.clone());
+                self.check_args(method, &params, args, &types, span);
+                Some(return_type)
+            },
+            NodeKind::ArrayLiteral(items) => {
+                let types: Vec<_> = items.iter().map(|i| self.type_of(i)).collect();
+                let mut types = types.into_iter().collect::<Option<Vec<_>>>()?;
+                adapt_literals(&items.iter().collect::<Vec<_>>(), &mut types);
+                let Some(first) = types.first().cloned() else {
+                    self.errors.push(Diagnostic::error("empty array literal", span)
+                        .with_label("arrays need at least one element"));
+                    return None;
+                };
+                if let Some((item, t)) = items.iter().zip(&types).find(|(_, t)| **t != first) {
+                    self.errors.push(Diagnostic::error(format!("array elements have different types: `{}` and `{}`", first, t), item.span)
+                        .with_label(format!("expected `{}` like the first element", first)));
+                    return None;
+                }
+                Some(Type::Array(Box::new(first), items.len()))
+            },
+            NodeKind::StructLiteral { name, fields } => {
+                let declared = self.structs.get(name)?.clone();
+                for (field, value) in fields {
+                    let expected = declared.iter().find(|f| &f.name == field).map(|f| Type::parse(&f.type_name));
+                    self.expect(value, expected.as_ref(), &format!("field `{}` of `{}`", field, name));
+                }
+                Some(Type::Struct(name.clone()))
+            },
+            NodeKind::SubscriptAccess { base, index } => {
+                let (b, i) = (self.type_of(base), self.type_of(index));
+                if let Some(i) = i {
+                    if !matches!(i, Type::Scalar(s) if s.is_integer()) {
+                        self.errors.push(Diagnostic::error(format!("expected `int` or `uint`, found `{}`", i), index.span)
+                            .with_label("indices must be integers")
+                            .with_hint("convert it, e.g. `int(x)`"));
+                    }
+                }
+                let b = b?;
+                let result = types::index_result(&b);
+                if result.is_none() {
+                    self.errors.push(Diagnostic::error(format!("cannot index into a value of type `{}`", b), span)
+                        .with_label("not an array, vector or matrix"));
+                }
+                result
+            },
+            NodeKind::MemberAccess { base, member } => {
+                let b = self.type_of(base)?;
+                if let Type::Struct(name) = &b {
+                    let field = self.structs.get(name).and_then(|fields| fields.iter().find(|f| &f.name == member));
+                    if field.is_none() {
+                        let names = self.structs.get(name).map(|fields| fields.iter().map(|f| f.name.as_str()).collect::<Vec<_>>().join(", "));
+                        self.errors.push(Diagnostic::error(format!("struct `{}` has no field `{}`", name, member), span)
+                            .with_label("unknown field")
+                            .with_hint(format!("`{}` has fields: {}", name, names.unwrap_or_default())));
+                    }
+                    return field.map(|f| Type::parse(&f.type_name));
+                }
+                let result = types::swizzle_result(&b, member);
+                if result.is_none() {
+                    let diag = Diagnostic::error(format!("no field `{}` on type `{}`", member, b), span);
+                    self.errors.push(match b {
+                        Type::Vector(..) => diag.with_label("invalid swizzle")
+                            .with_hint("a swizzle takes 1 to 4 letters from one of `xyzw`, `rgba` or `stpq`"),
+                        _ => diag.with_label("unknown field"),
+                    });
+                }
+                result
+            },
+            NodeKind::Swizzle { base, components } => {
+                let b = self.type_of(base)?;
+                let result = types::swizzle_result(&b, components);
+                if let (None, Type::Vector(_, width)) = (&result, &b) {
+                    let set = ["xyzw", "rgba", "stpq"].into_iter().find(|set| components.chars().all(|c| set.contains(c))).unwrap_or("xyzw");
+                    let out_of_range = components.chars().find(|c| set.find(*c).is_some_and(|i| i >= *width)).unwrap_or('?');
+                    let available = set.chars().take(*width).map(|c| format!("`{}`", c)).collect::<Vec<_>>().join(", ");
+                    self.errors.push(Diagnostic::error(format!("no component `{}` on `{}`", out_of_range, b), span)
+                        .with_label("out of range")
+                        .with_hint(format!("`{}` has components {}", b, available)));
+                }
+                result
+            },
+            _ => None,
+        }
+    }
+
+    /// A swizzle can only be assigned to if no component repeats (`p.xx = ..`).
+    fn check_swizzle_store(&mut self, target: &AstNode) {
+        let NodeKind::Swizzle { components, .. } = &target.kind else { return };
+        let Some(indices) = types::swizzle_indices(components) else { return };
+        if indices.iter().enumerate().any(|(i, c)| indices[..i].contains(c)) {
+            self.errors.push(Diagnostic::error(format!("cannot assign to `.{}`, which repeats a component", components), target.span)
+                .with_label("repeated component")
+                .with_hint("each component can only be assigned once"));
+        }
+    }
+
+    /// Rejects writes through a `let` or `const` binding, including `p.x = ..` and `a[i] = ..`.
+    fn check_assignable(&mut self, target: &AstNode) {
+        let root = target.root();
+        let NodeKind::Variable(name) = &root.kind else { return };
+        let Some(binding) = self.lookup(name) else {
+            if UNIFORMS.iter().any(|(u, _)| u == name) {
+                self.errors.push(Diagnostic::error(format!("cannot assign to built-in `{}`", name), target.span)
+                    .with_label("provided by the host")
+                    .with_hint("copy it into a `var` to change it"));
+            }
+            return;
+        };
+        let what = match binding.kind {
+            BindingKind::Var => return,
+            BindingKind::Let => "immutable binding",
+            BindingKind::Const => "constant",
+        };
+        let diag = Diagnostic::error(format!("cannot assign to {} `{}`", what, name), target.span)
+            .with_label(format!("`{}` is declared with `{}`", name, binding.kind.keyword()))
+            .with_hint(format!("`{}` is declared on line {}; use `var` if it needs to change", name, binding.span.line));
+        self.errors.push(diag);
+    }
+
+    /// A `const` initializer may only use literals, operators, built-in
+    /// functions/constructors and other constants.
+    fn check_const_expr(&mut self, expr: &AstNode) {
+        let mut errors = Vec::new();
+        expr.walk(&mut |node| match &node.kind {
+            NodeKind::Variable(name) => {
+                if let Some(b) = self.lookup(name) {
+                    if b.kind != BindingKind::Const {
+                        errors.push(Diagnostic::error(format!("`{}` is not a constant", name), node.span)
+                            .with_label("not known at compile time")
+                            .with_hint("a `const` can only be computed from literals and other constants; use `let` instead"));
+                    }
+                }
+            },
+            NodeKind::Call { func_name, .. } if self.functions.contains_key(func_name) => {
+                errors.push(Diagnostic::error(format!("cannot call `{}` in a constant", func_name), node.span)
+                    .with_label("user functions run at shader time")
+                    .with_hint("use `let` for values computed by functions"));
+            },
+            NodeKind::MethodCall { method, .. } => {
+                errors.push(Diagnostic::error(format!("cannot call method `{}` in a constant", method), node.span)
+                    .with_label("methods run at shader time")
+                    .with_hint("use `let` for values computed by functions"));
+            },
+            _ => {}
+        });
+        self.errors.extend(errors);
+    }
+}
+
+/// The candidate closest to `name` that is a plausible misspelling of it:
+/// about one edit per three characters away.
+fn closest_name<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let distance = |a: &str, b: &str| {
+        let b: Vec<char> = b.chars().collect();
+        let mut row: Vec<usize> = (0..=b.len()).collect();
+        for (i, ca) in a.chars().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = i + 1;
+            for (j, cb) in b.iter().enumerate() {
+                let substituted = diagonal + usize::from(ca != *cb);
+                diagonal = row[j + 1];
+                row[j + 1] = substituted.min(row[j] + 1).min(diagonal + 1);
+            }
+        }
+        row[b.len()]
+    };
+    candidates.map(|c| (distance(name, c), c))
+        .filter(|(d, _)| *d <= (name.chars().count() + 1) / 3)
+        .min()
+        .map(|(_, c)| c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+    use crate::parser::Parser;
+    use crate::source_map::SourceMap;
+
+    fn check_src(src: &str) -> Vec<Diagnostic> {
+        let (tokens, _) = tokenize(&SourceMap::single("t.sumi", src));
+        check(&Parser::new(tokens).parse().unwrap())
+    }
+
+    #[test]
+    fn test_assignment_to_let_and_const() {
+        let errors = check_src("const PI: float = 3.14;\n\
+                                fn f(p: vec3) float {\n\
+                                    let r: vec3 = p;\n\
+                                    r.x = 1.0;\n\
+                                    PI += 1.0;\n\
+                                    var t: float = 0.0;\n\
+                                    t = 2.0;\n\
+                                    p = r;\n\
+                                    return t;\n\
+                                }");
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["cannot assign to immutable binding `r`", "cannot assign to constant `PI`"]);
+        assert_eq!(errors[0].span.line, 4);
+    }
+
+    #[test]
+    fn test_shadowing_in_inner_scope() {
+        let errors = check_src("fn f() { let x: float = 1.0; { var x: float = 2.0; x = 3.0; } }");
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_const_initializer_must_be_constant() {
+        let errors = check_src("const A: float = 2.0;\n\
+                                const B: float = A * sqrt(2.0);\n\
+                                fn g() float { return 1.0; }\n\
+                                fn f(x: float) { const C: float = x + g(); }");
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["`x` is not a constant", "cannot call `g` in a constant"]);
+    }
+
+    fn messages(src: &str) -> Vec<String> {
+        check_src(src).into_iter().map(|e| e.message).collect()
+    }
+
+    fn messages_ok(src: &str) -> bool {
+        messages(src).is_empty()
+    }
+
+    #[test]
+    fn test_undefined_names() {
+        let errors = check_src("fn f() float {\n    let a: float = b + 1.0;\n    return g(a);\n}");
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["cannot find `b` in this scope", "cannot find function `g`"]);
+        assert_eq!(errors[1].hint.as_deref(), Some("a `pub fn` from another module needs `import module.{g}`"));
+        assert_eq!((errors[0].span.line, errors[0].span.column), (2, 20));
+        assert!(messages_ok("fn f() float { return iTime * iResolution.x; }"));
+
+        let errors = check_src("fn shade(p: vec3) float { return p.x; }\nfn f(p: vec3) float { return shdae(p) + smoothstpe(0.0, 1.0, p.y); }");
+        let hints: Vec<_> = errors.iter().map(|e| e.hint.as_deref().unwrap_or_default()).collect();
+        assert_eq!(hints, ["a function with a similar name exists: `shade`", "a function with a similar name exists: `smoothstep`"]);
+    }
+
+    #[test]
+    fn test_operand_and_argument_types() {
+        assert_eq!(messages("fn f(a: vec3, b: vec2) vec3 { return a + b; }"), vec!["cannot apply `+` to `vec3` and `vec2`"]);
+        assert_eq!(messages("fn g(p: vec3, r: float) float { return length(p) - r; }\n\
+                             fn f() float { return g(vec3(1.0), 2.0, 3.0) + g(vec2(1.0), 1.0); }"),
+                   vec!["`g` takes 2 arguments but 3 were given", "mismatched types: expected `vec3`, found `vec2`"]);
+        assert_eq!(messages("fn f(x: float) vec3 { return clamp(vec3(x), 0.0, vec2(1.0)); }"), vec!["`clamp` cannot take (vec3, float, vec2)"]);
+        assert!(messages_ok("fn f(m: mat3, v: vec3, i: int) vec3 { return clamp(m * v * 2, 0, 1) + v.zyx * float(i % 2); }"));
+    }
+
+    #[test]
+    fn test_declarations_and_statements() {
+        assert_eq!(messages("fn f(p: vec3) float {\n\
+                                 var x: float = p;\n\
+                                 let y: Thing = 1.0;\n\
+                                 if (x) { x = 1.0; }\n\
+                                 var p: float = 0.0;\n\
+                                 return p.x + p.k;\n\
+                             }"),
+                   vec![
+                       "mismatched types: expected `float`, found `vec3`",
+                       "unknown type `Thing`",
+                       "expected `bool`, found `float`",
+                       "`p` is already declared in this scope",
+                       "no field `k` on type `vec3`",
+                   ]);
+    }
+
+    #[test]
+    fn test_inference_reports_the_underlying_error() {
+        assert_eq!(messages("fn f() { let a = b * 2.0; var c = a; }"), vec!["cannot find `b` in this scope"]);
+        assert_eq!(messages("fn f(p: vec3) { let a = p.xy + p; }"), vec!["cannot apply `+` to `vec2` and `vec3`"]);
+    }
+
+    #[test]
+    fn test_swizzles() {
+        assert_eq!(messages("fn f(v: vec2) float {\n    var p = vec3(1.0)\n    p.xx = v\n    p.zx = v.yx\n    return v.z + p.w + p.b\n}"),
+                   vec!["cannot assign to `.xx`, which repeats a component", "no component `z` on `vec2`", "no component `w` on `vec3`"]);
+    }
+
+    #[test]
+    fn test_out_arguments() {
+        let src = "fn split(x: float, out whole: float, inout v: vec3) { whole = floor(x); v *= 2.0; }\n\
+                   fn f(p: vec3) {\n    let c = 1.0\n    var w = 0.0\n    var i = 0\n    p.x = 1.0\n    split(1.0, w, p)\n    split(1.0, c, p)\n    split(1.0, 2.0, p)\n    split(1.0, i, p)\n}";
+        assert_eq!(messages(src), vec![
+                       "cannot assign to immutable binding `c`",
+                       "`out` parameter `whole` of `split` needs a variable",
+                       "mismatched types: expected `float`, found `int`",
+                   ]);
+    }
+}
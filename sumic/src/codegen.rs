@@ -1,7 +1,213 @@
-use crate::ast::{AstNode, BinaryOperator, UnaryOperator};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{array_type, method_name, split_array_type, AstNode, NodeKind, BinaryOperator, BindingKind, Field, UnaryOperator};
+use crate::diagnostics::Diagnostic;
+use crate::resolve::{HOST_FUNCTIONS, UNIFORMS};
+use crate::types::Type;
+use crate::builtins::{self, Backend, Spelling};
 
 pub trait CodeGenerator {
     fn generate(&self, ast: &AstNode) -> String;
+
+    /// Reports constructs that are valid S2L but cannot be expressed in this
+    /// backend. Run before `generate`.
+    fn validate(&self, _ast: &AstNode) -> Vec<Diagnostic> { Vec::new() }
+
+    /// A node in statement position. A call made for its `out` arguments or
+    /// side effects is a bare expression in the AST, and needs its `;`.
+    fn generate_statement(&self, stmt: &AstNode) -> String {
+        match stmt.kind {
+            NodeKind::Call { .. } | NodeKind::MethodCall { .. } => format!("{};", self.generate(stmt)),
+            _ => self.generate(stmt),
+        }
+    }
+}
+
+/// A call to the built-in `name`, written as `backend` spells it. GLSL
+/// `mod(x, y)` is a floored remainder (`x - y * floor(x / y)`), unlike the
+/// truncating `%`/`fmod` of WGSL and Metal; with known argument types it
+/// calls a helper (see `needs_helper`), and is only expanded here without.
+fn builtin_call(backend: Backend, name: &str, args: &[String]) -> String {
+    let spelling = builtins::spelling(name, args.len(), backend).unwrap_or(Spelling::Same);
+    match (spelling, args) {
+        (Spelling::Renamed(to), _) => format!("{}({})", to, args.join(", ")),
+        (Spelling::Operator(op), [a, b]) => format!("({} {} {})", a, op, b),
+        (Spelling::Prefix(op), [a]) => format!("({}{})", op, a),
+        (Spelling::Times(factor), [a]) => format!("({} * {})", a, factor),
+        (Spelling::FlooredMod, [x, y]) => format!("({} - {} * floor({} / {}))", x, y, x, y),
+        _ => format!("{}({})", name, args.join(", ")),
+    }
+}
+
+/// Reports calls to the built-ins `backend` does not have.
+fn unavailable_builtins(program: &AstNode, backend: Backend, language: &str) -> Vec<Diagnostic> {
+    let mut errors = Vec::new();
+    program.walk(&mut |n| if let NodeKind::Call { func_name, args, overload: None, .. } = &n.kind {
+        if builtins::spelling(func_name, args.len(), backend) == Some(Spelling::Unavailable) {
+            errors.push(Diagnostic::error(format!("{} has no `{}`", language, func_name), n.span)
+                .with_label(format!("not a built-in function of {}", language))
+                .with_hint("compute it on the host and pass it in, or write it out with other built-ins"));
+        }
+    });
+    errors
+}
+
+/// Whether `backend` writes a call to `name` with arguments of `types` as a
+/// call to a helper function, so that no argument is evaluated twice: a
+/// floored `mod`, or a matrix built from a scalar (the diagonal) or from a
+/// matrix of another size, which GLSL constructs directly (GLSL ES 1.00
+/// only from a scalar).
+fn needs_helper(backend: Backend, name: &str, types: &[String]) -> bool {
+    match (Type::parse(name), types) {
+        (Type::Matrix(n), [arg]) => match Type::parse(arg) {
+            Type::Scalar(_) => !matches!(backend, Backend::Glsl | Backend::Glsl100),
+            Type::Matrix(m) => m != n && backend != Backend::Glsl,
+            _ => false,
+        },
+        (_, [_, _]) => builtins::spelling(name, 2, backend) == Some(Spelling::FlooredMod),
+        _ => false,
+    }
+}
+
+/// The helper a call to `name` with arguments of `types` is lowered to,
+/// e.g. `sumi_mod_vec3_float` or `sumi_mat3_mat4`.
+fn helper_symbol(name: &str, types: &[String]) -> String {
+    format!("sumi_{}_{}", name, types.join("_"))
+}
+
+/// A call to the helper for `name`. An integer literal given for a float
+/// parameter is written as a float, as WGSL does not convert it there.
+fn helper_call(name: &str, types: &[String], args: &[AstNode], generate: impl Fn(&AstNode) -> String) -> String {
+    let args = args.iter().zip(types).map(|(arg, t)| match &arg.kind {
+        NodeKind::LiteralInt(i) if t == "float" => format!("{}.0", i),
+        _ => generate(arg),
+    }).collect::<Vec<_>>();
+    format!("{}({})", helper_symbol(name, types), args.join(", "))
+}
+
+/// A function written out for the calls a backend has no spelling for, with
+/// its types as the backend names them.
+struct Helper {
+    symbol: String,
+    /// Named `x` and `y`.
+    params: Vec<String>,
+    returns: String,
+    value: String,
+}
+
+impl Helper {
+    /// The helper in Metal, GLSL and HLSL syntax.
+    fn c_style(&self) -> String {
+        let params = self.params.iter().zip(["x", "y"]).map(|(t, name)| format!("{} {}", t, name)).collect::<Vec<_>>().join(", ");
+        format!("{} {}({}) {{\n    return {};\n}}", self.returns, self.symbol, params, self.value)
+    }
+}
+
+/// The helpers for every call in `program` that `backend` lowers to one,
+/// once each, in order of first use.
+fn helpers(program: &AstNode, backend: Backend, map_type: impl Fn(&str) -> String) -> Vec<Helper> {
+    let mut calls: Vec<(String, Vec<String>)> = Vec::new();
+    program.walk(&mut |n| if let NodeKind::Call { func_name, overload: None, arg_types, .. } = &n.kind {
+        let call = (func_name.clone(), arg_types.clone());
+        if needs_helper(backend, func_name, arg_types) && !calls.contains(&call) { calls.push(call); }
+    });
+    calls.into_iter().map(|(name, types)| {
+        let (returns, value) = match (Type::parse(&name), Type::parse(&types[0])) {
+            (Type::Matrix(n), from) => {
+                let column = |i: usize| {
+                    let components = match &from {
+                        Type::Matrix(m) if i < *m && n <= *m => return format!("x[{}].{}", i, &"xyzw"[..n]),
+                        Type::Matrix(m) if i < *m => std::iter::once(format!("x[{}]", i)).chain(std::iter::repeat_n("0.0".to_string(), n - m)).collect::<Vec<_>>(),
+                        // The diagonal: `x`, or 1.0 past the end of a smaller matrix.
+                        from => (0..n).map(|j| match (j == i, from) {
+                            (true, Type::Scalar(_)) => "x".to_string(),
+                            (true, _) => "1.0".to_string(),
+                            (false, _) => "0.0".to_string(),
+                        }).collect::<Vec<_>>(),
+                    };
+                    format!("{}({})", map_type(&format!("vec{}", n)), components.join(", "))
+                };
+                (name.clone(), format!("{}({})", map_type(&name), (0..n).map(column).collect::<Vec<_>>().join(", ")))
+            },
+            _ => (types[0].clone(), "x - y * floor(x / y)".to_string()),
+        };
+        Helper { symbol: helper_symbol(&name, &types), params: types.iter().map(|t| map_type(t)).collect(), returns: map_type(&returns), value }
+    }).collect()
+}
+
+/// A method as the free function it is lowered to, e.g. `Ray.at` as `Ray_at`.
+fn lowered_method(type_name: &str, method: &AstNode) -> AstNode {
+    let mut function = method.clone();
+    if let NodeKind::FunctionDecl { name, .. } = &mut function.kind {
+        *name = method_name(type_name, name);
+    }
+    function
+}
+
+/// The name a function is declared and called by in a backend with
+/// overloading. User overloads are native overloads, except those of a
+/// built-in name, which would be ambiguous with (or in GLSL, hide) the
+/// built-in.
+fn native_function_name<'a>(name: &'a str, overload: Option<&'a str>) -> &'a str {
+    match overload {
+        Some(symbol) if builtins::is_builtin(name) => symbol,
+        _ => name,
+    }
+}
+
+/// An operator as Metal, GLSL and HLSL write it.
+fn c_operator(op: &BinaryOperator) -> &'static str {
+    match op {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Sub => "-",
+        BinaryOperator::Mul | BinaryOperator::MatrixMul => "*",
+        BinaryOperator::Div => "/",
+        BinaryOperator::Mod => "%",
+        BinaryOperator::Equal => "==",
+        BinaryOperator::NotEqual => "!=",
+        BinaryOperator::Less => "<",
+        BinaryOperator::Greater => ">",
+        BinaryOperator::LessEqual => "<=",
+        BinaryOperator::GreaterEqual => ">=",
+        BinaryOperator::And => "&&",
+        BinaryOperator::Or => "||",
+        BinaryOperator::BitAnd => "&",
+        BinaryOperator::BitOr => "|",
+        BinaryOperator::BitXor => "^",
+        BinaryOperator::ShiftLeft => "<<",
+        BinaryOperator::ShiftRight => ">>",
+    }
+}
+
+/// Whether the program has the `mainImage(fragCoord: vec2) vec4` that
+/// standalone backends wrap in an entry point.
+fn has_main_image(items: &[AstNode]) -> bool {
+    items.iter().any(|item| matches!(&item.kind,
+        NodeKind::FunctionDecl { name, args, return_type, .. } if name == "mainImage" && return_type == "vec4" && args.len() == 1 && args[0].type_name == "vec2"))
+}
+
+/// Whether the program calls into the host library, which standalone
+/// backends then write out.
+fn calls_host_function(program: &AstNode) -> bool {
+    let mut calls = false;
+    program.walk(&mut |n| calls |= matches!(&n.kind, NodeKind::Call { func_name, .. } if HOST_FUNCTIONS.iter().any(|(h, _)| h == func_name)));
+    calls
+}
+
+/// Reports array literals anywhere but as the value of an array
+/// declaration, for backends that cannot write them elsewhere.
+fn stray_array_literals(program: &AstNode, reason: &str) -> Vec<Diagnostic> {
+    let mut errors = Vec::new();
+    program.walk(&mut |node| {
+        if matches!(node.kind, NodeKind::ArrayDecl { .. }) { return }
+        for child in node.children().into_iter().filter(|c| matches!(c.kind, NodeKind::ArrayLiteral(_))) {
+            errors.push(Diagnostic::error("array literal outside a declaration", child.span)
+                .with_label(reason)
+                .with_hint("declare it first: `let values: array<float, 3> = [...]`"));
+        }
+    });
+    errors
 }
 
 // --- Metal Generator ---
@@ -10,96 +216,255 @@ pub struct MetalGenerator { pub is_std_lib: bool }
 
 impl MetalGenerator {
     pub fn new(is_std_lib: bool) -> Self { Self { is_std_lib } }
-
-    fn generate_op(&self, op: &BinaryOperator) -> &'static str {
-        match op {
-            BinaryOperator::Add => "+", 
-            BinaryOperator::Sub => "-",
-            BinaryOperator::Mul => "*", 
-            BinaryOperator::Div => "/",
-            BinaryOperator::Equal => "==", 
-            BinaryOperator::Less => "<", 
-            BinaryOperator::Greater => ">",
-            BinaryOperator::LessEqual => "<=",    // <--- Added
-            BinaryOperator::GreaterEqual => ">=", // <--- Added
-        }
-    }
 }
 
 impl CodeGenerator for MetalGenerator {
+    fn validate(&self, ast: &AstNode) -> Vec<Diagnostic> {
+        unavailable_builtins(ast, Backend::Metal, "Metal")
+    }
+
     fn generate(&self, ast: &AstNode) -> String {
-        match ast {
-            AstNode::Program(nodes) => nodes.iter().map(|n| self.generate(n)).collect::<Vec<_>>().join("\n\n"),
+        match &ast.kind {
+            NodeKind::Program(nodes) => helpers(ast, Backend::Metal, str::to_string).iter().map(Helper::c_style).chain(nodes.iter().map(|n| match &n.kind {
+                // Module-scope constants live in MSL's `constant` address space.
+                NodeKind::VarDecl { type_name, name, value: Some(v), .. } => {
+                    format!("constant {} {} = {};", type_name, name, self.generate(v))
+                },
+                NodeKind::ArrayDecl { type_name, name, size, value: Some(v), .. } => {
+                    format!("constant {} {} = {};", array_type(type_name, *size), name, self.generate(v))
+                },
+                _ => self.generate(n),
+            })).collect::<Vec<_>>().join("\n\n"),
             
-            AstNode::FunctionDecl { return_type, name, args, body, .. } => {
-                let arg_str = args.iter().map(|(t,n)| format!("{} {}", t, n)).collect::<Vec<_>>().join(", ");
-                format!("{} {}({}) {}", return_type, name, arg_str, self.generate(body))
+            NodeKind::FunctionDecl { return_type, name, args, body, overload, .. } => {
+                let arg_str = args.iter().map(|a| match a.qualifier.is_reference() {
+                    true => format!("thread {}& {}", a.type_name, a.name),
+                    false => format!("{} {}", a.type_name, a.name),
+                }).collect::<Vec<_>>().join(", ");
+                format!("{} {}({}) {}", return_type, native_function_name(name, overload.as_deref()), arg_str, self.generate(body))
             },
             
-            AstNode::StructDecl { name, fields, .. } => {
-                let f_str = fields.iter().map(|(t,n)| format!("    {} {};", t, n)).collect::<Vec<_>>().join("\n");
+            NodeKind::ImplBlock { type_name, methods } => {
+                methods.iter().map(|m| self.generate(&lowered_method(type_name, m))).collect::<Vec<_>>().join("\n\n")
+            },
+
+            NodeKind::StructDecl { name, fields, .. } => {
+                let f_str = fields.iter().map(|f| format!("    {} {};", f.type_name, f.name)).collect::<Vec<_>>().join("\n");
                 format!("struct {} {{\n{}\n}};", name, f_str)
             },
 
-            AstNode::Block(stmts) => format!("{{\n{}\n}}", stmts.iter().map(|s| format!("    {}", self.generate(s))).collect::<Vec<_>>().join("\n")),
+            NodeKind::Block(stmts) => format!("{{\n{}\n}}", stmts.iter().map(|s| format!("    {}", self.generate_statement(s))).collect::<Vec<_>>().join("\n")),
             
-            AstNode::ReturnStmt(expr) => format!("return {};", self.generate(expr)),
+            NodeKind::ReturnStmt(expr) => format!("return {};", self.generate(expr)),
             
-            AstNode::IfStmt { condition, then_branch, else_branch } => {
-                let base = format!("if ({}) {}", self.generate(condition), self.generate(then_branch));
-                if let Some(e) = else_branch { format!("{} else {}", base, self.generate(e)) } else { base }
+            NodeKind::IfStmt { condition, then_branch, else_branch } => {
+                let base = format!("if ({}) {}", self.generate(condition), self.generate_statement(then_branch));
+                if let Some(e) = else_branch { format!("{} else {}", base, self.generate_statement(e)) } else { base }
             },
 
-            AstNode::ForStmt { init, condition, increment, body } => {
+            NodeKind::ForStmt { init, condition, increment, body } => {
                 let i = self.generate(init);
                 let c = self.generate(condition);
                 let inc = self.generate(increment);
                 format!("for ({} {}; {}) {}", i, c, inc.trim_end_matches(';'), self.generate(body))
             },
 
-            AstNode::BreakStmt => "break;".to_string(),
+            NodeKind::WhileStmt { condition, body } => format!("while ({}) {}", self.generate(condition), self.generate(body)),
 
-            AstNode::VarDecl { type_name, name, value } => {
-                if let Some(v) = value { format!("{} {} = {};", type_name, name, self.generate(v)) } 
-                else { format!("{} {};", type_name, name) }
+            NodeKind::DoWhileStmt { body, condition } => format!("do {} while ({});", self.generate(body), self.generate(condition)),
+
+            NodeKind::LoopStmt(body) => format!("while (true) {}", self.generate(body)),
+
+            // MSL switches fall through, so every arm is closed with `break`.
+            NodeKind::SwitchStmt { selector, cases, default } => {
+                let mut arms = cases.iter().map(|c| {
+                    let labels = c.values.iter().map(|v| format!("case {}:", v)).collect::<Vec<_>>().join(" ");
+                    format!("    {} {} break;", labels, self.generate(&c.body))
+                }).collect::<Vec<_>>();
+                if let Some(d) = default { arms.push(format!("    default: {} break;", self.generate(d))); }
+                format!("switch ({}) {{\n{}\n}}", self.generate(selector), arms.join("\n"))
             },
 
-            AstNode::ArrayDecl { type_name, name, size, values } => {
-                let mut init_str = String::new();
-                if let Some(vals) = values {
-                    let v_str = vals.iter().map(|v| self.generate(v)).collect::<Vec<_>>().join(", ");
-                    init_str = format!(" = {{ {} }}", v_str);
-                }
-                format!("{} {}[{}]{};", type_name, name, size, init_str)
+            // Imports are replaced by the imported items when modules are linked.
+            NodeKind::Import { .. } => String::new(),
+            NodeKind::BreakStmt => "break;".to_string(),
+            NodeKind::ContinueStmt => "continue;".to_string(),
+
+            NodeKind::VarDecl { binding, type_name, name, value, .. } => {
+                let qualifier = if *binding == BindingKind::Var { "" } else { "const " };
+                if let Some(v) = value { format!("{}{} {} = {};", qualifier, type_name, name, self.generate(v)) }
+                else { format!("{}{} {};", qualifier, type_name, name) }
+            },
+
+            // `metal::array` rather than a C array, so arrays can be passed and returned by value.
+            NodeKind::ArrayDecl { binding, type_name, name, size, value, .. } => {
+                let qualifier = if *binding == BindingKind::Var { "" } else { "const " };
+                let t_arr = array_type(type_name, *size);
+                if let Some(v) = value { format!("{}{} {} = {};", qualifier, t_arr, name, self.generate(v)) }
+                else { format!("{}{} {};", qualifier, t_arr, name) }
             },
 
-            AstNode::Assignment { target, value } => format!("{} = {};", self.generate(target), self.generate(value)),
+            NodeKind::Assignment { target, value } => format!("{} = {};", self.generate(target), self.generate(value)),
+            NodeKind::CompoundAssignment { target, op, value } => format!("{} {}= {};", self.generate(target), c_operator(op), self.generate(value)),
             
-            AstNode::BinaryOp { left, op, right } => format!("({} {} {})", self.generate(left), self.generate_op(op), self.generate(right)),
+            NodeKind::BinaryOp { left, op, right } => format!("({} {} {})", self.generate(left), c_operator(op), self.generate(right)),
             
-            AstNode::UnaryOp { op, right } => {
-                let s = match op { UnaryOperator::Negate => "-", UnaryOperator::Not => "!" };
+            NodeKind::UnaryOp { op, right } => {
+                let s = match op { UnaryOperator::Negate => "-", UnaryOperator::Not => "!", UnaryOperator::BitNot => "~" };
                 format!("({}{})", s, self.generate(right))
             },
 
-            AstNode::Call { func_name, args } => format!("{}({})", func_name, args.iter().map(|a| self.generate(a)).collect::<Vec<_>>().join(", ")),
+            NodeKind::Ternary { condition, then_expr, else_expr, .. } => {
+                format!("({} ? {} : {})", self.generate(condition), self.generate(then_expr), self.generate(else_expr))
+            },
+
+            // Aggregate initialization, in declaration order.
+            NodeKind::StructLiteral { name, fields } if fields.is_empty() => format!("{}{{}}", name),
+            NodeKind::StructLiteral { name, fields } => {
+                format!("{}{{ {} }}", name, fields.iter().map(|(_, v)| self.generate(v)).collect::<Vec<_>>().join(", "))
+            },
+            NodeKind::ArrayLiteral(items) => format!("{{ {} }}", items.iter().map(|i| self.generate(i)).collect::<Vec<_>>().join(", ")),
+
+            NodeKind::MethodCall { receiver, method, args, receiver_type } => {
+                let args = std::iter::once(receiver.as_ref()).chain(args).map(|a| self.generate(a)).collect::<Vec<_>>();
+                format!("{}({})", method_name(receiver_type, method), args.join(", "))
+            },
+            NodeKind::Call { func_name, args, overload: Some(symbol), .. } => {
+                let arg_strs = args.iter().map(|a| self.generate(a)).collect::<Vec<_>>();
+                format!("{}({})", native_function_name(func_name, Some(symbol)), arg_strs.join(", "))
+            },
+            NodeKind::Call { func_name, args, arg_types, .. } => {
+                let arg_strs = args.iter().map(|a| self.generate(a)).collect::<Vec<_>>();
+                match needs_helper(Backend::Metal, func_name, arg_types) {
+                    true => helper_call(func_name, arg_types, args, |a| self.generate(a)),
+                    false => builtin_call(Backend::Metal, func_name, &arg_strs),
+                }
+            },
             
-            AstNode::MemberAccess { base, member } => format!("{}.{}", self.generate(base), member),
-            AstNode::SubscriptAccess { base, index } => format!("{}[{}]", self.generate(base), self.generate(index)),
+            NodeKind::MemberAccess { base, member } | NodeKind::Swizzle { base, components: member } => format!("{}.{}", self.generate(base), member),
+            NodeKind::SubscriptAccess { base, index } => format!("{}[{}]", self.generate(base), self.generate(index)),
             
-            AstNode::LiteralFloat(f) => if f.fract() == 0.0 { format!("{:.1}", f) } else { format!("{}", f) },
-            AstNode::LiteralInt(i) => format!("{}", i),
-            AstNode::Variable(n) => n.clone(),
+            NodeKind::LiteralFloat { text, .. } => text.clone(),
+            NodeKind::LiteralInt(i) => format!("{}", i),
+            NodeKind::LiteralUint(u) => format!("{}u", u),
+            NodeKind::LiteralBool(b) => format!("{}", b),
+            NodeKind::Variable(n) => n.clone(),
         }
     }
 }
 
 // --- WGSL Generator ---
 
-pub struct WgslGenerator;
+#[derive(Default, Clone)]
+pub struct WgslGenerator {
+    /// For each function with `out`/`inout` parameters, by the symbol calls
+    /// use, which arguments are passed as pointers.
+    references: HashMap<String, Vec<bool>>,
+    /// The `out`/`inout` parameters of the function being generated, which
+    /// are pointers and used through `*`.
+    pointers: HashSet<String>,
+    /// How many `?:`s the function being generated has chosen with an `if`,
+    /// which names the `var` for the next one.
+    choices: Cell<usize>,
+}
+
+/// Which arguments of each function are `out`/`inout`, keyed by the name
+/// calls to it are generated with: its overload symbol or lowered method name.
+fn reference_params(program: &AstNode) -> HashMap<String, Vec<bool>> {
+    let NodeKind::Program(items) = &program.kind else { return HashMap::new() };
+    let functions = items.iter().flat_map(|item| match &item.kind {
+        NodeKind::FunctionDecl { name, overload, args, .. } => vec![(overload.clone().unwrap_or_else(|| name.clone()), args)],
+        NodeKind::ImplBlock { type_name, methods } => methods.iter().filter_map(|m| match &m.kind {
+            NodeKind::FunctionDecl { name, args, .. } => Some((method_name(type_name, name), args)),
+            _ => None,
+        }).collect(),
+        _ => vec![],
+    });
+    functions.filter(|(_, args)| args.iter().any(|a| a.qualifier.is_reference()))
+        .map(|(symbol, args)| (symbol, args.iter().map(|a| a.qualifier.is_reference()).collect()))
+        .collect()
+}
 
 impl WgslGenerator {
-    pub fn new() -> Self { Self }
+    pub fn new() -> Self { Self::default() }
+
+    /// The symbol a call is generated with, and its arguments (a method's
+    /// receiver first).
+    fn call_parts(node: &AstNode) -> Option<(String, Vec<&AstNode>)> {
+        match &node.kind {
+            NodeKind::Call { func_name, args, overload, .. } => Some((overload.clone().unwrap_or_else(|| func_name.clone()), args.iter().collect())),
+            NodeKind::MethodCall { receiver, method, args, receiver_type } => {
+                Some((method_name(receiver_type, method), std::iter::once(receiver.as_ref()).chain(args).collect()))
+            },
+            _ => None,
+        }
+    }
+
+    /// The arguments of a call to `symbol`, with `&` before those passed to
+    /// `out`/`inout` parameters.
+    fn generate_args(&self, symbol: &str, args: &[&AstNode]) -> String {
+        let references = self.references.get(symbol);
+        args.iter().enumerate().map(|(i, a)| match references.and_then(|r| r.get(i)) {
+            Some(true) => format!("&{}", self.generate(a)),
+            _ => self.generate(a),
+        }).collect::<Vec<_>>().join(", ")
+    }
+
+    /// The variables a function body writes to, by assignment or by passing
+    /// them to an `out`/`inout` parameter.
+    fn written_variables(&self, body: &AstNode) -> HashSet<String> {
+        let mut written = HashSet::new();
+        body.walk(&mut |node| {
+            let places = match &node.kind {
+                NodeKind::Assignment { target, .. } | NodeKind::CompoundAssignment { target, .. } => vec![target.as_ref()],
+                _ => match Self::call_parts(node) {
+                    Some((symbol, args)) => match self.references.get(&symbol) {
+                        Some(references) => args.into_iter().zip(references).filter(|(_, r)| **r).map(|(a, _)| a).collect(),
+                        None => vec![],
+                    },
+                    None => vec![],
+                },
+            };
+            for place in places {
+                if let NodeKind::Variable(name) = &place.root().kind { written.insert(name.clone()); }
+            }
+        });
+        written
+    }
+
+    /// Whether a `?:` of this type is chosen with an `if`: `select` only takes
+    /// scalars and vectors.
+    fn chosen_with_if(result_type: &str) -> bool {
+        matches!(Type::parse(result_type), Type::Struct(_) | Type::Array(..) | Type::Matrix(_))
+    }
+
+    /// Replaces the `?:`s in `expr` that `select` cannot choose by `var`s,
+    /// and returns them with the names of those `var`s. Their branches are
+    /// left to `generate_choice`.
+    fn take_choices(&self, expr: &mut AstNode, choices: &mut Vec<(String, AstNode)>) {
+        if let NodeKind::Ternary { result_type, .. } = &expr.kind {
+            if Self::chosen_with_if(result_type) {
+                let n = self.choices.replace(self.choices.get() + 1);
+                let name = if n == 0 { "sumi_choice".to_string() } else { format!("sumi_choice_{}", n) };
+                let var = AstNode::new(NodeKind::Variable(name.clone()), expr.span);
+                choices.push((name, std::mem::replace(expr, var)));
+                return;
+            }
+        }
+        expr.children_mut().into_iter().for_each(|child| self.take_choices(child, choices));
+    }
+
+    /// `var name: T;` and the `if` assigning it one branch of `choice`.
+    fn generate_choice(&self, name: &str, choice: AstNode) -> String {
+        let NodeKind::Ternary { condition, then_expr, else_expr, result_type } = choice.kind else { return String::new() };
+        let span = choice.span;
+        let assign = |value: Box<AstNode>| {
+            let target = Box::new(AstNode::new(NodeKind::Variable(name.to_string()), span));
+            Box::new(AstNode::new(NodeKind::Block(vec![AstNode::new(NodeKind::Assignment { target, value }, span)]), span))
+        };
+        let choose = AstNode::new(NodeKind::IfStmt { condition, then_branch: assign(then_expr), else_branch: Some(assign(else_expr)) }, span);
+        format!("var {}: {};\n{}", name, self.map_type(&result_type), self.generate_statement(&choose))
+    }
 
     fn map_type(&self, t: &str) -> String {
         match t {
@@ -114,7 +479,21 @@ impl WgslGenerator {
             "mat3"  => "mat3x3<f32>".to_string(),
             "mat4"  => "mat4x4<f32>".to_string(),
             "void"  => "".to_string(),
-            _ => t.to_string(),
+            _ => match (split_array_type(t), Type::parse(t)) {
+                (Some((element, size)), _) => array_type(&self.map_type(element), size),
+                (None, Type::Vector(element, n)) => format!("vec{}<{}>", n, self.map_type(&Type::Scalar(element).to_string())),
+                (None, _) => t.to_string(),
+            },
+        }
+    }
+
+    /// An array initializer; literals get the explicit `array<T, N>(..)` constructor.
+    fn generate_array_value(&self, t_arr: &str, value: &AstNode) -> String {
+        match &value.kind {
+            NodeKind::ArrayLiteral(items) => {
+                format!("{}({})", t_arr, items.iter().map(|i| self.generate(i)).collect::<Vec<_>>().join(", "))
+            },
+            _ => self.generate(value),
         }
     }
 
@@ -122,108 +501,1317 @@ impl WgslGenerator {
         match op {
             BinaryOperator::Add => "+", 
             BinaryOperator::Sub => "-",
-            BinaryOperator::Mul => "*", 
+            BinaryOperator::Mul | BinaryOperator::MatrixMul => "*",
             BinaryOperator::Div => "/",
+            BinaryOperator::Mod => "%",
             BinaryOperator::Equal => "==", 
+            BinaryOperator::NotEqual => "!=",
             BinaryOperator::Less => "<", 
             BinaryOperator::Greater => ">",
             BinaryOperator::LessEqual => "<=",    // <--- Added
             BinaryOperator::GreaterEqual => ">=", // <--- Added
+            BinaryOperator::And => "&&",
+            BinaryOperator::Or => "||",
+            BinaryOperator::BitAnd => "&",
+            BinaryOperator::BitOr => "|",
+            BinaryOperator::BitXor => "^",
+            BinaryOperator::ShiftLeft => "<<",
+            BinaryOperator::ShiftRight => ">>",
+        }
+    }
+}
+
+impl WgslGenerator {
+    /// WGSL requires the shift amount to be `u32`; integer literals convert
+    /// implicitly, anything else is wrapped in an explicit conversion.
+    fn generate_operand(&self, op: &BinaryOperator, right: &AstNode) -> String {
+        match (op, &right.kind) {
+            (BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight, NodeKind::LiteralInt(_) | NodeKind::LiteralUint(_)) => self.generate(right),
+            (BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight, _) => format!("u32({})", self.generate(right)),
+            _ => self.generate(right),
         }
     }
+
+    /// WGSL can only assign to one component at a time, so `p.xz = v` becomes
+    /// `p.x = v.x; p.z = v.y;`. The value goes through a temporary unless it is
+    /// a variable other than `p`; `p.xz += d` puts `p.xz + d` there first. A
+    /// target like `a[i].xz` is only evaluated once, through a pointer.
+    fn generate_swizzle_store(&self, base: &AstNode, components: &str, op: Option<&BinaryOperator>, value: &AstNode) -> String {
+        let root = base.root();
+        let mut setup = Vec::new();
+        let target = match base.kind {
+            NodeKind::Variable(_) => self.generate(base),
+            _ => {
+                setup.push(format!("let sumi_target = &{};", self.generate(base)));
+                "(*sumi_target)".to_string()
+            },
+        };
+        let source = match (op, &value.kind) {
+            (None, NodeKind::Variable(name)) if root.kind != NodeKind::Variable(name.clone()) => self.generate(value),
+            (None, _) => {
+                setup.push(format!("let sumi_value = {};", self.generate(value)));
+                "sumi_value".to_string()
+            },
+            (Some(op), _) => {
+                setup.push(format!("let sumi_value = {}.{} {} {};", target, components, self.generate_op(op), self.generate_operand(op, value)));
+                "sumi_value".to_string()
+            },
+        };
+        let stores: Vec<_> = components.chars().zip("xyzw".chars()).map(|(c, from)| format!("{}.{} = {}.{};", target, c, source, from)).collect();
+        if setup.is_empty() { stores.join(" ") } else { format!("{{ {} {} }}", setup.join(" "), stores.join(" ")) }
+    }
 }
 
 impl CodeGenerator for WgslGenerator {
+    fn validate(&self, ast: &AstNode) -> Vec<Diagnostic> {
+        let mut errors = unavailable_builtins(ast, Backend::Wgsl, "WGSL");
+        let references = reference_params(ast);
+        // Statements choose structs, arrays and matrices with an `if` before
+        // they run (see `generate_statement`); loop headers and globals cannot.
+        let globals = match &ast.kind {
+            NodeKind::Program(items) => items.iter().filter(|i| matches!(i.kind, NodeKind::VarDecl { .. } | NodeKind::ArrayDecl { .. })).collect(),
+            _ => vec![],
+        };
+        ast.walk(&mut |node| {
+            if let NodeKind::SwitchStmt { default: None, .. } = &node.kind {
+                errors.push(Diagnostic::error("switch without a `default` arm", node.span)
+                    .with_label("WGSL requires every switch to have a `default`")
+                    .with_hint("add `default: break;` if no other values need handling"));
+            }
+            let (place, headers) = match &node.kind {
+                NodeKind::ForStmt { init, condition, increment, .. } => ("a loop header", vec![init.as_ref(), condition, increment]),
+                NodeKind::WhileStmt { condition, .. } | NodeKind::DoWhileStmt { condition, .. } => ("a loop condition", vec![condition.as_ref()]),
+                _ if globals.iter().any(|g| std::ptr::eq(*g, node)) => ("a global declaration", vec![node]),
+                _ => ("", vec![]),
+            };
+            for header in headers {
+                header.walk(&mut |n| match &n.kind {
+                    NodeKind::Ternary { result_type, .. } if Self::chosen_with_if(result_type) => {
+                        errors.push(Diagnostic::error(format!("cannot choose a `{}` with `?:` in {}", result_type, place), n.span)
+                            .with_label("WGSL's `select` only takes scalars and vectors")
+                            .with_hint("choose it into a variable first, with `if`"));
+                    },
+                    _ => {},
+                });
+            }
+            let Some((symbol, args)) = Self::call_parts(node) else { return };
+            let Some(flags) = references.get(&symbol) else { return };
+            for (arg, _) in args.into_iter().zip(flags).filter(|(_, r)| **r) {
+                if let NodeKind::Swizzle { components, .. } = &arg.kind {
+                    errors.push(Diagnostic::error(format!("cannot pass `.{}` to an `out` or `inout` parameter", components), arg.span)
+                        .with_label("WGSL cannot point into a vector")
+                        .with_hint("pass a whole vector variable, or copy through a temporary `var`"));
+                }
+            }
+        });
+        errors
+    }
+
+    /// `select` only takes scalars and vectors, so a statement choosing a
+    /// struct, array or matrix with `?:` is preceded by an `if` assigning
+    /// the chosen value to a `var`, which the statement uses instead.
+    fn generate_statement(&self, stmt: &AstNode) -> String {
+        let generate = |stmt: &AstNode| match stmt.kind {
+            NodeKind::Call { .. } | NodeKind::MethodCall { .. } => format!("{};", self.generate(stmt)),
+            _ => self.generate(stmt),
+        };
+        let mut chosen = false;
+        stmt.walk(&mut |n| chosen |= matches!(&n.kind, NodeKind::Ternary { result_type, .. } if Self::chosen_with_if(result_type)));
+        if !chosen {
+            return generate(stmt);
+        }
+        // Only the parts evaluated before anything the statement contains.
+        let mut stmt = stmt.clone();
+        let mut choices = Vec::new();
+        match &mut stmt.kind {
+            NodeKind::IfStmt { condition: expr, .. } | NodeKind::SwitchStmt { selector: expr, .. } => self.take_choices(expr, &mut choices),
+            NodeKind::Block(_) | NodeKind::ForStmt { .. } | NodeKind::WhileStmt { .. } | NodeKind::DoWhileStmt { .. } | NodeKind::LoopStmt(_) => {},
+            _ => self.take_choices(&mut stmt, &mut choices),
+        }
+        if choices.is_empty() {
+            return generate(&stmt);
+        }
+        let mut lines = choices.into_iter().map(|(name, choice)| self.generate_choice(&name, choice)).collect::<Vec<_>>();
+        lines.push(generate(&stmt));
+        match stmt.kind {
+            // A declaration stays in scope for the statements after it.
+            NodeKind::VarDecl { .. } | NodeKind::ArrayDecl { .. } => lines.join("\n"),
+            _ => format!("{{\n{}\n}}", lines.join("\n")),
+        }
+    }
+
     fn generate(&self, ast: &AstNode) -> String {
-        match ast {
-            AstNode::Program(nodes) => nodes.iter().map(|n| self.generate(n)).filter(|s| !s.is_empty()).collect::<Vec<_>>().join("\n\n"),
+        match &ast.kind {
+            NodeKind::Program(nodes) => {
+                let generator = Self { references: reference_params(ast), ..self.clone() };
+                let helpers = helpers(ast, Backend::Wgsl, |t| self.map_type(t)).into_iter().map(|h| {
+                    let params = h.params.iter().zip(["x", "y"]).map(|(t, name)| format!("{}: {}", name, t)).collect::<Vec<_>>().join(", ");
+                    format!("fn {}({}) -> {} {{\n    return {};\n}}", h.symbol, params, h.returns, h.value)
+                });
+                helpers.chain(nodes.iter().map(|n| match &n.kind {
+                    // naga only allows constant indices into `const` arrays, so lookup
+                    // tables become private variables (the checker keeps them read-only).
+                    NodeKind::ArrayDecl { type_name, name, size, value: Some(v), .. } => {
+                        let t_arr = self.map_type(&array_type(type_name, *size));
+                        format!("var<private> {}: {} = {};", name, t_arr, generator.generate_array_value(&t_arr, v))
+                    },
+                    _ => generator.generate(n),
+                })).filter(|s| !s.is_empty()).collect::<Vec<_>>().join("\n\n")
+            },
+
+            NodeKind::ImplBlock { type_name, methods } => {
+                methods.iter().map(|m| self.generate(&lowered_method(type_name, m))).collect::<Vec<_>>().join("\n\n")
+            },
 
-            AstNode::StructDecl { name, fields, .. } => {
-                let f_str = fields.iter().map(|(t,n)| format!("    {}: {},", n, self.map_type(t))).collect::<Vec<_>>().join("\n");
+            NodeKind::StructDecl { name, fields, .. } => {
+                let f_str = fields.iter().map(|f| format!("    {}: {},", f.name, self.map_type(&f.type_name))).collect::<Vec<_>>().join("\n");
                 format!("struct {} {{\n{}\n}};", name, f_str)
             },
 
-            AstNode::FunctionDecl { return_type, name, args, body, .. } => {
+            NodeKind::FunctionDecl { return_type, name, args, body, overload, .. } => {
                 let ret = self.map_type(return_type);
                 let ret_str = if ret.is_empty() { "".to_string() } else { format!("-> {}", ret) };
-                let arg_str = args.iter().map(|(t,n)| format!("{}: {}", n, self.map_type(t))).collect::<Vec<_>>().join(", ");
-                format!("fn {}({}) {} {}", name, arg_str, ret_str, self.generate(body))
+                // WGSL parameters are immutable values, and naga only lets us index
+                // arrays with constants; copy array parameters, and any the body
+                // writes to, into a local `var` under the original name.
+                // `out`/`inout` parameters are pointers.
+                let written = self.written_variables(body);
+                let mut body = body.as_ref().clone();
+                let mut copies = Vec::new();
+                let arg_str = args.iter().map(|a| {
+                    let t = self.map_type(&a.type_name);
+                    // `self` is reserved in WGSL
+                    let name = if a.name == "self" { "self_" } else { a.name.as_str() };
+                    if a.qualifier.is_reference() {
+                        format!("{}: ptr<function, {}>", name, t)
+                    } else if split_array_type(&a.type_name).is_some() || written.contains(&a.name) {
+                        let value = Some(Box::new(AstNode::new(NodeKind::Variable(format!("{}_in", name)), body.span)));
+                        let copy = NodeKind::VarDecl { binding: BindingKind::Var, type_name: a.type_name.clone(), name: name.to_string(), value, doc_string: None, public: false };
+                        copies.push(AstNode::new(copy, body.span));
+                        format!("{}_in: {}", name, t)
+                    } else {
+                        format!("{}: {}", name, t)
+                    }
+                }).collect::<Vec<_>>().join(", ");
+                if let NodeKind::Block(stmts) = &mut body.kind {
+                    stmts.splice(0..0, copies);
+                }
+                let pointers = args.iter().filter(|a| a.qualifier.is_reference()).map(|a| a.name.clone()).collect();
+                let body = Self { pointers, ..self.clone() }.generate(&body);
+                // WGSL has no overloading: each overload gets its own symbol.
+                format!("fn {}({}) {} {}", overload.as_ref().unwrap_or(name), arg_str, ret_str, body)
             },
 
-            AstNode::Block(stmts) => {
-                let inner = stmts.iter().map(|s| format!("    {}", self.generate(s))).collect::<Vec<_>>().join("\n");
+            NodeKind::Block(stmts) => {
+                let inner = stmts.iter().map(|s| format!("    {}", self.generate_statement(s))).collect::<Vec<_>>().join("\n");
                 format!("{{\n{}\n}}", inner)
             },
 
-            AstNode::ReturnStmt(expr) => format!("return {};", self.generate(expr)),
+            NodeKind::ReturnStmt(expr) => format!("return {};", self.generate(expr)),
 
-            AstNode::IfStmt { condition, then_branch, else_branch } => {
-                let base = format!("if ({}) {}", self.generate(condition), self.generate(then_branch));
-                if let Some(e) = else_branch { format!("{} else {}", base, self.generate(e)) } else { base }
+            NodeKind::IfStmt { condition, then_branch, else_branch } => {
+                let base = format!("if ({}) {}", self.generate(condition), self.generate_statement(then_branch));
+                if let Some(e) = else_branch { format!("{} else {}", base, self.generate_statement(e)) } else { base }
             },
 
-            AstNode::ForStmt { init, condition, increment, body } => {
+            NodeKind::ForStmt { init, condition, increment, body } => {
                 let i = self.generate(init);
                 let c = self.generate(condition);
                 let inc = self.generate(increment);
                 format!("for ({} {}; {}) {}", i, c, inc.trim_end_matches(';'), self.generate(body))
             },
 
-            AstNode::BreakStmt => "break;".to_string(),
+            NodeKind::WhileStmt { condition, body } => format!("while ({}) {}", self.generate(condition), self.generate(body)),
 
-            AstNode::VarDecl { type_name, name, value } => {
-                let t = self.map_type(type_name);
-                if let Some(v) = value { format!("var {}: {} = {};", name, t, self.generate(v)) }
-                else { format!("var {}: {};", name, t) }
+            // WGSL has no do-while: test the condition in the `continuing` block,
+            // which is also where `continue` jumps to.
+            NodeKind::DoWhileStmt { body, condition } => {
+                let stmts = match &body.kind {
+                    NodeKind::Block(stmts) => stmts.iter().collect::<Vec<_>>(),
+                    _ => vec![body.as_ref()],
+                };
+                let mut inner = stmts.iter().map(|s| format!("    {}", self.generate_statement(s))).collect::<Vec<_>>();
+                inner.push(format!("    continuing {{\n        break if !({});\n    }}", self.generate(condition)));
+                format!("loop {{\n{}\n}}", inner.join("\n"))
+            },
+
+            NodeKind::LoopStmt(body) => format!("loop {}", self.generate(body)),
+
+            NodeKind::SwitchStmt { selector, cases, default } => {
+                let mut arms = cases.iter().map(|c| {
+                    let labels = c.values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ");
+                    format!("    case {}: {}", labels, self.generate(&c.body))
+                }).collect::<Vec<_>>();
+                if let Some(d) = default { arms.push(format!("    default: {}", self.generate(d))); }
+                format!("switch ({}) {{\n{}\n}}", self.generate(selector), arms.join("\n"))
             },
 
-            AstNode::ArrayDecl { type_name, name, size, values } => {
+            // Imports are replaced by the imported items when modules are linked.
+            NodeKind::Import { .. } => String::new(),
+            NodeKind::BreakStmt => "break;".to_string(),
+            NodeKind::ContinueStmt => "continue;".to_string(),
+
+            NodeKind::VarDecl { binding, type_name, name, value, .. } => {
                 let t = self.map_type(type_name);
-                let t_arr = format!("array<{}, {}>", t, size);
-                if let Some(vals) = values {
-                    let v_str = vals.iter().map(|v| self.generate(v)).collect::<Vec<_>>().join(", ");
-                    format!("var {}: {} = {}({});", name, t_arr, t_arr, v_str)
-                } else { format!("var {}: {};", name, t_arr) }
+                if let Some(v) = value { format!("{} {}: {} = {};", binding.keyword(), name, t, self.generate(v)) }
+                else { format!("{} {}: {};", binding.keyword(), name, t) }
+            },
+
+            // Always `var`, even for `let`/`const`: naga rejects dynamic indexing of
+            // array values.
+            NodeKind::ArrayDecl { type_name, name, size, value, .. } => {
+                let t_arr = self.map_type(&array_type(type_name, *size));
+                if let Some(v) = value { format!("var {}: {} = {};", name, t_arr, self.generate_array_value(&t_arr, v)) }
+                else { format!("var {}: {};", name, t_arr) }
             },
 
-            AstNode::Assignment { target, value } => format!("{} = {};", self.generate(target), self.generate(value)),
+            NodeKind::Assignment { target, value } => match &target.kind {
+                NodeKind::Swizzle { base, components } if components.len() > 1 => self.generate_swizzle_store(base, components, None, value),
+                _ => format!("{} = {};", self.generate(target), self.generate(value)),
+            },
+            NodeKind::CompoundAssignment { target, op, value } => match &target.kind {
+                NodeKind::Swizzle { base, components } if components.len() > 1 => self.generate_swizzle_store(base, components, Some(op), value),
+                _ => format!("{} {}= {};", self.generate(target), self.generate_op(op), self.generate_operand(op, value)),
+            },
 
-            AstNode::BinaryOp { left, op, right } => format!("({} {} {})", self.generate(left), self.generate_op(op), self.generate(right)),
+            NodeKind::BinaryOp { left, op, right } => format!("({} {} {})", self.generate(left), self.generate_op(op), self.generate_operand(op, right)),
 
-            AstNode::UnaryOp { op, right } => {
-                let s = match op { UnaryOperator::Negate => "-", UnaryOperator::Not => "!" };
+            NodeKind::UnaryOp { op, right } => {
+                let s = match op { UnaryOperator::Negate => "-", UnaryOperator::Not => "!", UnaryOperator::BitNot => "~" };
                 format!("({}{})", s, self.generate(right))
             },
 
-            AstNode::Call { func_name, args } => {
-                let arg_str = args.iter().map(|a| self.generate(a)).collect::<Vec<_>>().join(", ");
-                match func_name.as_str() {
-                    "vec2" | "vec3" | "vec4" => format!("{}<f32>({})", func_name, arg_str),
-                    "mat2" => format!("mat2x2<f32>({})", arg_str),
-                    "mat3" => format!("mat3x3<f32>({})", arg_str),
-                    "mat4" => format!("mat4x4<f32>({})", arg_str),
-                    "float" => format!("f32({})", arg_str),
-                    "int" => format!("i32({})", arg_str),
-                    "uint" => format!("u32({})", arg_str),
-                    _ => format!("{}({})", func_name, arg_str)
+            // WGSL's select() takes the false value first: select(f, t, cond)
+            NodeKind::Ternary { condition, then_expr, else_expr, .. } => {
+                format!("select({}, {}, {})", self.generate(else_expr), self.generate(then_expr), self.generate(condition))
+            },
+
+            NodeKind::StructLiteral { name, fields } => {
+                format!("{}({})", name, fields.iter().map(|(_, v)| self.generate(v)).collect::<Vec<_>>().join(", "))
+            },
+            NodeKind::ArrayLiteral(items) => format!("array({})", items.iter().map(|i| self.generate(i)).collect::<Vec<_>>().join(", ")),
+
+            NodeKind::MethodCall { .. } | NodeKind::Call { overload: Some(_), .. } => {
+                let (symbol, args) = Self::call_parts(ast).unwrap_or_default();
+                format!("{}({})", symbol, self.generate_args(&symbol, &args))
+            },
+            NodeKind::Call { func_name, args, .. } if self.references.contains_key(func_name) => {
+                format!("{}({})", func_name, self.generate_args(func_name, &args.iter().collect::<Vec<_>>()))
+            },
+            NodeKind::Call { func_name, args, arg_types, .. } => {
+                let arg_strs = args.iter().map(|a| self.generate(a)).collect::<Vec<_>>();
+                match builtins::is_builtin(func_name) {
+                    _ if needs_helper(Backend::Wgsl, func_name, arg_types) => helper_call(func_name, arg_types, args, |a| self.generate(a)),
+                    true => builtin_call(Backend::Wgsl, func_name, &arg_strs),
+                    // Constructors, under the WGSL type name.
+                    false => format!("{}({})", self.map_type(func_name), arg_strs.join(", ")),
                 }
             },
 
-            AstNode::MemberAccess { base, member } => format!("{}.{}", self.generate(base), member),
-            AstNode::SubscriptAccess { base, index } => format!("{}[{}]", self.generate(base), self.generate(index)),
+            NodeKind::MemberAccess { base, member } | NodeKind::Swizzle { base, components: member } => format!("{}.{}", self.generate(base), member),
+            NodeKind::SubscriptAccess { base, index } => format!("{}[{}]", self.generate(base), self.generate(index)),
             
-            AstNode::LiteralFloat(f) => if f.fract() == 0.0 { format!("{:.1}", f) } else { format!("{}", f) },
-            AstNode::LiteralInt(i) => format!("{}", i),
+            NodeKind::LiteralFloat { text, .. } => text.clone(),
+            NodeKind::LiteralInt(i) => format!("{}", i),
+            NodeKind::LiteralUint(u) => format!("{}u", u),
+            NodeKind::LiteralBool(b) => format!("{}", b),
             
-            AstNode::Variable(name) => match name.as_str() {
+            NodeKind::Variable(name) => match name.as_str() {
                 "iTime" => "u.time".to_string(),
                 "iResolution" => "vec3<f32>(u.resolution, 1.0)".to_string(),
                 "iMouse" => "u.mouse".to_string(),
+                // `self` is reserved in WGSL
+                "self" => "self_".to_string(),
+                _ if self.pointers.contains(name) => format!("(*{})", name),
                 _ => name.clone(),
             },
         }
     }
 }
 
+// --- GLSL Generator ---
+
+/// The GLSL dialect to write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GlslProfile {
+    /// Desktop OpenGL 4.5.
+    #[default]
+    Glsl450,
+    /// OpenGL ES 3.0 and WebGL 2, as Shadertoy uses.
+    Es300,
+    /// OpenGL ES 2.0 and WebGL 1: no integers beyond `int`, no `switch`, and
+    /// only `for` loops.
+    Es100,
+}
+
+impl GlslProfile {
+    fn version(self) -> &'static str {
+        match self {
+            GlslProfile::Glsl450 => "#version 450",
+            GlslProfile::Es300 => "#version 300 es",
+            GlslProfile::Es100 => "#version 100",
+        }
+    }
+}
+
+impl std::str::FromStr for GlslProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "450" => Ok(GlslProfile::Glsl450),
+            "300es" => Ok(GlslProfile::Es300),
+            "100" => Ok(GlslProfile::Es100),
+            _ => Err("expected `450`, `300es` or `100`".to_string()),
+        }
+    }
+}
+
+/// The host functions for programs that call them.
+const GLSL_HOST_FUNCTIONS: &str = include_str!("stdlib.glsl");
+
+/// Writes a complete fragment shader: the version directive, precision
+/// qualifiers and uniforms, then the program, then a Shadertoy
+/// `mainImage(out vec4, in vec2)` around the S2L `mainImage(vec2) vec4` and a
+/// `main` calling it. With `shadertoy`, only the part to paste into
+/// shadertoy.com, which declares the rest itself.
+#[derive(Default)]
+pub struct GlslGenerator {
+    pub profile: GlslProfile,
+    pub shadertoy: bool,
+}
+
+impl GlslGenerator {
+    pub fn new(profile: GlslProfile, shadertoy: bool) -> Self { Self { profile, shadertoy } }
+
+    fn backend(&self) -> Backend {
+        if self.profile == GlslProfile::Es100 { Backend::Glsl100 } else { Backend::Glsl }
+    }
+
+    /// `array<float, 3>` is `float[3]`, and the WGSL scalar names S2L
+    /// accepts (`i32`) are the GLSL ones.
+    fn map_type(&self, t: &str) -> String {
+        match split_array_type(t) {
+            Some((element, size)) => format!("{}[{}]", self.map_type(element), size),
+            None => match Type::parse(t) {
+                Type::Struct(_) => t.to_string(),
+                parsed => parsed.to_string(),
+            },
+        }
+    }
+
+    /// A declaration of `name`; array sizes go after the name, C style, which
+    /// is the only way GLSL ES 1.00 has.
+    fn declare(&self, t: &str, name: &str) -> String {
+        match split_array_type(t) {
+            Some((element, size)) => format!("{} {}[{}]", self.map_type(element), name, size),
+            None => format!("{} {}", self.map_type(t), name),
+        }
+    }
+
+    /// An array initializer; literals become a constructor of the array type.
+    fn generate_array_value(&self, t_arr: &str, value: &AstNode) -> String {
+        match &value.kind {
+            NodeKind::ArrayLiteral(items) => format!("{}({})", t_arr, items.iter().map(|i| self.generate(i)).collect::<Vec<_>>().join(", ")),
+            _ => self.generate(value),
+        }
+    }
+
+    /// The declarations before the program: everything a standalone shader
+    /// needs that Shadertoy provides.
+    fn header(&self) -> String {
+        let mut lines = vec![self.profile.version().to_string()];
+        if self.profile != GlslProfile::Glsl450 {
+            // Fragment shaders have no default float precision in GLSL ES.
+            lines.push("precision highp float;".to_string());
+            lines.push("precision highp int;".to_string());
+        }
+        lines.push(String::new());
+        lines.extend(UNIFORMS.iter().map(|(name, t)| format!("uniform {} {};", t, name)));
+        lines.join("\n")
+    }
+
+    /// Shadertoy's entry point, if the program has an S2L `mainImage` to wrap,
+    /// and for a standalone shader the `main` that calls it.
+    fn entry_point(&self, items: &[AstNode]) -> Option<String> {
+        if !has_main_image(items) {
+            return None;
+        }
+        let wrapper = "void mainImage(out vec4 fragColor, in vec2 fragCoord) {\n    fragColor = mainImage(fragCoord);\n}";
+        if self.shadertoy {
+            return Some(wrapper.to_string());
+        }
+        let main = match self.profile {
+            GlslProfile::Es100 => "void main() {\n    mainImage(gl_FragColor, gl_FragCoord.xy);\n}".to_string(),
+            _ => "layout(location = 0) out vec4 sumi_FragColor;\n\nvoid main() {\n    mainImage(sumi_FragColor, gl_FragCoord.xy);\n}".to_string(),
+        };
+        Some(format!("{}\n\n{}", wrapper, main))
+    }
+
+    /// The feature of `node` that GLSL ES 1.00 lacks, with a hint, or `None`
+    /// if it can express `node`.
+    fn es100_error(node: &AstNode) -> Option<(String, &'static str)> {
+        let uses_uint = |t: &str| ["uint", "uvec", "u32"].iter().any(|u| t.contains(u));
+        match &node.kind {
+            NodeKind::SwitchStmt { .. } => Some(("`switch`".into(), "use `if`/`else if` chains")),
+            NodeKind::WhileStmt { .. } | NodeKind::DoWhileStmt { .. } | NodeKind::LoopStmt(_) => {
+                Some(("loops other than `for`".into(), "use a `for` loop with a constant bound and `break`"))
+            },
+            NodeKind::BinaryOp { op, .. } | NodeKind::CompoundAssignment { op, .. } if matches!(op,
+                BinaryOperator::Mod | BinaryOperator::BitAnd | BinaryOperator::BitOr | BinaryOperator::BitXor | BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight) => {
+                Some((format!("`{}` operator", c_operator(op)), "use `mod`, or arithmetic on floats"))
+            },
+            NodeKind::UnaryOp { op: UnaryOperator::BitNot, .. } => Some(("`~` operator".into(), "use arithmetic on floats")),
+            NodeKind::LiteralUint(_) => Some(("`uint`".into(), "use `int`")),
+            NodeKind::VarDecl { type_name, .. } | NodeKind::ArrayDecl { type_name, .. } if uses_uint(type_name) => Some(("`uint`".into(), "use `int`")),
+            NodeKind::ArrayDecl { value: Some(_), .. } => Some(("array initializers".into(), "assign the elements one by one")),
+            NodeKind::FunctionDecl { return_type, args, .. } if uses_uint(return_type) || args.iter().any(|a| uses_uint(&a.type_name)) => {
+                Some(("`uint`".into(), "use `int`"))
+            },
+            NodeKind::FunctionDecl { return_type, .. } if split_array_type(return_type).is_some() => {
+                Some(("array return types".into(), "return a struct holding the array"))
+            },
+            NodeKind::FunctionDecl { args, .. } if args.iter().any(|a| split_array_type(&a.type_name).is_some()) => {
+                Some(("array parameters".into(), "pass a struct holding the array"))
+            },
+            NodeKind::Call { func_name, args, overload: None, .. } if builtins::spelling(func_name, args.len(), Backend::Glsl100) == Some(Spelling::Unavailable) => {
+                Some((format!("`{}`", func_name), "use the `300es` profile, or write it out with the functions GLSL ES 1.00 has"))
+            },
+            _ => None,
+        }
+    }
+}
+
+impl CodeGenerator for GlslGenerator {
+    fn validate(&self, ast: &AstNode) -> Vec<Diagnostic> {
+        // Array constructors need the element type, which only a declaration gives.
+        let mut errors = stray_array_literals(ast, "GLSL needs the array's type to construct it");
+        ast.walk(&mut |node| {
+            if self.profile != GlslProfile::Es100 { return }
+            if let Some((feature, hint)) = Self::es100_error(node) {
+                errors.push(Diagnostic::error(format!("GLSL ES 1.00 has no {}", feature), node.span)
+                    .with_label("not supported by the `100` profile")
+                    .with_hint(hint));
+            }
+        });
+        errors
+    }
+
+    fn generate(&self, ast: &AstNode) -> String {
+        match &ast.kind {
+            NodeKind::Program(nodes) => {
+                let mut sections = Vec::new();
+                if !self.shadertoy {
+                    sections.push(self.header());
+                }
+                if calls_host_function(ast) {
+                    sections.push(GLSL_HOST_FUNCTIONS.trim_end().to_string());
+                }
+                sections.extend(helpers(ast, self.backend(), |t| self.map_type(t)).iter().map(Helper::c_style));
+                sections.extend(nodes.iter().map(|n| match &n.kind {
+                    NodeKind::VarDecl { type_name, name, value: Some(v), .. } => {
+                        format!("const {} {} = {};", self.map_type(type_name), name, self.generate(v))
+                    },
+                    NodeKind::ArrayDecl { type_name, name, size, value: Some(v), .. } => {
+                        let t_arr = array_type(type_name, *size);
+                        format!("const {} = {};", self.declare(&t_arr, name), self.generate_array_value(&self.map_type(&t_arr), v))
+                    },
+                    _ => self.generate(n),
+                }).filter(|s| !s.is_empty()));
+                sections.extend(self.entry_point(nodes));
+                sections.join("\n\n")
+            },
+
+            NodeKind::FunctionDecl { return_type, name, args, body, overload, .. } => {
+                let arg_str = args.iter().map(|a| match a.qualifier.is_reference() {
+                    true => format!("{} {}", a.qualifier.keyword(), self.declare(&a.type_name, &a.name)),
+                    false => self.declare(&a.type_name, &a.name),
+                }).collect::<Vec<_>>().join(", ");
+                format!("{} {}({}) {}", self.map_type(return_type), native_function_name(name, overload.as_deref()), arg_str, self.generate(body))
+            },
+
+            NodeKind::ImplBlock { type_name, methods } => {
+                methods.iter().map(|m| self.generate(&lowered_method(type_name, m))).collect::<Vec<_>>().join("\n\n")
+            },
+
+            NodeKind::StructDecl { name, fields, .. } => {
+                let f_str = fields.iter().map(|f| format!("    {};", self.declare(&f.type_name, &f.name))).collect::<Vec<_>>().join("\n");
+                format!("struct {} {{\n{}\n}};", name, f_str)
+            },
+
+            NodeKind::Block(stmts) => format!("{{\n{}\n}}", stmts.iter().map(|s| format!("    {}", self.generate_statement(s))).collect::<Vec<_>>().join("\n")),
+
+            NodeKind::ReturnStmt(expr) => format!("return {};", self.generate(expr)),
+
+            NodeKind::IfStmt { condition, then_branch, else_branch } => {
+                let base = format!("if ({}) {}", self.generate(condition), self.generate_statement(then_branch));
+                if let Some(e) = else_branch { format!("{} else {}", base, self.generate_statement(e)) } else { base }
+            },
+
+            NodeKind::ForStmt { init, condition, increment, body } => {
+                let i = self.generate(init);
+                let c = self.generate(condition);
+                let inc = self.generate(increment);
+                format!("for ({} {}; {}) {}", i, c, inc.trim_end_matches(';'), self.generate(body))
+            },
+
+            NodeKind::WhileStmt { condition, body } => format!("while ({}) {}", self.generate(condition), self.generate(body)),
+
+            NodeKind::DoWhileStmt { body, condition } => format!("do {} while ({});", self.generate(body), self.generate(condition)),
+
+            NodeKind::LoopStmt(body) => format!("while (true) {}", self.generate(body)),
+
+            // GLSL switches fall through, so every arm is closed with `break`.
+            NodeKind::SwitchStmt { selector, cases, default } => {
+                let mut arms = cases.iter().map(|c| {
+                    let labels = c.values.iter().map(|v| format!("case {}:", v)).collect::<Vec<_>>().join(" ");
+                    format!("    {} {} break;", labels, self.generate(&c.body))
+                }).collect::<Vec<_>>();
+                if let Some(d) = default { arms.push(format!("    default: {} break;", self.generate(d))); }
+                format!("switch ({}) {{\n{}\n}}", self.generate(selector), arms.join("\n"))
+            },
+
+            // Imports are replaced by the imported items when modules are linked.
+            NodeKind::Import { .. } => String::new(),
+            NodeKind::BreakStmt => "break;".to_string(),
+            NodeKind::ContinueStmt => "continue;".to_string(),
+
+            // GLSL ES only allows `const` with a constant initializer, so `let`
+            // is a plain local.
+            NodeKind::VarDecl { binding, type_name, name, value, .. } => {
+                let qualifier = if *binding == BindingKind::Const { "const " } else { "" };
+                let t = self.map_type(type_name);
+                if let Some(v) = value { format!("{}{} {} = {};", qualifier, t, name, self.generate(v)) }
+                else { format!("{}{} {};", qualifier, t, name) }
+            },
+
+            NodeKind::ArrayDecl { binding, type_name, name, size, value, .. } => {
+                let qualifier = if *binding == BindingKind::Const { "const " } else { "" };
+                let t_arr = array_type(type_name, *size);
+                if let Some(v) = value { format!("{}{} = {};", qualifier, self.declare(&t_arr, name), self.generate_array_value(&self.map_type(&t_arr), v)) }
+                else { format!("{}{};", qualifier, self.declare(&t_arr, name)) }
+            },
+
+            NodeKind::Assignment { target, value } => format!("{} = {};", self.generate(target), self.generate(value)),
+            NodeKind::CompoundAssignment { target, op, value } => format!("{} {}= {};", self.generate(target), c_operator(op), self.generate(value)),
+
+            NodeKind::BinaryOp { left, op, right } => format!("({} {} {})", self.generate(left), c_operator(op), self.generate(right)),
+
+            NodeKind::UnaryOp { op, right } => {
+                let s = match op { UnaryOperator::Negate => "-", UnaryOperator::Not => "!", UnaryOperator::BitNot => "~" };
+                format!("({}{})", s, self.generate(right))
+            },
+
+            NodeKind::Ternary { condition, then_expr, else_expr, .. } => {
+                format!("({} ? {} : {})", self.generate(condition), self.generate(then_expr), self.generate(else_expr))
+            },
+
+            NodeKind::StructLiteral { name, fields } => {
+                format!("{}({})", name, fields.iter().map(|(_, v)| self.generate(v)).collect::<Vec<_>>().join(", "))
+            },
+            // Only reached in a position `validate` rejects.
+            NodeKind::ArrayLiteral(items) => format!("{{ {} }}", items.iter().map(|i| self.generate(i)).collect::<Vec<_>>().join(", ")),
+
+            NodeKind::MethodCall { receiver, method, args, receiver_type } => {
+                let args = std::iter::once(receiver.as_ref()).chain(args).map(|a| self.generate(a)).collect::<Vec<_>>();
+                format!("{}({})", method_name(receiver_type, method), args.join(", "))
+            },
+            NodeKind::Call { func_name, args, overload, arg_types } => {
+                let arg_strs = args.iter().map(|a| self.generate(a)).collect::<Vec<_>>();
+                match overload {
+                    Some(symbol) => format!("{}({})", native_function_name(func_name, Some(symbol)), arg_strs.join(", ")),
+                    None if needs_helper(self.backend(), func_name, arg_types) => helper_call(func_name, arg_types, args, |a| self.generate(a)),
+                    None if builtins::is_builtin(func_name) => builtin_call(self.backend(), func_name, &arg_strs),
+                    // Constructors, under the GLSL type name.
+                    None => format!("{}({})", self.map_type(func_name), arg_strs.join(", ")),
+                }
+            },
+
+            NodeKind::MemberAccess { base, member } | NodeKind::Swizzle { base, components: member } => format!("{}.{}", self.generate(base), member),
+            NodeKind::SubscriptAccess { base, index } => format!("{}[{}]", self.generate(base), self.generate(index)),
+
+            NodeKind::LiteralFloat { text, .. } => text.clone(),
+            NodeKind::LiteralInt(i) => format!("{}", i),
+            NodeKind::LiteralUint(u) => format!("{}u", u),
+            NodeKind::LiteralBool(b) => format!("{}", b),
+            NodeKind::Variable(n) => n.clone(),
+        }
+    }
+}
+
+// --- HLSL Generator ---
+
+/// The host functions for programs that call them.
+const HLSL_HOST_FUNCTIONS: &str = include_str!("stdlib.hlsl");
+
+/// Writes a pixel shader: the uniforms in a `cbuffer`, the program, and a
+/// `ps_main` entry point calling `mainImage`. HLSL builds matrices from rows
+/// where GLSL builds them from columns, so each S2L matrix is kept as its
+/// transpose: constructors and indexing carry over as they are, and the
+/// product `a * b` is written `mul(b, a)`.
+pub struct HlslGenerator;
+
+impl HlslGenerator {
+    /// `vec3` is `float3`, `mat4` is `float4x4`.
+    fn map_type(&self, t: &str) -> String {
+        match Type::parse(t) {
+            Type::Vector(element, n) => format!("{}{}", Type::Scalar(element), n),
+            Type::Matrix(n) => format!("float{}x{}", n, n),
+            Type::Struct(_) => t.to_string(),
+            parsed => parsed.to_string(),
+        }
+    }
+
+    /// A declaration of `name`; array sizes go after the name, C style.
+    fn declare(&self, t: &str, name: &str) -> String {
+        match split_array_type(t) {
+            Some((element, size)) => format!("{} {}[{}]", self.map_type(element), name, size),
+            None => format!("{} {}", self.map_type(t), name),
+        }
+    }
+
+    /// A constructor for a struct used in a literal: HLSL only initializes
+    /// structs in declarations.
+    fn struct_constructor(&self, name: &str, fields: &[Field]) -> String {
+        let params = fields.iter().map(|f| self.declare(&f.type_name, &f.name)).collect::<Vec<_>>().join(", ");
+        let values = fields.iter().map(|f| f.name.as_str()).collect::<Vec<_>>().join(", ");
+        format!("{} sumi_make_{}({}) {{\n    {} sumi_value = {{ {} }};\n    return sumi_value;\n}}", name, name, params, name, values)
+    }
+
+    /// HLSL swizzles take `xyzw` or `rgba`, not `stpq`.
+    fn swizzle(components: &str) -> String {
+        components.chars().map(|c| match "stpq".find(c) {
+            Some(i) => "xyzw".as_bytes()[i] as char,
+            None => c,
+        }).collect()
+    }
+}
+
+impl CodeGenerator for HlslGenerator {
+    fn validate(&self, ast: &AstNode) -> Vec<Diagnostic> {
+        let mut errors = stray_array_literals(ast, "HLSL only takes `{ ... }` initializers in declarations");
+        errors.extend(unavailable_builtins(ast, Backend::Hlsl, "HLSL"));
+        ast.walk(&mut |node| {
+            if let NodeKind::FunctionDecl { return_type, .. } = &node.kind {
+                if split_array_type(return_type).is_some() {
+                    errors.push(Diagnostic::error(format!("cannot return `{}` in HLSL", return_type), node.span)
+                        .with_label("HLSL functions cannot return arrays")
+                        .with_hint("return a struct with an array field, or fill an `out` parameter"));
+                }
+            }
+        });
+        errors
+    }
+
+    fn generate(&self, ast: &AstNode) -> String {
+        match &ast.kind {
+            NodeKind::Program(nodes) => {
+                let uniforms = UNIFORMS.iter().map(|(name, t)| format!("    {};", self.declare(t, name))).collect::<Vec<_>>().join("\n");
+                let mut sections = vec![format!("cbuffer Uniforms : register(b0) {{\n{}\n}};", uniforms)];
+                if calls_host_function(ast) {
+                    sections.push(HLSL_HOST_FUNCTIONS.trim_end().to_string());
+                }
+                sections.extend(helpers(ast, Backend::Hlsl, |t| self.map_type(t)).iter().map(Helper::c_style));
+                let mut constructed = HashSet::new();
+                ast.walk(&mut |n| if let NodeKind::StructLiteral { name, fields } = &n.kind {
+                    if !fields.is_empty() { constructed.insert(name.clone()); }
+                });
+                for n in nodes {
+                    sections.push(match &n.kind {
+                        // Globals without `static` would be uniforms.
+                        NodeKind::VarDecl { .. } | NodeKind::ArrayDecl { .. } => format!("static {}", self.generate(n)),
+                        NodeKind::StructDecl { name, fields, .. } if constructed.contains(name) => {
+                            format!("{}\n\n{}", self.generate(n), self.struct_constructor(name, fields))
+                        },
+                        _ => self.generate(n),
+                    });
+                }
+                if has_main_image(nodes) {
+                    // Shadertoy's origin is the bottom-left corner, Direct3D's the top-left.
+                    sections.push("float4 ps_main(float4 position : SV_Position) : SV_Target {\n    return mainImage(float2(position.x, iResolution.y - position.y));\n}".to_string());
+                }
+                sections.into_iter().filter(|s| !s.is_empty()).collect::<Vec<_>>().join("\n\n")
+            },
+
+            NodeKind::FunctionDecl { return_type, name, args, body, overload, .. } => {
+                let arg_str = args.iter().map(|a| match a.qualifier.is_reference() {
+                    true => format!("{} {}", a.qualifier.keyword(), self.declare(&a.type_name, &a.name)),
+                    false => self.declare(&a.type_name, &a.name),
+                }).collect::<Vec<_>>().join(", ");
+                format!("{} {}({}) {}", self.map_type(return_type), native_function_name(name, overload.as_deref()), arg_str, self.generate(body))
+            },
+
+            NodeKind::ImplBlock { type_name, methods } => {
+                methods.iter().map(|m| self.generate(&lowered_method(type_name, m))).collect::<Vec<_>>().join("\n\n")
+            },
+
+            NodeKind::StructDecl { name, fields, .. } => {
+                let f_str = fields.iter().map(|f| format!("    {};", self.declare(&f.type_name, &f.name))).collect::<Vec<_>>().join("\n");
+                format!("struct {} {{\n{}\n}};", name, f_str)
+            },
+
+            NodeKind::Block(stmts) => format!("{{\n{}\n}}", stmts.iter().map(|s| format!("    {}", self.generate_statement(s))).collect::<Vec<_>>().join("\n")),
+
+            NodeKind::ReturnStmt(expr) => format!("return {};", self.generate(expr)),
+
+            NodeKind::IfStmt { condition, then_branch, else_branch } => {
+                let base = format!("if ({}) {}", self.generate(condition), self.generate_statement(then_branch));
+                if let Some(e) = else_branch { format!("{} else {}", base, self.generate_statement(e)) } else { base }
+            },
+
+            NodeKind::ForStmt { init, condition, increment, body } => {
+                let i = self.generate(init);
+                let c = self.generate(condition);
+                let inc = self.generate(increment);
+                format!("for ({} {}; {}) {}", i, c, inc.trim_end_matches(';'), self.generate(body))
+            },
+
+            NodeKind::WhileStmt { condition, body } => format!("while ({}) {}", self.generate(condition), self.generate(body)),
+
+            NodeKind::DoWhileStmt { body, condition } => format!("do {} while ({});", self.generate(body), self.generate(condition)),
+
+            NodeKind::LoopStmt(body) => format!("while (true) {}", self.generate(body)),
+
+            // HLSL switches fall through, so every arm is closed with `break`.
+            NodeKind::SwitchStmt { selector, cases, default } => {
+                let mut arms = cases.iter().map(|c| {
+                    let labels = c.values.iter().map(|v| format!("case {}:", v)).collect::<Vec<_>>().join(" ");
+                    format!("    {} {} break;", labels, self.generate(&c.body))
+                }).collect::<Vec<_>>();
+                if let Some(d) = default { arms.push(format!("    default: {} break;", self.generate(d))); }
+                format!("switch ({}) {{\n{}\n}}", self.generate(selector), arms.join("\n"))
+            },
+
+            // Imports are replaced by the imported items when modules are linked.
+            NodeKind::Import { .. } => String::new(),
+            NodeKind::BreakStmt => "break;".to_string(),
+            NodeKind::ContinueStmt => "continue;".to_string(),
+
+            NodeKind::VarDecl { binding, type_name, name, value, .. } => {
+                let qualifier = if *binding == BindingKind::Var { "" } else { "const " };
+                if let Some(v) = value { format!("{}{} = {};", qualifier, self.declare(type_name, name), self.generate(v)) }
+                else { format!("{}{};", qualifier, self.declare(type_name, name)) }
+            },
+
+            NodeKind::ArrayDecl { binding, type_name, name, size, value, .. } => {
+                let qualifier = if *binding == BindingKind::Var { "" } else { "const " };
+                let declaration = self.declare(&array_type(type_name, *size), name);
+                if let Some(v) = value { format!("{}{} = {};", qualifier, declaration, self.generate(v)) }
+                else { format!("{}{};", qualifier, declaration) }
+            },
+
+            NodeKind::Assignment { target, value } => format!("{} = {};", self.generate(target), self.generate(value)),
+            NodeKind::CompoundAssignment { target, op: BinaryOperator::MatrixMul, value } => {
+                let target = self.generate(target);
+                format!("{} = mul({}, {});", target, self.generate(value), target)
+            },
+            NodeKind::CompoundAssignment { target, op, value } => format!("{} {}= {};", self.generate(target), c_operator(op), self.generate(value)),
+
+            NodeKind::BinaryOp { left, op: BinaryOperator::MatrixMul, right } => format!("mul({}, {})", self.generate(right), self.generate(left)),
+            NodeKind::BinaryOp { left, op, right } => format!("({} {} {})", self.generate(left), c_operator(op), self.generate(right)),
+
+            NodeKind::UnaryOp { op, right } => {
+                let s = match op { UnaryOperator::Negate => "-", UnaryOperator::Not => "!", UnaryOperator::BitNot => "~" };
+                format!("({}{})", s, self.generate(right))
+            },
+
+            NodeKind::Ternary { condition, then_expr, else_expr, .. } => {
+                format!("({} ? {} : {})", self.generate(condition), self.generate(then_expr), self.generate(else_expr))
+            },
+
+            NodeKind::StructLiteral { name, fields } if fields.is_empty() => format!("(({})0)", name),
+            NodeKind::StructLiteral { name, fields } => {
+                format!("sumi_make_{}({})", name, fields.iter().map(|(_, v)| self.generate(v)).collect::<Vec<_>>().join(", "))
+            },
+            NodeKind::ArrayLiteral(items) => format!("{{ {} }}", items.iter().map(|i| self.generate(i)).collect::<Vec<_>>().join(", ")),
+
+            NodeKind::MethodCall { receiver, method, args, receiver_type } => {
+                let args = std::iter::once(receiver.as_ref()).chain(args).map(|a| self.generate(a)).collect::<Vec<_>>();
+                format!("{}({})", method_name(receiver_type, method), args.join(", "))
+            },
+            NodeKind::Call { func_name, args, overload: Some(symbol), .. } => {
+                let arg_strs = args.iter().map(|a| self.generate(a)).collect::<Vec<_>>();
+                format!("{}({})", native_function_name(func_name, Some(symbol)), arg_strs.join(", "))
+            },
+            NodeKind::Call { func_name, args, arg_types, .. } => {
+                let arg_strs = args.iter().map(|a| self.generate(a)).collect::<Vec<_>>();
+                match (Type::parse(func_name), arg_strs.as_slice()) {
+                    _ if needs_helper(Backend::Hlsl, func_name, arg_types) => helper_call(func_name, arg_types, args, |a| self.generate(a)),
+                    _ if builtins::is_builtin(func_name) => builtin_call(Backend::Hlsl, func_name, &arg_strs),
+                    // `float3(x)` is not a splat in HLSL; a cast is. Matrices from a
+                    // scalar or another size went to a helper above, so a cast only
+                    // splats or converts here.
+                    (Type::Vector(..) | Type::Matrix(_), [arg]) => format!("(({}){})", self.map_type(func_name), arg),
+                    _ => format!("{}({})", self.map_type(func_name), arg_strs.join(", ")),
+                }
+            },
+
+            NodeKind::MemberAccess { base, member } => format!("{}.{}", self.generate(base), member),
+            NodeKind::Swizzle { base, components } => format!("{}.{}", self.generate(base), Self::swizzle(components)),
+            NodeKind::SubscriptAccess { base, index } => format!("{}[{}]", self.generate(base), self.generate(index)),
+
+            NodeKind::LiteralFloat { text, .. } => text.clone(),
+            NodeKind::LiteralInt(i) => format!("{}", i),
+            NodeKind::LiteralUint(u) => format!("{}u", u),
+            NodeKind::LiteralBool(b) => format!("{}", b),
+            NodeKind::Variable(n) => n.clone(),
+        }
+    }
+}
+
+// --- Markdown Generator ---
+
+/// Renders the `///` docs of constants, structs and functions as a reference page.
 pub struct MarkdownGenerator;
-impl CodeGenerator for MarkdownGenerator { fn generate(&self, _: &AstNode) -> String { String::new() } }
+
+impl MarkdownGenerator {
+    fn doc(doc_string: &Option<String>) -> String {
+        doc_string.as_ref().map(|d| format!("\n{}\n", d)).unwrap_or_default()
+    }
+
+    fn function(title: &str, function: &AstNode) -> String {
+        let NodeKind::FunctionDecl { return_type, args, doc_string, .. } = &function.kind else { return String::new() };
+        let signature = args.iter().map(|a| match a.qualifier.is_reference() {
+            true => format!("{} {}: {}", a.qualifier.keyword(), a.name, a.type_name),
+            false => format!("{}: {}", a.name, a.type_name),
+        }).collect::<Vec<_>>().join(", ");
+        let mut section = format!("### `fn {}({}) {}`\n{}", title, signature, return_type, Self::doc(doc_string));
+        if args.iter().any(|a| a.doc_string.is_some()) {
+            let params = Self::members(args.iter().map(|a| (a.name.as_str(), a.type_name.as_str(), &a.doc_string)));
+            section.push_str(&format!("\n{}\n", params));
+        }
+        section
+    }
+
+    /// A bullet list of documented names, e.g. fields or parameters.
+    fn members<'a>(items: impl Iterator<Item = (&'a str, &'a str, &'a Option<String>)>) -> String {
+        items.map(|(name, type_name, doc)| match doc {
+            Some(d) => format!("- `{}: {}` — {}", name, type_name, d.replace('\n', " ")),
+            None => format!("- `{}: {}`", name, type_name),
+        }).collect::<Vec<_>>().join("\n")
+    }
+}
+
+impl CodeGenerator for MarkdownGenerator {
+    fn generate(&self, ast: &AstNode) -> String {
+        let NodeKind::Program(items) = &ast.kind else { return String::new() };
+        let mut sections = Vec::new();
+        for item in items {
+            match &item.kind {
+                NodeKind::VarDecl { name, type_name, doc_string, .. } => {
+                    sections.push(format!("### `const {}: {}`\n{}", name, type_name, Self::doc(doc_string)));
+                },
+                NodeKind::StructDecl { name, fields, doc_string, .. } => {
+                    let fields = Self::members(fields.iter().map(|f| (f.name.as_str(), f.type_name.as_str(), &f.doc_string)));
+                    sections.push(format!("### `struct {}`\n{}\n{}\n", name, Self::doc(doc_string), fields));
+                },
+                NodeKind::FunctionDecl { name, .. } => sections.push(Self::function(name, item)),
+                NodeKind::ImplBlock { type_name, methods } => {
+                    for method in methods {
+                        if let NodeKind::FunctionDecl { name, .. } = &method.kind {
+                            sections.push(Self::function(&format!("{}.{}", type_name, name), method));
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+        sections.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker;
+    use crate::lexer::tokenize;
+    use crate::parser::Parser;
+    use crate::source_map::SourceMap;
+
+    /// `src` through the real pipeline, which must accept it.
+    fn compile(src: &str, generator: &dyn CodeGenerator) -> String {
+        let (tokens, _) = tokenize(&SourceMap::single("t.sumi", src));
+        let ast = Parser::new(tokens).parse().unwrap();
+        let errors: Vec<_> = checker::check(&ast).into_iter().map(|e| e.message).collect();
+        assert!(errors.is_empty(), "{:?}", errors);
+        generator.generate(&ast)
+    }
+
+    #[test]
+    fn test_operators_wgsl() {
+        let out = compile("fn f(a: int, n: int) int { a <<= n; a %= 3; return ~a & (a >> 2); }", &WgslGenerator::new());
+        assert!(out.contains("a <<= u32(n);"));
+        assert!(out.contains("a %= 3;"));
+        assert!(out.contains("((~a) & (a >> 2))"));
+    }
+
+    #[test]
+    fn test_ternary_argument_order() {
+        let src = "fn f(a: float, b: float) float { return a < b ? a : b; }";
+        assert!(compile(src, &WgslGenerator::new()).contains("return select(b, a, (a < b));"));
+        assert!(compile(src, &MetalGenerator::new(false)).contains("return ((a < b) ? a : b);"));
+    }
+
+    /// Fails with naga's report unless `wgsl` parses and validates.
+    fn naga_validate(wgsl: &str) {
+        let module = naga::front::wgsl::parse_str(wgsl).unwrap_or_else(|e| panic!("{}", e.emit_to_string(wgsl)));
+        let mut validator = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all());
+        if let Err(e) = validator.validate(&module) {
+            panic!("{}", e.emit_to_string(wgsl));
+        }
+    }
+
+    #[test]
+    fn test_wgsl_chooses_structs_with_if() {
+        let src = "struct Hit { d: float, id: int }\n\
+                   fn closer(a: Hit, b: Hit) Hit { return a.d < b.d ? a : b; }\n\
+                   fn pick(c: bool, a: Hit, b: Hit) float { let h = c ? (a.d < 0.0 ? a : b) : closer(a, b); let m = c ? mat2(1.0) : mat2(2.0); return h.d + m[0].x; }";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.contains("{\nvar sumi_choice: Hit;\nif ((a.d < b.d)) {\n    sumi_choice = a;\n} else {\n    sumi_choice = b;\n}\nreturn sumi_choice;\n}"));
+        assert!(wgsl.contains("sumi_choice = sumi_choice_1;"));
+        assert!(wgsl.contains("let h: Hit = sumi_choice;"));
+        assert!(wgsl.contains("var sumi_choice_2: mat2x2<f32>;"));
+        naga_validate(&wgsl);
+
+        let (tokens, _) = tokenize(&SourceMap::single("t.sumi", "struct Hit { d: float }\nfn f(a: Hit, b: Hit) { while ((a.d < b.d ? a : b).d < 1.0) { break; } }"));
+        let errors = WgslGenerator::new().validate(&Parser::new(tokens).parse().unwrap());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "cannot choose a `Hit` with `?:` in a loop condition");
+    }
+
+    #[test]
+    fn test_loops() {
+        let src = "fn f() { var t = 0.0; do { t += 1.0; } while (t < 4.0); loop { break; } }";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.contains("loop {\n    t += 1.0;\n    continuing {\n        break if !((t < 4.0));\n    }\n}"));
+        assert!(wgsl.contains("loop {\n    break;\n}"));
+
+        let metal = compile(src, &MetalGenerator::new(false));
+        assert!(metal.contains("do {\n    t += 1.0;\n} while ((t < 4.0));"));
+        assert!(metal.contains("while (true) {\n    break;\n}"));
+    }
+
+    #[test]
+    fn test_switch() {
+        let src = "fn f(id: int) { var x: float; switch (id) { case 1, 2..4: x = 1.0; default: x = 0.0; } }";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.contains("switch (id) {\n    case 1, 2, 3: {\n    x = 1.0;\n}\n    default: {\n    x = 0.0;\n}\n}"));
+        let metal = compile(src, &MetalGenerator::new(false));
+        assert!(metal.contains("    case 1: case 2: case 3: {\n    x = 1.0;\n} break;"));
+        assert!(metal.contains("    default: {\n    x = 0.0;\n} break;"));
+    }
+
+    #[test]
+    fn test_wgsl_requires_switch_default() {
+        let (tokens, _) = tokenize(&SourceMap::single("t.sumi", "fn f(id: int) { switch (id) { case 1: break; } }"));
+        let ast = Parser::new(tokens).parse().unwrap();
+        let errors = WgslGenerator::new().validate(&ast);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "switch without a `default` arm");
+        assert!(MetalGenerator::new(false).validate(&ast).is_empty());
+    }
+
+    #[test]
+    fn test_bindings() {
+        let src = "const PI: float = 3.14159;\nfn f() float { let r: float = PI; const k: float = 2.0; return r * k; }";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.starts_with("const PI: f32 = 3.14159;"));
+        assert!(wgsl.contains("let r: f32 = PI;"));
+        assert!(wgsl.contains("const k: f32 = 2.0;"));
+
+        let metal = compile(src, &MetalGenerator::new(false));
+        assert!(metal.starts_with("constant float PI = 3.14159;"));
+        assert!(metal.contains("const float r = PI;"));
+        assert!(metal.contains("const float k = 2.0;"));
+    }
+
+    #[test]
+    fn test_literals() {
+        let src = "fn f() { var x: float; var y: uint; var z: int; var b: bool; x = 1e-3 + .5 + 2.0f + 3f; y = 0xFFu + 3u; z = 0x10; b = true; }";
+        for out in [compile(src, &WgslGenerator::new()), compile(src, &MetalGenerator::new(false))] {
+            assert!(out.contains("(((1e-3 + 0.5) + 2.0) + 3.0)"));
+            assert!(out.contains("y = (255u + 3u);"));
+            assert!(out.contains("z = 16;"));
+            assert!(out.contains("b = true;"));
+        }
+    }
+
+    #[test]
+    fn test_markdown_uses_member_docs() {
+        let src = "/// Surface properties.\n\
+                   struct Material {\n\
+                       /// Base color.\n\
+                       vec3 color;\n\
+                       float rough;\n\
+                   };\n\
+                   /// Circle constant.\n\
+                   const PI: float = 3.14159;\n\
+                   /* Signed distance to a sphere. */\n\
+                   fn sdSphere(\n\
+                       /// Sample point.\n\
+                       p: vec3,\n\
+                       r: float) float { return length(p) - r; }";
+        let out = compile(src, &MarkdownGenerator);
+        assert!(out.contains("### `struct Material`\n\nSurface properties.\n"));
+        assert!(out.contains("- `color: vec3` — Base color.\n- `rough: float`"));
+        assert!(out.contains("### `const PI: float`\n\nCircle constant.\n"));
+        assert!(out.contains("### `fn sdSphere(p: vec3, r: float) float`\n\n- `p: vec3` — Sample point.\n- `r: float`"));
+    }
+
+    #[test]
+    fn test_arrays() {
+        let src = "const PALETTE: array<vec3, 2> = [vec3(1.0), vec3(0.5)];\n\
+                   fn blur(w: array<float, 3>, i: int) array<float, 2> {\n\
+                       float k[] = {0.25, 0.5, 0.25};\n\
+                       let c: vec3 = PALETTE[i];\n\
+                       return [w[i] * k[i], c.x];\n\
+                   }";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.contains("var<private> PALETTE: array<vec3<f32>, 2> = array<vec3<f32>, 2>(vec3<f32>(1.0), vec3<f32>(0.5));"));
+        assert!(wgsl.contains("fn blur(w_in: array<f32, 3>, i: i32) -> array<f32, 2> {\n    var w: array<f32, 3> = w_in;\n"));
+        assert!(wgsl.contains("var k: array<f32, 3> = array<f32, 3>(0.25, 0.5, 0.25);"));
+        assert!(wgsl.contains("return array((w[i] * k[i]), c.x);"));
+
+        let metal = compile(src, &MetalGenerator::new(false));
+        assert!(metal.contains("constant array<vec3, 2> PALETTE = { vec3(1.0), vec3(0.5) };"));
+        assert!(metal.contains("array<float, 2> blur(array<float, 3> w, int i)"));
+        assert!(metal.contains("array<float, 3> k = { 0.25, 0.5, 0.25 };"));
+    }
+
+    #[test]
+    fn test_struct_constructors() {
+        let src = "struct Material { color: vec3, rough: float }\n\
+                   fn f() Material { return Material(rough: 0.2, color: vec3(1.0)); }\n\
+                   fn g() Material { return Material(); }";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.contains("struct Material {\n    color: vec3<f32>,\n    rough: f32,\n}"));
+        assert!(wgsl.contains("return Material(vec3<f32>(1.0), 0.2);"));
+        assert!(wgsl.contains("return Material();"));
+        let metal = compile(src, &MetalGenerator::new(false));
+        assert!(metal.contains("struct Material {\n    vec3 color;\n    float rough;\n};"));
+        assert!(metal.contains("return Material{ vec3(1.0), 0.2 };"));
+        assert!(metal.contains("return Material{};"));
+    }
+
+    #[test]
+    fn test_methods_lower_to_free_functions() {
+        let src = "struct Ray { o: vec3, d: vec3 }\n\
+                   impl Ray {\n\
+                       fn at(self, t: float) vec3 { return self.o + self.d * t; }\n\
+                       fn moved(self, t: float) Ray { return Ray(self.at(t), self.d); }\n\
+                   }\n\
+                   fn f(r: Ray) vec3 { return r.moved(1.0).at(2.0); }";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.contains("fn Ray_at(self_: Ray, t: f32) -> vec3<f32> {\n    return (self_.o + (self_.d * t));"));
+        assert!(wgsl.contains("return Ray(Ray_at(self_, t), self_.d);"));
+        assert!(wgsl.contains("return Ray_at(Ray_moved(r, 1.0), 2.0);"));
+        let metal = compile(src, &MetalGenerator::new(false));
+        assert!(metal.contains("vec3 Ray_at(Ray self, float t) {"));
+        assert!(metal.contains("return Ray_at(Ray_moved(r, 1.0), 2.0);"));
+    }
+
+    #[test]
+    fn test_overloads() {
+        let src = "fn mix(x: vec2, y: vec2, a: vec2) vec2 { return vec2(mix(x.x, y.x, a.x), mix(x.y, y.y, a.y)); }\n\
+                   fn sq(x: float) float { return x * x; }\n\
+                   fn sq(v: vec2) float { return dot(v, v); }\n\
+                   fn f(p: vec2) float { return sq(mix(p, p, p)) + sq(p.x) + mix(1.0, 2.0, 0.5); }";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.contains("fn mix_vec2_vec2_vec2(x: vec2<f32>, y: vec2<f32>, a: vec2<f32>) -> vec2<f32>"));
+        assert!(wgsl.contains("vec2<f32>(mix(x.x, y.x, a.x), mix(x.y, y.y, a.y))"));
+        assert!(wgsl.contains("fn sq_float(x: f32) -> f32"));
+        assert!(wgsl.contains("fn sq_vec2(v: vec2<f32>) -> f32"));
+        assert!(wgsl.contains("return ((sq_vec2(mix_vec2_vec2_vec2(p, p, p)) + sq_float(p.x)) + mix(1.0, 2.0, 0.5));"));
+
+        let metal = compile(src, &MetalGenerator::new(false));
+        assert!(metal.contains("vec2 mix_vec2_vec2_vec2(vec2 x, vec2 y, vec2 a)"));
+        assert!(metal.contains("float sq(float x)"));
+        assert!(metal.contains("float sq(vec2 v)"));
+        assert!(metal.contains("return ((sq(mix_vec2_vec2_vec2(p, p, p)) + sq(p.x)) + mix(1.0, 2.0, 0.5));"));
+    }
+
+    #[test]
+    fn test_glsl_mod_is_floored() {
+        let src = "fn g(v: vec3) vec3 { return v; }\nfn f(p: vec3, x: float) vec3 { return mod(g(p), mod(x, 2.0)) + mod(p, p); }";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.starts_with("fn sumi_mod_vec3_float(x: vec3<f32>, y: f32) -> vec3<f32> {\n    return x - y * floor(x / y);\n}\n\nfn sumi_mod_float_float(x: f32, y: f32) -> f32 {"));
+        assert!(wgsl.contains("return (sumi_mod_vec3_float(g(p), sumi_mod_float_float(x, 2.0)) + sumi_mod_vec3_vec3(p, p));"));
+        let metal = compile(src, &MetalGenerator::new(false));
+        assert!(metal.starts_with("vec3 sumi_mod_vec3_float(vec3 x, float y) {"));
+        assert!(metal.contains("sumi_mod_vec3_float(g(p), sumi_mod_float_float(x, 2.0))"));
+        assert!(compile(src, &HlslGenerator).contains("float3 sumi_mod_vec3_float(float3 x, float y) {"));
+        assert!(compile(src, &GlslGenerator::new(GlslProfile::Glsl450, false)).contains("(mod(g(p), mod(x, 2.0)) + mod(p, p))"));
+    }
+
+    #[test]
+    fn test_matrix_from_scalar_or_matrix() {
+        let src = "fn f(m: mat4) mat3 { return mat3(m) * mat3(2); }";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.contains("fn sumi_mat3_mat4(x: mat4x4<f32>) -> mat3x3<f32> {\n    return mat3x3<f32>(x[0].xyz, x[1].xyz, x[2].xyz);\n}"));
+        assert!(wgsl.contains("return mat3x3<f32>(vec3<f32>(x, 0.0, 0.0), vec3<f32>(0.0, x, 0.0), vec3<f32>(0.0, 0.0, x));"));
+        assert!(wgsl.contains("return (sumi_mat3_mat4(m) * sumi_mat3_float(2.0));"));
+        assert!(compile("fn f(m: mat2) mat3 { return mat3(m); }", &MetalGenerator::new(false))
+            .contains("return mat3(vec3(x[0], 0.0), vec3(x[1], 0.0), vec3(0.0, 0.0, 1.0));"));
+
+        assert!(compile(src, &GlslGenerator::new(GlslProfile::Glsl450, false)).ends_with("mat3 f(mat4 m) {\n    return (mat3(m) * mat3(2.0));\n}"));
+        let webgl = compile(src, &GlslGenerator::new(GlslProfile::Es100, false));
+        assert!(webgl.contains("return (sumi_mat3_mat4(m) * mat3(2.0));"));
+    }
+
+    #[test]
+    fn test_glsl_profiles() {
+        let src = "fn mainImage(fragCoord: vec2) vec4 { var x: i32 = 1; let w: array<float, 2> = [1.0, opUnion(0.5, iTime)]; return vec4(w[x]); }";
+        let desktop = compile(src, &GlslGenerator::new(GlslProfile::Glsl450, false));
+        assert!(desktop.starts_with("#version 450\n\nuniform float iTime;"));
+        assert!(desktop.contains("float opUnion(float d1, float d2)"));
+        assert!(desktop.contains("    int x = 1;\n    float w[2] = float[2](1.0, opUnion(0.5, iTime));"));
+        assert!(desktop.contains("void mainImage(out vec4 fragColor, in vec2 fragCoord) {\n    fragColor = mainImage(fragCoord);\n}"));
+        assert!(desktop.ends_with("void main() {\n    mainImage(sumi_FragColor, gl_FragCoord.xy);\n}"));
+
+        let es = compile(src, &GlslGenerator::new(GlslProfile::Es300, false));
+        assert!(es.starts_with("#version 300 es\nprecision highp float;\nprecision highp int;\n"));
+        let webgl = compile(src, &GlslGenerator::new(GlslProfile::Es100, false));
+        assert!(webgl.ends_with("mainImage(gl_FragColor, gl_FragCoord.xy);\n}"));
+
+        let shadertoy = compile(src, &GlslGenerator::new(GlslProfile::Es300, true));
+        assert!(shadertoy.starts_with("// --- SUMI HOST LIBRARY (GLSL) ---"));
+        assert!(!shadertoy.contains("uniform") && !shadertoy.contains("void main()"));
+    }
+
+    #[test]
+    fn test_glsl_es_float_literals() {
+        // GLSL ES has no implicit int to float conversion.
+        let src = "fn g(a: float) float { return a; }\n\
+                   fn f(x: float, v: vec3) vec3 { let y: float = 1; var w = v * 2; w += 1; return w * (x * 2 + 3 + g(1) + y) - -1; }";
+        let es = compile(src, &GlslGenerator::new(GlslProfile::Es300, false));
+        assert!(es.contains("    float y = 1.0;\n    vec3 w = (v * 2.0);\n    w += 1.0;\n"), "{}", es);
+        assert!(es.contains("return ((w * ((((x * 2.0) + 3.0) + g(1.0)) + y)) - (-1.0));"));
+        assert!(compile(src, &GlslGenerator::new(GlslProfile::Es100, false)).contains("return ((w * ((((x * 2.0) + 3.0) + g(1.0)) + y)) - (-1.0));"));
+    }
+
+    #[test]
+    fn test_glsl_es100_limits() {
+        let (tokens, _) = tokenize(&SourceMap::single("t.sumi", "fn f(n: int) float { var t = 0.0; while (t < 1.0) { t += 0.5; } return t + float(n % 2) + g([t]); }"));
+        let ast = Parser::new(tokens).parse().unwrap();
+        let messages = |profile| GlslGenerator::new(profile, false).validate(&ast).into_iter().map(|e| e.message).collect::<Vec<_>>();
+        assert_eq!(messages(GlslProfile::Es300), ["array literal outside a declaration"]);
+        assert_eq!(messages(GlslProfile::Es100), [
+            "array literal outside a declaration",
+            "GLSL ES 1.00 has no loops other than `for`",
+            "GLSL ES 1.00 has no `%` operator",
+        ]);
+
+        let src = "fn sum(w: array<float, 2>) float { return w[0] + w[1]; }\nfn pair() array<float, 2> { var k: array<float, 2>; return k; }";
+        let (tokens, _) = tokenize(&SourceMap::single("t.sumi", src));
+        let ast = Parser::new(tokens).parse().unwrap();
+        let messages = |profile| GlslGenerator::new(profile, false).validate(&ast).into_iter().map(|e| e.message).collect::<Vec<_>>();
+        assert!(messages(GlslProfile::Es300).is_empty());
+        assert_eq!(messages(GlslProfile::Es100), ["GLSL ES 1.00 has no array parameters", "GLSL ES 1.00 has no array return types"]);
+
+        // Array sizes go after the name, the only way GLSL ES 1.00 has.
+        let webgl = compile("fn f() float { var k: array<float, 3>; k[0] = 1.0; return k[0]; }", &GlslGenerator::new(GlslProfile::Es100, false));
+        assert!(webgl.contains("float f() {\n    float k[3];\n    k[0] = 1.0;"), "{}", webgl);
+    }
+
+    #[test]
+    fn test_hlsl() {
+        let src = "struct Light { dir: vec3, color: vec3 }\n\
+                   fn shade(l: Light, m: mat3, n: vec3) vec3 { let d = m * l.dir; return mix(l.color, vec3(fract(iTime)), max(dot(n, d), 0.0)); }\n\
+                   fn mainImage(fragCoord: vec2) vec4 { let l = Light(dir: vec3(0.0, 1.0, 0.0), color: vec3(1.0)); return vec4(shade(l, mat3(1.0), vec3(0.0, 0.0, 1.0)), 1.0); }";
+        let hlsl = compile(src, &HlslGenerator);
+        assert!(hlsl.starts_with("cbuffer Uniforms : register(b0) {\n    float iTime;\n    float3 iResolution;"));
+        assert!(hlsl.contains("float3 shade(Light l, float3x3 m, float3 n) {"));
+        assert!(hlsl.contains("const float3 d = mul(l.dir, m);"));
+        assert!(hlsl.contains("return lerp(l.color, ((float3)frac(iTime)), max(dot(n, d), 0.0));"));
+        assert!(hlsl.contains("const Light l = sumi_make_Light(float3(0.0, 1.0, 0.0), ((float3)1.0));"));
+        assert!(hlsl.contains("float3x3 sumi_mat3_float(float x) {\n    return float3x3(float3(x, 0.0, 0.0), float3(0.0, x, 0.0), float3(0.0, 0.0, x));\n}"));
+        assert!(hlsl.contains("shade(l, sumi_mat3_float(1.0), float3(0.0, 0.0, 1.0))"));
+        assert!(hlsl.ends_with("float4 ps_main(float4 position : SV_Position) : SV_Target {\n    return mainImage(float2(position.x, iResolution.y - position.y));\n}"));
+    }
+
+    #[test]
+    fn test_builtin_spellings() {
+        let src = "fn f(p: vec2) bool { let a = atan(p.y, p.x) * inversesqrt(p.x); let b = lessThan(ivec2(p), ivec2(1, 2)); return any(not(b)); }";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.contains("let a: f32 = (atan2(p.y, p.x) * inverseSqrt(p.x));"));
+        assert!(wgsl.contains("let b: vec2<bool> = (vec2<i32>(p) < vec2<i32>(1, 2));"));
+        assert!(wgsl.contains("return any((!b));"));
+
+        let metal = compile(src, &MetalGenerator::new(false));
+        assert!(metal.contains("const float a = (atan2(p.y, p.x) * rsqrt(p.x));"));
+        assert!(metal.contains("return any((!b));"));
+
+        let src = "fn f(p: vec2) float { return fwidth(p.x) + dFdx(p.y); }";
+        assert!(compile(src, &WgslGenerator::new()).contains("return (fwidth(p.x) + dpdx(p.y));"));
+        assert!(compile(src, &MetalGenerator::new(false)).contains("return (fwidth(p.x) + dfdx(p.y));"));
+        assert!(compile(src, &HlslGenerator).contains("return (fwidth(p.x) + ddx(p.y));"));
+
+        let (tokens, _) = tokenize(&SourceMap::single("t.sumi", "fn f(m: mat3) mat3 { return inverse(m); }"));
+        let ast = Parser::new(tokens).parse().unwrap();
+        assert_eq!(WgslGenerator::new().validate(&ast).into_iter().map(|e| e.message).collect::<Vec<_>>(), ["WGSL has no `inverse`"]);
+        assert!(GlslGenerator::new(GlslProfile::Glsl450, false).validate(&ast).is_empty());
+    }
+
+    #[test]
+    fn test_inferred_types_are_written_out() {
+        let src = "fn f(x: float) vec3 {\n    let col = vec3(1.0)\n    var t = 0.0\n    var i = 0\n    let w = [x, 2]\n    t += w[i]\n    return col * t\n}";
+        let wgsl = compile(src, &WgslGenerator::new());
+        for line in ["let col: vec3<f32> = vec3<f32>(1.0);", "var t: f32 = 0.0;", "var i: i32 = 0;", "var w: array<f32, 2> = array<f32, 2>(x, 2.0);"] {
+            assert!(wgsl.contains(line), "{}", wgsl);
+        }
+        let metal = compile(src, &MetalGenerator::new(false));
+        assert!(metal.contains("const vec3 col = vec3(1.0);"));
+        assert!(metal.contains("const array<float, 2> w = { x, 2.0 };"));
+    }
+
+    #[test]
+    fn test_implicit_conversions() {
+        let src = "fn f(x: float, i: int, v: vec3) vec3 {\n\
+                       let g = vec2(i, 1)\n\
+                       return clamp(v, 0.0, 1.0) * (x + i) + mix(v, v, 0.5) + pow(v, 2.2) * g.x\n\
+                   }";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.contains("let g: vec2<f32> = vec2<f32>(f32(i), 1.0);"), "{}", wgsl);
+        assert!(wgsl.contains("clamp(v, vec3<f32>(0.0), vec3<f32>(1.0)) * (x + f32(i))"), "{}", wgsl);
+        assert!(wgsl.contains("mix(v, v, 0.5)"));
+        assert!(wgsl.contains("pow(v, vec3<f32>(2.2))"));
+        assert!(compile(src, &MetalGenerator::new(false)).contains("clamp(v, vec3(0.0), vec3(1.0)) * (x + float(i))"));
+    }
+
+    #[test]
+    fn test_swizzle_stores() {
+        let src = "fn f(v: vec2) vec3 {\n    var p = vec3(1.0)\n    p.xz = v\n    p.zy = p.yz\n    p.xy *= 2.0\n    p.x = v.y\n    return p\n}";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.contains("p.x = v.x; p.z = v.y;"), "{}", wgsl);
+        assert!(wgsl.contains("{ let sumi_value = p.yz; p.z = sumi_value.x; p.y = sumi_value.y; }"));
+        assert!(wgsl.contains("{ let sumi_value = p.xy * 2.0; p.x = sumi_value.x; p.y = sumi_value.y; }"));
+        assert!(wgsl.contains("p.x = v.y;"));
+        let metal = compile(src, &MetalGenerator::new(false));
+        assert!(metal.contains("p.xz = v;") && metal.contains("p.xy *= 2.0;"));
+
+        let src = "fn g(i: int) int { return i; }\nfn f(v: vec2) {\n    var swizzled: array<vec3, 2>\n    swizzled[g(1)].xy += v\n}";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.contains("{ let sumi_target = &swizzled[g(1)]; let sumi_value = (*sumi_target).xy + v; (*sumi_target).x = sumi_value.x; (*sumi_target).y = sumi_value.y; }"), "{}", wgsl);
+    }
+
+    #[test]
+    fn test_out_and_inout_params() {
+        let src = "fn split(x: float, out whole: float, inout v: vec3) {\n    whole = floor(x)\n    v.x += whole\n}\n\
+                   fn f(p: vec3) vec3 {\n    p *= 2.0\n    var w = 0.0\n    split(p.x, w, p)\n    return p\n}";
+        let wgsl = compile(src, &WgslGenerator::new());
+        assert!(wgsl.contains("fn split(x: f32, whole: ptr<function, f32>, v: ptr<function, vec3<f32>>)"), "{}", wgsl);
+        assert!(wgsl.contains("(*whole) = floor(x);") && wgsl.contains("(*v).x += (*whole);"));
+        // By-value parameters are immutable in WGSL, so one that is written gets a copy.
+        assert!(wgsl.contains("fn f(p_in: vec3<f32>) -> vec3<f32> {\n    var p: vec3<f32> = p_in;"));
+        assert!(wgsl.contains("split(p.x, &w, &p);"));
+        let metal = compile(src, &MetalGenerator::new(false));
+        assert!(metal.contains("void split(float x, thread float& whole, thread vec3& v)"), "{}", metal);
+        assert!(metal.contains("split(p.x, w, p);"));
+
+        let (tokens, _) = tokenize(&SourceMap::single("t.sumi", "fn g(out v: vec2) { v = vec2(1.0) }\nfn f() { var p = vec3(0.0)\n    g(p.xy)\n}"));
+        let ast = Parser::new(tokens).parse().unwrap();
+        let errors = WgslGenerator::new().validate(&ast);
+        assert_eq!(errors.iter().map(|e| e.message.as_str()).collect::<Vec<_>>(), vec!["cannot pass `.xy` to an `out` or `inout` parameter"]);
+    }
+}
+
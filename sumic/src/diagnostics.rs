@@ -0,0 +1,138 @@
+use std::fmt;
+use crate::source_map::{FileId, SourceMap};
+
+/// A region of an original (pre-include) source file.
+///
+/// `start`/`end` are byte offsets into that file, while
+/// `line`/`column` (1-based) point at the first character for humans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub file: FileId,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(file: FileId, start: usize, end: usize, line: usize, column: usize) -> Self {
+        Self { file, start, end, line, column }
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    /// Spans from different files (across an `#include`) keep `self`.
+    pub fn to(self, other: Span) -> Span {
+        if other.file != self.file { return self; }
+        let first = if other.start < self.start { other } else { self };
+        Span { end: self.end.max(other.end), ..first }
+    }
+
+    /// A zero-width span just past the end of a single-line span
+    /// (used for "missing `;`" carets).
+    pub fn after(self) -> Span {
+        Span { start: self.end, column: self.column + (self.end - self.start), ..self }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A compiler message pointing into the source, rendered rustc-style.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    pub label: Option<String>,
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self { severity: Severity::Error, message: message.into(), span, label: None, hint: None }
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self { severity: Severity::Warning, message: message.into(), span, label: None, hint: None }
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// Renders the message with the offending line and a caret underline:
+    ///
+    /// ```text
+    /// error: expected `;`, found `return`
+    ///  --> shader.sumi:3:18
+    ///   |
+    /// 3 |     float x = 1.0
+    ///   |                  ^ expected `;`
+    ///   |
+    ///   = help: end the statement with `;`
+    /// ```
+    pub fn render(&self, sources: &SourceMap) -> String {
+        let source = sources.file(self.span.file);
+        let line_no = self.span.line.to_string();
+        let gutter = " ".repeat(line_no.len());
+        let text = source.line_text(self.span.line);
+
+        let col = self.span.column.saturating_sub(1);
+        let remaining = text.chars().count().saturating_sub(col);
+        let width = source.text.get(self.span.start..self.span.end)
+            .map(|s| s.split('\n').next().unwrap_or("").chars().count())
+            .unwrap_or(0)
+            .min(remaining)
+            .max(1);
+        let indent: String = text.chars().take(col).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+
+        let mut out = format!("{}\n", self);
+        out.push_str(&format!("{}--> {}:{}:{}\n", gutter, source.name, self.span.line, self.span.column));
+        out.push_str(&format!("{} |\n", gutter));
+        out.push_str(&format!("{} | {}\n", line_no, text));
+        out.push_str(&format!("{} | {}{}", gutter, indent, "^".repeat(width)));
+        if let Some(label) = &self.label { out.push_str(&format!(" {}", label)); }
+        out.push('\n');
+        if let Some(hint) = &self.hint {
+            out.push_str(&format!("{} |\n{} = help: {}\n", gutter, gutter, hint));
+        }
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.severity { Severity::Error => "error", Severity::Warning => "warning" };
+        write!(f, "{}: {}", level, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_caret() {
+        let src = SourceMap::single("t.sumi", "fn a() {\n    return 1.0\n}");
+        let diag = Diagnostic::error("expected `;`, found `}`", src.span(23, 23))
+            .with_label("expected `;`")
+            .with_hint("end the statement with `;`");
+        let out = diag.render(&src);
+        assert!(out.starts_with("error: expected `;`, found `}`\n"));
+        assert!(out.contains(" --> t.sumi:2:15\n"));
+        assert!(out.contains("2 |     return 1.0\n"));
+        assert!(out.contains("  |               ^ expected `;`\n"));
+        assert!(out.contains("= help: end the statement with `;`"));
+    }
+}
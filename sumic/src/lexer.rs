@@ -1,4 +1,7 @@
+use std::fmt;
 use logos::Logos;
+use crate::diagnostics::{Diagnostic, Span};
+use crate::source_map::SourceMap;
 
 #[derive(Logos, Debug, PartialEq, Clone)]
 #[logos(skip r"[ \t\n\f]+")] // Skip whitespace
@@ -6,17 +9,34 @@ pub enum Token {
     // --- Keywords ---
     #[token("struct")]
     Struct,
+    #[token("impl")]
+    Impl,
     #[token("fn")]     // Future-proofing for S2L, though Swift used implicit func
     Fn, 
     #[token("return")]
     Return,
+    #[token("let")]
+    Let,
+    #[token("const")]
+    Const,
+    #[token("import")]
+    Import,
+    #[token("pub")]
+    Pub,
     #[token("if")]
     If,
     #[token("else")]
     Else,
 
     #[token("for")] For,
+    #[token("while")] While,
+    #[token("do")] Do,
+    #[token("loop")] Loop,
+    #[token("switch")] Switch,
+    #[token("case")] Case,
+    #[token("default")] Default,
     #[token("break")] Break,
+    #[token("continue")] Continue,
     #[token("!")] Bang, 
 
     // --- Symbols ---
@@ -28,8 +48,11 @@ pub enum Token {
     #[token("]")] RBracket,
     #[token(";")] Semicolon,
     #[token(":")] Colon,
+    #[token("?")] Question,
     #[token(",")] Comma,
     #[token(".")] Dot,
+    #[token("..")] DotDot,
+    #[token("..=")] DotDotEquals,
     #[token("=")] Equals,
 
     // --- Operators ---
@@ -42,6 +65,28 @@ pub enum Token {
     #[token("==")] DoubleEquals,
     #[token("<=")] LessEqual,   
     #[token(">=")] GreaterEqual,
+    #[token("!=")] BangEquals,
+    #[token("%")] Percent,
+    #[token("&&")] AndAnd,
+    #[token("||")] OrOr,
+    #[token("&")] Ampersand,
+    #[token("|")] Pipe,
+    #[token("^")] Caret,
+    #[token("~")] Tilde,
+    #[token("<<")] ShiftLeft,
+    #[token(">>")] ShiftRight,
+
+    // --- Compound Assignment ---
+    #[token("+=")] PlusEquals,
+    #[token("-=")] MinusEquals,
+    #[token("*=")] StarEquals,
+    #[token("/=")] SlashEquals,
+    #[token("%=")] PercentEquals,
+    #[token("&=")] AmpersandEquals,
+    #[token("|=")] PipeEquals,
+    #[token("^=")] CaretEquals,
+    #[token("<<=")] ShiftLeftEquals,
+    #[token(">>=")] ShiftRightEquals,
 
     // --- Literals & Identifiers ---
     
@@ -49,10 +94,16 @@ pub enum Token {
     #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice().to_string())]
     Identifier(String),
 
-    // Matches numbers (integers or floats)
-    #[regex(r"[0-9]+(\.[0-9]+)?", |lex| lex.slice().to_string())]
+    // Matches numbers as written: `1`, `3u`, `0xFF`, `1.5`, `1.`, `.5`, `1e-3`, `1.0f`.
+    // Typing and validation of the text happen in the parser.
+    #[regex(r"[0-9]+(\.[0-9]+)?([eE][+-]?[0-9]+)?[fFuU]?", number)]
+    #[regex(r"\.[0-9]+([eE][+-]?[0-9]+)?[fF]?", |lex| lex.slice().to_string())]
+    #[regex(r"0[xX][0-9a-fA-F]+[uU]?", |lex| lex.slice().to_string())]
     Number(String),
 
+    #[token("true")] True,
+    #[token("false")] False,
+
     // --- Comments ---
 
     // Doc Comments (///): We want to keep these for the AST
@@ -63,6 +114,160 @@ pub enum Token {
     #[regex(r"//[^/].*", logos::skip)] // Matches // followed by not /, then anything
     #[regex(r"//", logos::skip)]       // Matches empty //
     Comment,
+
+    // Block comments (/* ... */), which may nest; skipped like `//`
+    #[token("/*", block_comment)]
+    BlockComment,
+}
+
+/// Skips the rest of a (possibly nested) block comment after its opening `/*`.
+/// An unterminated comment swallows the rest of the input and is reported as an error.
+fn block_comment(lex: &mut logos::Lexer<Token>) -> logos::FilterResult<(), ()> {
+    let rest = lex.remainder();
+    let mut depth = 1;
+    let mut i = 0;
+    while i < rest.len() {
+        if rest[i..].starts_with("/*") {
+            depth += 1;
+            i += 2;
+        } else if rest[i..].starts_with("*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                lex.bump(i);
+                return logos::FilterResult::Skip;
+            }
+        } else {
+            i += rest[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    lex.bump(rest.len());
+    logos::FilterResult::Error(())
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Token::Struct => "`struct`",
+            Token::Impl => "`impl`",
+            Token::Fn => "`fn`",
+            Token::Return => "`return`",
+            Token::Let => "`let`",
+            Token::Const => "`const`",
+            Token::Import => "`import`",
+            Token::Pub => "`pub`",
+            Token::If => "`if`",
+            Token::Else => "`else`",
+            Token::For => "`for`",
+            Token::While => "`while`",
+            Token::Do => "`do`",
+            Token::Loop => "`loop`",
+            Token::Switch => "`switch`",
+            Token::Case => "`case`",
+            Token::Default => "`default`",
+            Token::Break => "`break`",
+            Token::Continue => "`continue`",
+            Token::Bang => "`!`",
+            Token::LParen => "`(`",
+            Token::RParen => "`)`",
+            Token::LBrace => "`{`",
+            Token::RBrace => "`}`",
+            Token::LBracket => "`[`",
+            Token::RBracket => "`]`",
+            Token::Semicolon => "`;`",
+            Token::Colon => "`:`",
+            Token::Question => "`?`",
+            Token::Comma => "`,`",
+            Token::Dot => "`.`",
+            Token::DotDot => "`..`",
+            Token::DotDotEquals => "`..=`",
+            Token::Equals => "`=`",
+            Token::Plus => "`+`",
+            Token::Minus => "`-`",
+            Token::Star => "`*`",
+            Token::Slash => "`/`",
+            Token::Greater => "`>`",
+            Token::Less => "`<`",
+            Token::DoubleEquals => "`==`",
+            Token::LessEqual => "`<=`",
+            Token::GreaterEqual => "`>=`",
+            Token::BangEquals => "`!=`",
+            Token::Percent => "`%`",
+            Token::AndAnd => "`&&`",
+            Token::OrOr => "`||`",
+            Token::Ampersand => "`&`",
+            Token::Pipe => "`|`",
+            Token::Caret => "`^`",
+            Token::Tilde => "`~`",
+            Token::ShiftLeft => "`<<`",
+            Token::ShiftRight => "`>>`",
+            Token::PlusEquals => "`+=`",
+            Token::MinusEquals => "`-=`",
+            Token::StarEquals => "`*=`",
+            Token::SlashEquals => "`/=`",
+            Token::PercentEquals => "`%=`",
+            Token::AmpersandEquals => "`&=`",
+            Token::PipeEquals => "`|=`",
+            Token::CaretEquals => "`^=`",
+            Token::ShiftLeftEquals => "`<<=`",
+            Token::ShiftRightEquals => "`>>=`",
+            Token::Identifier(name) => return write!(f, "identifier `{}`", name),
+            Token::Number(n) => return write!(f, "number `{}`", n),
+            Token::True => "`true`",
+            Token::False => "`false`",
+            Token::DocComment(_) => "doc comment",
+            Token::BlockComment => "block comment",
+            Token::Comment => "comment",
+        };
+        f.write_str(s)
+    }
+}
+
+/// The text of a number. Plain digits take a trailing `.` (`1.`), unless it
+/// starts a range (`1..4`) or a swizzle (`1.xx`).
+fn number(lex: &mut logos::Lexer<Token>) -> String {
+    let mut rest = lex.remainder().chars();
+    let digits = lex.slice().bytes().all(|b| b.is_ascii_digit());
+    if digits && rest.next() == Some('.') && !rest.next().is_some_and(|c| c == '.' || c == '_' || c.is_ascii_alphanumeric()) {
+        lex.bump(1);
+    }
+    lex.slice().to_string()
+}
+
+/// A token together with where it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+/// Lexes the preprocessed program, keeping spans (resolved through the source map
+/// to the original files) and collecting, rather than dropping, invalid characters
+/// as diagnostics.
+pub fn tokenize(sources: &SourceMap) -> (Vec<SpannedToken>, Vec<Diagnostic>) {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    for (result, range) in Token::lexer(sources.text()).spanned() {
+        let span = sources.span(range.start, range.end);
+        match result {
+            Ok(token) => tokens.push(SpannedToken { token, span }),
+            Err(_) if sources.text()[range.clone()].starts_with("/*") => {
+                errors.push(Diagnostic::error("unterminated block comment", sources.span(range.start, range.start + 2))
+                    .with_label("comment starts here")
+                    .with_hint("close it with `*/`; block comments nest, so each `/*` needs its own `*/`"));
+            }
+            Err(_) => {
+                let text = &sources.text()[range];
+                let mut diag = Diagnostic::error(format!("unexpected character `{}`", text), span)
+                    .with_label("not valid in S2L");
+                if text.starts_with('#') {
+                    diag = diag.with_hint("preprocessor directives like `#define` must start their own line");
+                }
+                errors.push(diag);
+            }
+        }
+    }
+    (tokens, errors)
 }
 
 // Helper for tests to see what the lexer produced
@@ -92,4 +297,63 @@ mod tests {
         }
         assert_eq!(tokens[1], Ok(Token::Struct)); // The // comment was skipped
     }
+
+    #[test]
+    fn test_operator_lexing() {
+        let input = "a != b && c || d % e << 2 >>= ~f";
+        let tokens: Vec<_> = Token::lexer(input).filter_map(Result::ok)
+            .filter(|t| !matches!(t, Token::Identifier(_) | Token::Number(_)))
+            .collect();
+        assert_eq!(tokens, vec![
+            Token::BangEquals, Token::AndAnd, Token::OrOr, Token::Percent,
+            Token::ShiftLeft, Token::ShiftRightEquals, Token::Tilde,
+        ]);
+    }
+
+    #[test]
+    fn test_nested_block_comments() {
+        let input = "a /* one /* two */ still comment */ b /**/ c";
+        let tokens: Vec<_> = Token::lexer(input).filter_map(Result::ok).collect();
+        let ident = |s: &str| Token::Identifier(s.to_string());
+        assert_eq!(tokens, vec![ident("a"), ident("b"), ident("c")]);
+
+        let (tokens, errors) = tokenize(&SourceMap::single("t.sumi", "x /* /* */ y"));
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(errors[0].message, "unterminated block comment");
+        assert_eq!((errors[0].span.start, errors[0].span.end), (2, 4));
+    }
+
+    #[test]
+    fn test_number_forms() {
+        let input = "1 3u 0xFF 1.5 .5 1e-3 2.5E+4f 1.0f 1..4";
+        let tokens: Vec<_> = Token::lexer(input).filter_map(Result::ok).collect();
+        let number = |s: &str| Token::Number(s.to_string());
+        assert_eq!(tokens, vec![
+            number("1"), number("3u"), number("0xFF"), number("1.5"), number(".5"),
+            number("1e-3"), number("2.5E+4f"), number("1.0f"),
+            number("1"), Token::DotDot, number("4"),
+        ]);
+
+        let tokens: Vec<_> = Token::lexer("2.*x 1.) 0..=3 1.xy 3.").filter_map(Result::ok).collect();
+        let ident = |s: &str| Token::Identifier(s.to_string());
+        assert_eq!(tokens, vec![
+            number("2."), Token::Star, ident("x"), number("1."), Token::RParen,
+            number("0"), Token::DotDotEquals, number("3"),
+            number("1"), Token::Dot, ident("xy"), number("3."),
+        ]);
+    }
+
+    #[test]
+    fn test_tokenize_spans_and_errors() {
+        let source = SourceMap::single("t.sumi", "float x;\n  y @ 2");
+        let (tokens, errors) = tokenize(&source);
+
+        assert_eq!(tokens[1].token, Token::Identifier("x".to_string()));
+        assert_eq!(tokens[1].span, Span::new(0, 6, 7, 1, 7));
+        assert_eq!(tokens[3].span, Span::new(0, 11, 12, 2, 3));
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "unexpected character `@`");
+        assert_eq!((errors[0].span.line, errors[0].span.column), (2, 5));
+    }
 }
@@ -1,10 +1,19 @@
 pub mod ast;
+pub mod builtins;
+pub mod checker;
+pub mod diagnostics;
 pub mod lexer;
+pub mod modules;
 pub mod parser;
 pub mod codegen; 
 pub mod preprocessor;
+pub mod resolve;
+pub mod source_map;
+pub mod types;
 
-pub use ast::AstNode;
+pub use ast::{AstNode, NodeKind};
+pub use diagnostics::{Diagnostic, Span};
+pub use source_map::{SourceFile, SourceMap};
 pub use lexer::Token;
 pub use parser::Parser;
 pub use codegen::{MetalGenerator, MarkdownGenerator, CodeGenerator};
@@ -2,12 +2,13 @@ use clap::{Parser as ClapParser, ValueEnum};
 use std::path::PathBuf;
 use std::fs;
 use anyhow::{Context, Result};
-use logos::Logos;
 
-use sumic::lexer::Token;
-use sumic::parser::Parser;
-use sumic::codegen::{MetalGenerator, WgslGenerator, MarkdownGenerator, CodeGenerator};
-use sumic::preprocessor::Preprocessor; // Ensure this is imported
+use sumic::checker;
+use sumic::diagnostics::Diagnostic;
+use sumic::source_map::SourceMap;
+use sumic::modules::ModuleLoader;
+use sumic::preprocessor::Preprocessor;
+use sumic::codegen::{MetalGenerator, WgslGenerator, GlslGenerator, GlslProfile, HlslGenerator, MarkdownGenerator, CodeGenerator};
 
 #[derive(ClapParser, Debug)]
 #[command(author, version, about)]
@@ -20,12 +21,34 @@ struct Args {
 
     #[arg(short, long, value_enum, default_value_t = Target::Wgsl)]
     format: Target,
+
+    /// GLSL dialect for `--format glsl`: `450`, `300es` or `100`
+    #[arg(long = "glsl-profile", value_name = "PROFILE", default_value = "450")]
+    glsl_profile: GlslProfile,
+
+    /// With `--format glsl`, only the code to paste into shadertoy.com
+    #[arg(long)]
+    shadertoy: bool,
+
+    /// Directory to look for imported modules in, after the importing file's own (repeatable)
+    #[arg(short = 'M', long = "module-path", value_name = "DIR")]
+    module_path: Vec<PathBuf>,
+
+    /// Directory to look for `#include`s in, after the including file's own (repeatable)
+    #[arg(short = 'I', long = "include-dir", value_name = "DIR")]
+    include_dir: Vec<PathBuf>,
+
+    /// Defines a macro, as `#define NAME VALUE` would (repeatable; VALUE defaults to 1)
+    #[arg(short = 'D', long = "define", value_name = "NAME[=VALUE]")]
+    define: Vec<String>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 enum Target {
     Metal,
     Wgsl,
+    Glsl,
+    Hlsl,
     Markdown,
 }
 
@@ -33,50 +56,56 @@ fn main() -> Result<()> {
     let args = Args::parse();
     println!("--- SumiC Compiler ---");
 
-    // 1. Preprocess (Resolve Includes)
-    println!("🔍 Preprocessing...");
+    // 1-3. Preprocess, lex and parse the file and every module it imports,
+    // then link them into one program. Spans are resolved through the source
+    // map, so errors name the original file.
     let mut preprocessor = Preprocessor::new();
-    
-    // FIX: We capture the output here...
-    let preprocessed_source = preprocessor.process(&args.input)
+    args.include_dir.iter().for_each(|dir| preprocessor.include_dir(dir));
+    for definition in &args.define {
+        preprocessor.define(definition)?;
+    }
+    println!("🏗️ Parsing...");
+    let linked = ModuleLoader::new(args.module_path, preprocessor).load(&args.input)
         .with_context(|| format!("Failed to preprocess {:?}", args.input))?;
-
-    // 2. Lex
-    // ...and we MUST pass that specific variable to the lexer!
-    let lexer = Token::lexer(&preprocessed_source);
-    
-    let mut tokens = Vec::new();
-    for (result, span) in lexer.spanned() {
-        match result {
-            Ok(token) => tokens.push(token),
-            Err(_) => {
-                // Optional: You could print the span to debug specific invalid tokens
-                // eprintln!("Lexer Error at {:?}", span);
-            }
-        }
+    let (source, ast) = (linked.sources, linked.ast);
+    if !linked.errors.is_empty() {
+        return Err(report(&source, &linked.errors));
     }
 
-    // 3. Parse
-    println!("🏗️ Parsing...");
-    let mut parser = Parser::new(tokens);
-    let ast = parser.parse()
-        .map_err(|e| anyhow::anyhow!("Parser Error: {}", e))?;
+    // 3b. Check
+    let check_errors = checker::check(&ast);
+    if !check_errors.is_empty() {
+        return Err(report(&source, &check_errors));
+    }
 
     // 4. Generate
-    let code = match args.format {
+    let generator: Box<dyn CodeGenerator> = match args.format {
         Target::Metal => {
             println!("⚙️ Generating Metal...");
-            MetalGenerator::new(false).generate(&ast)
+            Box::new(MetalGenerator::new(false))
         },
         Target::Wgsl => {
             println!("⚙️ Generating WGSL...");
-            WgslGenerator::new().generate(&ast)
+            Box::new(WgslGenerator::new())
+        },
+        Target::Glsl => {
+            println!("⚙️ Generating GLSL...");
+            Box::new(GlslGenerator::new(args.glsl_profile, args.shadertoy))
+        },
+        Target::Hlsl => {
+            println!("⚙️ Generating HLSL...");
+            Box::new(HlslGenerator)
         },
         Target::Markdown => {
             println!("📄 Generating Docs...");
-            MarkdownGenerator.generate(&ast)
+            Box::new(MarkdownGenerator)
         }
     };
+    let backend_errors = generator.validate(&ast);
+    if !backend_errors.is_empty() {
+        return Err(report(&source, &backend_errors));
+    }
+    let code = generator.generate(&ast);
 
     // 5. Output
     if let Some(out_path) = args.output {
@@ -88,3 +117,12 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Prints rendered diagnostics to stderr and returns the error that aborts compilation.
+fn report(source: &SourceMap, diagnostics: &[Diagnostic]) -> anyhow::Error {
+    for diag in diagnostics {
+        eprintln!("{}", diag.render(source));
+    }
+    let n = diagnostics.len();
+    anyhow::anyhow!("aborting due to {} previous error{}", n, if n == 1 { "" } else { "s" })
+}
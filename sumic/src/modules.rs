@@ -0,0 +1,477 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+
+use crate::ast::{array_type, split_array_type, AstNode, NodeKind};
+use crate::builtins;
+use crate::diagnostics::{Diagnostic, Span};
+use crate::lexer::tokenize;
+use crate::parser::Parser;
+use crate::preprocessor::Preprocessor;
+use crate::resolve;
+use crate::source_map::SourceMap;
+
+/// A file and the modules it imports, linked into one program.
+pub struct Linked {
+    /// Every file that was read, for rendering diagnostics.
+    pub sources: SourceMap,
+    pub ast: AstNode,
+    /// Syntax, import and name resolution errors, from every module.
+    pub errors: Vec<Diagnostic>,
+}
+
+/// A top-level item of a module.
+struct Symbol {
+    /// The name it is generated under, e.g. `sdf_sdBox`.
+    mangled: String,
+    public: bool,
+    keyword: &'static str,
+}
+
+/// An `import` of a module, once the module is loaded.
+struct Import {
+    module: usize,
+    path: String,
+    items: Option<Vec<String>>,
+    span: Span,
+}
+
+struct Module {
+    /// The dotted path it was first imported as, e.g. `shapes.sdf`; empty
+    /// for the file being compiled, whose names are kept as they are.
+    name: String,
+    span: Span,
+    items: Vec<AstNode>,
+    imports: Vec<Import>,
+    symbols: HashMap<String, Symbol>,
+}
+
+/// Loads a file and, recursively, the modules it imports.
+///
+/// `import shapes.sdf` looks for `shapes/sdf.sumi` next to the importing
+/// file, then in each directory of the search path. Every module is loaded
+/// once; its items are renamed to `shapes_sdf_<name>` so that they cannot
+/// collide with those of other modules.
+pub struct ModuleLoader {
+    search_path: Vec<PathBuf>,
+    /// Preprocesses each module, starting from the same macros and include directories.
+    preprocessor: Preprocessor,
+    sources: SourceMap,
+    modules: Vec<Module>,
+    loaded: HashMap<PathBuf, usize>,
+    /// The files being loaded, outermost first, to report import cycles.
+    loading: Vec<(PathBuf, String)>,
+    errors: Vec<Diagnostic>,
+}
+
+impl ModuleLoader {
+    pub fn new(search_path: Vec<PathBuf>, preprocessor: Preprocessor) -> Self {
+        Self { search_path, preprocessor, sources: SourceMap::new(), modules: Vec::new(), loaded: HashMap::new(), loading: Vec::new(), errors: Vec::new() }
+    }
+
+    /// Loads `entry` with its imports, links them and resolves names.
+    /// Imported modules come first in the program, dependencies before the
+    /// modules that use them.
+    pub fn load(mut self, entry: &Path) -> Result<Linked> {
+        self.load_file(entry, String::new())?;
+        let mut items = Vec::new();
+        for index in 0..self.modules.len() {
+            items.extend(self.link(index));
+        }
+        let span = self.modules.last().map(|m| m.span).unwrap_or_default();
+        let mut ast = AstNode::new(NodeKind::Program(items), span);
+        self.errors.extend(resolve::resolve(&mut ast));
+        Ok(Linked { sources: self.sources, ast, errors: self.errors })
+    }
+
+    fn load_file(&mut self, path: &Path, name: String) -> Result<usize> {
+        let canonical = fs::canonicalize(path)
+            .with_context(|| format!("Failed to resolve path: {:?}", path))?;
+        if let Some(&index) = self.loaded.get(&canonical) {
+            return Ok(index);
+        }
+
+        // The newest map holds the files of every unit loaded so far.
+        let sources = self.preprocessor.clone().process_into(path, self.sources.next_unit())?;
+        let (tokens, lex_errors) = tokenize(&sources);
+        self.sources = sources;
+        let (program, errors) = match lex_errors.is_empty() {
+            true => Parser::new(tokens).parse_module(),
+            false => (AstNode::new(NodeKind::Program(Vec::new()), Span::default()), lex_errors),
+        };
+        self.errors.extend(errors);
+
+        self.loading.push((canonical.clone(), name.clone()));
+        let NodeKind::Program(nodes) = program.kind else { unreachable!() };
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut items = Vec::new();
+        let mut imports = Vec::new();
+        for node in nodes {
+            let NodeKind::Import { path: module_path, items: names } = node.kind else {
+                items.push(node);
+                continue;
+            };
+            if let Some(module) = self.import(dir, &module_path, node.span)? {
+                imports.push(Import { module, path: module_path.join("."), items: names, span: node.span });
+            }
+        }
+        self.loading.pop();
+
+        let prefix = self.prefix(&name);
+        let symbols = items.iter().flat_map(|item| match &item.kind {
+            NodeKind::FunctionDecl { name, public, .. } => Some((name, *public, "fn")),
+            NodeKind::StructDecl { name, public, .. } => Some((name, *public, "struct")),
+            NodeKind::VarDecl { name, public, .. } | NodeKind::ArrayDecl { name, public, .. } => Some((name, *public, "const")),
+            _ => None,
+        }).map(|(item, public, keyword)| {
+            // Overloads of a built-in stay overloads of the built-in.
+            let mangled = match prefix.is_empty() || builtins::is_builtin(item) {
+                true => item.clone(),
+                false => format!("{}_{}", prefix, item),
+            };
+            (item.clone(), Symbol { mangled, public, keyword })
+        }).collect();
+
+        self.modules.push(Module { name, span: program.span, items, imports, symbols });
+        self.loaded.insert(canonical, self.modules.len() - 1);
+        Ok(self.modules.len() - 1)
+    }
+
+    /// Finds and loads the module `path`, reporting it if it is missing or
+    /// part of an import cycle.
+    fn import(&mut self, dir: &Path, path: &[String], span: Span) -> Result<Option<usize>> {
+        let name = path.join(".");
+        let file = PathBuf::from(format!("{}.sumi", path.join("/")));
+        let Some(found) = std::iter::once(dir).chain(self.search_path.iter().map(|p| p.as_path())).map(|d| d.join(&file)).find(|f| f.is_file()) else {
+            let searched = std::iter::once(dir).chain(self.search_path.iter().map(|p| p.as_path()))
+                .map(|d| format!("`{}`", if d.as_os_str().is_empty() { Path::new(".") } else { d }.display()))
+                .collect::<Vec<_>>().join(", ");
+            self.errors.push(Diagnostic::error(format!("cannot find module `{}`", name), span)
+                .with_label(format!("no `{}` found", file.display()))
+                .with_hint(format!("searched {}; add directories with `--module-path`", searched)));
+            return Ok(None);
+        };
+
+        let canonical = fs::canonicalize(&found).with_context(|| format!("Failed to resolve path: {:?}", found))?;
+        if let Some(position) = self.loading.iter().position(|(p, _)| *p == canonical) {
+            let cycle = self.loading[position..].iter().map(|(p, n)| match n.is_empty() {
+                true => p.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default(),
+                false => n.clone(),
+            }).chain(std::iter::once(name.clone())).collect::<Vec<_>>();
+            self.errors.push(Diagnostic::error(format!("module `{}` imports itself", name), span)
+                .with_label("import cycle")
+                .with_hint(format!("{}; move what they share into a module that both import", cycle.join(" → "))));
+            return Ok(None);
+        }
+
+        println!("  📦 Importing: {}", name);
+        let (line, file_name) = (span.line, self.sources.file(span.file).name.clone());
+        self.load_file(&found, name).map(Some)
+            .with_context(|| format!("imported from {}:{}", file_name, line))
+    }
+
+    /// The prefix of a module's mangled names: its path with `_` for `.`,
+    /// made unique if two different files were imported under one path.
+    fn prefix(&self, name: &str) -> String {
+        if name.is_empty() { return String::new(); }
+        let base = name.replace('.', "_");
+        let taken = |p: &str| self.modules.iter().any(|m| m.name.replace('.', "_") == p);
+        let mut prefix = base.clone();
+        let mut n = 1;
+        while taken(&prefix) {
+            n += 1;
+            prefix = format!("{}{}", base, n);
+        }
+        prefix
+    }
+
+    /// A module's items, with every name they use rewritten to the mangled
+    /// name of the item it refers to.
+    fn link(&mut self, index: usize) -> Vec<AstNode> {
+        let mut items = std::mem::take(&mut self.modules[index].items);
+        let module = &self.modules[index];
+        let mut linker = Linker {
+            modules: &self.modules,
+            names: module.symbols.iter().map(|(n, s)| (n.clone(), s.mangled.clone())).collect(),
+            aliases: HashMap::new(),
+            locals: Vec::new(),
+            errors: Vec::new(),
+        };
+        for import in &module.imports {
+            let Some(names) = &import.items else {
+                let alias = import.path.rsplit('.').next().unwrap_or_default().to_string();
+                linker.aliases.insert(alias, import.module);
+                continue;
+            };
+            for name in names {
+                let mangled = linker.export(import.module, name, import.span);
+                if module.symbols.contains_key(name) {
+                    linker.errors.push(Diagnostic::error(format!("`{}` is imported from `{}` and also defined in this module", name, import.path), import.span)
+                        .with_label(format!("`{}` imported here", name))
+                        .with_hint(format!("rename one of them, or `import {}` and write `{}.{}`", import.path, import.path.rsplit('.').next().unwrap_or_default(), name)));
+                    continue;
+                }
+                linker.names.insert(name.clone(), mangled);
+            }
+        }
+        items.iter_mut().for_each(|item| linker.item(item));
+        let errors = linker.errors;
+        self.errors.extend(errors);
+        items
+    }
+}
+
+/// Rewrites the names used by one module's items.
+struct Linker<'a> {
+    modules: &'a [Module],
+    /// Unqualified names in scope at module level: its own items and those
+    /// imported by name.
+    names: HashMap<String, String>,
+    /// Modules imported whole, by the last part of their path: `sdf` for
+    /// `import shapes.sdf`.
+    aliases: HashMap<String, usize>,
+    /// Parameters and local variables, which shadow module-level names.
+    locals: Vec<HashSet<String>>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Linker<'_> {
+    /// The mangled name of `name` in another module, reporting it if that
+    /// module has no such item or does not make it `pub`.
+    fn export(&mut self, module: usize, name: &str, span: Span) -> String {
+        let module = &self.modules[module];
+        match module.symbols.get(name) {
+            Some(symbol) => {
+                if !symbol.public {
+                    self.errors.push(Diagnostic::error(format!("`{}` is private to module `{}`", name, module.name), span)
+                        .with_label("not declared `pub`")
+                        .with_hint(format!("declare it `pub {} {}` in `{}` to use it from other modules", symbol.keyword, name, module.name)));
+                }
+                symbol.mangled.clone()
+            },
+            None => {
+                let mut exports = module.symbols.iter().filter(|(_, s)| s.public).map(|(n, _)| format!("`{}`", n)).collect::<Vec<_>>();
+                exports.sort();
+                exports.dedup();
+                let hint = match exports.is_empty() {
+                    true => format!("`{}` has no `pub` items", module.name),
+                    false => format!("`{}` exports {}", module.name, exports.join(", ")),
+                };
+                self.errors.push(Diagnostic::error(format!("cannot find `{}` in module `{}`", name, module.name), span)
+                    .with_label(format!("not defined in `{}`", module.name))
+                    .with_hint(hint));
+                format!("{}_{}", module.name.replace('.', "_"), name)
+            },
+        }
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.locals.iter().any(|scope| scope.contains(name))
+    }
+
+    fn declare(&mut self, name: &str) {
+        if let Some(scope) = self.locals.last_mut() {
+            scope.insert(name.to_string());
+        }
+    }
+
+    /// The module a qualified name like `sdf.sdBox` starts with, unless a
+    /// local variable of that name hides it.
+    fn alias(&self, name: &str) -> Option<usize> {
+        if self.is_local(name) { return None; }
+        self.aliases.get(name).copied()
+    }
+
+    fn rename_type(&mut self, type_name: &mut String, span: Span) {
+        if let Some((element, size)) = split_array_type(type_name) {
+            let mut element = element.to_string();
+            self.rename_type(&mut element, span);
+            *type_name = array_type(&element, size);
+        } else if let Some((alias, name)) = type_name.rsplit_once('.') {
+            match self.aliases.get(alias) {
+                Some(&module) => *type_name = self.export(module, name, span),
+                None => self.errors.push(Diagnostic::error(format!("cannot find module `{}`", alias), span)
+                    .with_label(format!("in type `{}`", type_name))
+                    .with_hint(format!("add `import {}`", alias))),
+            }
+        } else if let Some(mangled) = self.names.get(type_name.as_str()) {
+            *type_name = mangled.clone();
+        }
+    }
+
+    fn item(&mut self, item: &mut AstNode) {
+        let span = item.span;
+        match &mut item.kind {
+            NodeKind::FunctionDecl { .. } => self.function(item, true),
+            NodeKind::ImplBlock { type_name, methods } => {
+                self.rename_type(type_name, span);
+                methods.iter_mut().for_each(|m| self.function(m, false));
+            },
+            NodeKind::StructDecl { name, fields, .. } => {
+                *name = self.names[name.as_str()].clone();
+                fields.iter_mut().for_each(|f| self.rename_type(&mut f.type_name, span));
+            },
+            NodeKind::VarDecl { name, type_name, value, .. } | NodeKind::ArrayDecl { name, type_name, value, .. } => {
+                *name = self.names[name.as_str()].clone();
+                self.rename_type(type_name, span);
+                if let Some(v) = value { self.node(v); }
+            },
+            _ => self.node(item),
+        }
+    }
+
+    /// A function or method; only free functions are renamed themselves.
+    fn function(&mut self, function: &mut AstNode, rename: bool) {
+        let span = function.span;
+        let NodeKind::FunctionDecl { name, return_type, args, body, .. } = &mut function.kind else { return };
+        if rename {
+            *name = self.names[name.as_str()].clone();
+        }
+        self.rename_type(return_type, span);
+        args.iter_mut().for_each(|a| self.rename_type(&mut a.type_name, span));
+        self.locals.push(args.iter().map(|a| a.name.clone()).collect());
+        self.node(body);
+        self.locals.pop();
+    }
+
+    fn node(&mut self, node: &mut AstNode) {
+        let span = node.span;
+        match &mut node.kind {
+            NodeKind::Block(_) | NodeKind::ForStmt { .. } => {
+                self.locals.push(HashSet::new());
+                node.children_mut().into_iter().for_each(|c| self.node(c));
+                self.locals.pop();
+            },
+            NodeKind::VarDecl { name, type_name, value, .. } | NodeKind::ArrayDecl { name, type_name, value, .. } => {
+                if let Some(v) = value { self.node(v); }
+                self.rename_type(type_name, span);
+                let name = name.clone();
+                self.declare(&name);
+            },
+            NodeKind::Variable(name) | NodeKind::Call { func_name: name, .. } if !self.is_local(name) => {
+                if let Some(mangled) = self.names.get(name.as_str()) {
+                    *name = mangled.clone();
+                }
+                node.children_mut().into_iter().for_each(|c| self.node(c));
+            },
+            NodeKind::StructLiteral { name, .. } => {
+                self.rename_type(name, span);
+                node.children_mut().into_iter().for_each(|c| self.node(c));
+            },
+            // `sdf.sdBox(p, b)`: a call into a module, not a method call.
+            NodeKind::MethodCall { receiver, method, args, .. } if matches!(&receiver.kind, NodeKind::Variable(m) if self.alias(m).is_some()) => {
+                let NodeKind::Variable(alias) = &receiver.kind else { return };
+                let Some(module) = self.alias(alias) else { return };
+                let func_name = self.export(module, method, span);
+                let mut args = std::mem::take(args);
+                args.iter_mut().for_each(|a| self.node(a));
+                node.kind = NodeKind::Call { func_name, args, overload: None, arg_types: Vec::new() };
+            },
+            // `sdf.PI`
+            NodeKind::MemberAccess { base, member } if matches!(&base.kind, NodeKind::Variable(m) if self.alias(m).is_some()) => {
+                let NodeKind::Variable(alias) = &base.kind else { return };
+                let Some(module) = self.alias(alias) else { return };
+                node.kind = NodeKind::Variable(self.export(module, member, span));
+            },
+            _ => node.children_mut().into_iter().for_each(|c| self.node(c)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_files(files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sumic_modules_{}_{}", std::process::id(), files[0].0.replace('/', "_")));
+        for (name, text) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        dir
+    }
+
+    fn messages(linked: &Linked) -> Vec<&str> {
+        linked.errors.iter().map(|e| e.message.as_str()).collect()
+    }
+
+    fn function_names(linked: &Linked) -> Vec<String> {
+        let NodeKind::Program(items) = &linked.ast.kind else { panic!() };
+        items.iter().filter_map(|i| match &i.kind {
+            NodeKind::FunctionDecl { name, .. } | NodeKind::StructDecl { name, .. } => Some(name.clone()),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn test_imported_names_are_mangled() {
+        let dir = write_files(&[
+            ("main.sumi", "import noise\nimport shapes.sdf.{sdSphere, Hit}\n\
+                           fn hash(p: vec2) float { return 0.5 }\n\
+                           fn f(p: vec3) float { let h = Hit(sdSphere(p, 1.0)); return noise.hash(p.xy) + hash(p.xy) + h.d + noise.SCALE }"),
+            ("noise.sumi", "pub const SCALE: float = 2.0;\nfn fade(t: float) float { return t }\npub fn hash(p: vec2) float { return fade(p.x) * SCALE }"),
+            ("shapes/sdf.sumi", "pub struct Hit { d: float }\npub fn sdSphere(p: vec3, r: float) float { return length(p) - r }"),
+        ]);
+        let linked = ModuleLoader::new(Vec::new(), Preprocessor::new()).load(&dir.join("main.sumi")).unwrap();
+        assert!(linked.errors.is_empty(), "{:?}", messages(&linked));
+        assert_eq!(function_names(&linked), vec!["noise_fade", "noise_hash", "shapes_sdf_Hit", "shapes_sdf_sdSphere", "hash", "f"]);
+
+        let mut calls = Vec::new();
+        linked.ast.walk(&mut |n| match &n.kind {
+            NodeKind::Call { func_name: name, .. } | NodeKind::StructLiteral { name, .. } | NodeKind::Variable(name) if name.contains('_') => calls.push(name.clone()),
+            _ => {}
+        });
+        assert_eq!(calls, vec!["noise_fade", "noise_SCALE", "shapes_sdf_Hit", "shapes_sdf_sdSphere", "noise_hash", "noise_SCALE"]);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_qualified_struct_with_named_fields() {
+        let dir = write_files(&[
+            ("main.sumi", "import shapes.sdf\nfn f() float { let h = sdf.Hit(id: 1, d: 2.0); return h.d + sdf.Hit(1.0, 2).d }"),
+            ("shapes/sdf.sumi", "pub struct Hit { d: float, id: int }"),
+        ]);
+        let linked = ModuleLoader::new(Vec::new(), Preprocessor::new()).load(&dir.join("main.sumi")).unwrap();
+        assert!(linked.errors.is_empty(), "{:?}", messages(&linked));
+
+        let mut literals = Vec::new();
+        linked.ast.walk(&mut |n| if let NodeKind::StructLiteral { name, fields } = &n.kind {
+            literals.push(format!("{}({})", name, fields.iter().map(|(f, _)| f.as_str()).collect::<Vec<_>>().join(", ")));
+        });
+        assert_eq!(literals, vec!["shapes_sdf_Hit(d, id)", "shapes_sdf_Hit(d, id)"]);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let dir = write_files(&[
+            ("errors.sumi", "import lib.{helper, missing}\nimport nowhere\nimport cycle\nfn f() float { return helper() }"),
+            ("lib.sumi", "fn helper() float { return 1.0 }"),
+            ("cycle.sumi", "import errors"),
+        ]);
+        let linked = ModuleLoader::new(Vec::new(), Preprocessor::new()).load(&dir.join("errors.sumi")).unwrap();
+        assert_eq!(messages(&linked), vec![
+            "cannot find module `nowhere`",
+            "module `errors` imports itself",
+            "`helper` is private to module `lib`",
+            "cannot find `missing` in module `lib`",
+        ]);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_search_path() {
+        let dir = write_files(&[
+            ("app/main.sumi", "import util.{twice}\nfn f() float { return twice(1.0) }"),
+            ("lib/util.sumi", "pub fn twice(x: float) float { return x * 2.0 }"),
+        ]);
+        let linked = ModuleLoader::new(Vec::new(), Preprocessor::new()).load(&dir.join("app/main.sumi")).unwrap();
+        assert_eq!(messages(&linked), vec!["cannot find module `util`"]);
+        let linked = ModuleLoader::new(vec![dir.join("lib")], Preprocessor::new()).load(&dir.join("app/main.sumi")).unwrap();
+        assert!(linked.errors.is_empty(), "{:?}", messages(&linked));
+        assert_eq!(function_names(&linked), vec!["util_twice", "f"]);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,360 +1,1072 @@
-use crate::ast::{AstNode, BinaryOperator, UnaryOperator};
-use crate::lexer::Token;
+use crate::ast::{array_type, split_array_type, AstNode, NodeKind, BinaryOperator, BindingKind, Field, Param, ParamQualifier, SwitchCase, UnaryOperator};
+use crate::diagnostics::{Diagnostic, Span};
+use crate::lexer::{SpannedToken, Token};
+use crate::resolve;
+
+type ParseResult<T> = Result<T, Diagnostic>;
 
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<SpannedToken>,
     cursor: usize,
+    errors: Vec<Diagnostic>,
+    /// How many `(`/`[` groups enclose the current token; line breaks only end
+    /// statements outside of them.
+    nesting: usize,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, cursor: 0 }
+    pub fn new(tokens: Vec<SpannedToken>) -> Self {
+        Self { tokens, cursor: 0, errors: Vec::new(), nesting: 0 }
     }
 
-    fn current(&self) -> Option<&Token> { self.tokens.get(self.cursor) }
-    fn peek(&self) -> Option<&Token> { self.tokens.get(self.cursor + 1) }
+    fn current(&self) -> Option<&Token> { self.tokens.get(self.cursor).map(|t| &t.token) }
+    fn peek(&self) -> Option<&Token> { self.tokens.get(self.cursor + 1).map(|t| &t.token) }
     fn advance(&mut self) { if self.cursor < self.tokens.len() { self.cursor += 1; } }
-    
+
     fn check(&self, token: &Token) -> bool { self.current() == Some(token) }
 
-    fn consume(&mut self, expected: Token) -> Result<(), String> {
+    /// Span of the current token, or a point just past the last token at EOF.
+    fn current_span(&self) -> Span {
+        match self.tokens.get(self.cursor) {
+            Some(t) => t.span,
+            None => self.tokens.last().map(|t| t.span.after()).unwrap_or_default(),
+        }
+    }
+
+    /// Span of the most recently consumed token.
+    fn prev_span(&self) -> Span {
+        self.cursor.checked_sub(1).and_then(|i| self.tokens.get(i)).map(|t| t.span).unwrap_or_default()
+    }
+
+    /// Builds a node spanning from `start` to the last consumed token.
+    fn node(&self, kind: NodeKind, start: Span) -> AstNode {
+        AstNode::new(kind, start.to(self.prev_span()))
+    }
+
+    fn found(&self) -> String {
+        self.current().map(|t| t.to_string()).unwrap_or_else(|| "end of file".to_string())
+    }
+
+    /// An "expected X, found Y" error pointing at the current token.
+    fn error_expected(&self, what: &str) -> Diagnostic {
+        Diagnostic::error(format!("expected {}, found {}", what, self.found()), self.current_span())
+            .with_label(format!("expected {}", what))
+    }
+
+    fn consume(&mut self, expected: Token) -> ParseResult<Span> {
         if self.check(&expected) {
+            let span = self.current_span();
             self.advance();
-            Ok(())
-        } else {
-            let got = self.current().map(|t| format!("{:?}", t)).unwrap_or("EOF".to_string());
-            Err(format!("Expected {:?}, got {}", expected, got))
+            return Ok(span);
+        }
+        let what = expected.to_string();
+        if expected == Token::Semicolon && self.cursor > 0 {
+            // Point just after the statement that is missing its terminator.
+            return Err(Diagnostic::error(format!("expected {}, found {}", what, self.found()), self.prev_span().after())
+                .with_label(format!("expected {}", what))
+                .with_hint("end the statement with `;`"));
         }
+        let mut diag = self.error_expected(&what);
+        if self.current().is_none() {
+            diag = diag.with_hint(format!("the file ended early; is a {} missing?", what));
+        }
+        Err(diag)
     }
 
-    pub fn parse(&mut self) -> Result<AstNode, String> {
+    /// True if the current token starts a new line outside any brackets, so an
+    /// expression before it cannot continue onto it.
+    fn at_line_break(&self) -> bool {
+        if self.nesting > 0 || self.cursor == 0 { return false; }
+        match (self.tokens.get(self.cursor - 1), self.tokens.get(self.cursor)) {
+            (Some(prev), Some(cur)) => prev.span.line != cur.span.line || prev.span.file != cur.span.file,
+            _ => false,
+        }
+    }
+
+    /// Runs `f` inside a `(`/`[` group, where line breaks do not end statements.
+    fn bracketed<T>(&mut self, f: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        self.nesting += 1;
+        let result = f(self);
+        self.nesting -= 1;
+        result
+    }
+
+    /// Ends a statement with an explicit `;` or, outside brackets, with a line
+    /// break, the closing `}` of the block or the end of the file.
+    fn end_statement(&mut self) -> ParseResult<()> {
+        if self.check(&Token::Semicolon) {
+            self.advance();
+            return Ok(());
+        }
+        if self.nesting == 0 && (self.at_line_break() || self.check(&Token::RBrace) || self.current().is_none()) {
+            return Ok(());
+        }
+        Err(Diagnostic::error(format!("expected `;` or a new line, found {}", self.found()), self.prev_span().after())
+            .with_label("expected `;` or a new line")
+            .with_hint("put the next statement on its own line, or separate them with `;`"))
+    }
+
+    fn check_peek(&self, token: &Token) -> bool { self.peek() == Some(token) }
+
+    fn expect_ident(&mut self, what: &str) -> ParseResult<String> {
+        match self.current() {
+            Some(Token::Identifier(s)) => { let s = s.clone(); self.advance(); Ok(s) },
+            _ => Err(self.error_expected(what)),
+        }
+    }
+
+    /// Parses the whole program, failing with every syntax error found.
+    pub fn parse(&mut self) -> Result<AstNode, Vec<Diagnostic>> {
+        let (ast, errors) = self.parse_recovering();
+        if errors.is_empty() { Ok(ast) } else { Err(errors) }
+    }
+
+    /// Parses the whole program, recovering from syntax errors.
+    ///
+    /// Returns the (possibly partial) tree alongside all errors; items and
+    /// statements that failed to parse are left out of the tree.
+    pub fn parse_recovering(&mut self) -> (AstNode, Vec<Diagnostic>) {
+        let (mut program, mut errors) = self.parse_module();
+        errors.extend(resolve::resolve(&mut program));
+        (program, errors)
+    }
+
+    /// Parses one module, recovering from syntax errors like `parse_recovering`,
+    /// but without name resolution, which needs its imports linked in first.
+    pub fn parse_module(&mut self) -> (AstNode, Vec<Diagnostic>) {
+        let start = self.current_span();
         let mut nodes = Vec::new();
         while self.current().is_some() {
-            nodes.push(self.parse_top_level()?);
+            let before = self.cursor;
+            match self.parse_top_level() {
+                Ok(node) => nodes.push(node),
+                Err(e) => {
+                    self.errors.push(e);
+                    if self.cursor == before { self.advance(); }
+                    self.synchronize_top_level();
+                }
+            }
         }
-        Ok(AstNode::Program(nodes))
+        (self.node(NodeKind::Program(nodes), start), std::mem::take(&mut self.errors))
     }
 
-    fn parse_top_level(&mut self) -> Result<AstNode, String> {
-        // Handle Doc Comments
-        let mut doc_string = None;
-        if let Some(Token::DocComment(s)) = self.current() {
-            doc_string = Some(s.clone());
+    /// Skips to the start of the next `fn`/`struct`/`const` item (or past the end of
+    /// the current `{ ... }` body).
+    fn synchronize_top_level(&mut self) {
+        let mut depth = 0usize;
+        while let Some(token) = self.current() {
+            match token {
+                Token::Fn | Token::Struct | Token::Impl | Token::Const | Token::Import | Token::Pub | Token::DocComment(_) if depth == 0 => return,
+                Token::LBrace => depth += 1,
+                Token::RBrace => {
+                    self.advance();
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 { return; }
+                    continue;
+                }
+                _ => {}
+            }
             self.advance();
-            while let Some(Token::DocComment(s2)) = self.current() {
-                doc_string = Some(format!("{}\n{}", doc_string.unwrap(), s2));
-                self.advance();
+        }
+    }
+
+    /// Skips past the end of a broken statement: just after its `;`, up to the next
+    /// line outside brackets, just after a `{ ... }` it opened, or up to the
+    /// `}`/`case` ending the enclosing block. Stops early at `fn`/`struct`, which
+    /// can only start a new top-level item.
+    fn synchronize_statement(&mut self) {
+        let mut depth = 0usize;
+        let mut brackets = 0usize;
+        while let Some(token) = self.current() {
+            if depth == 0 && brackets == 0 && self.at_line_break() { return; }
+            match token {
+                Token::LParen | Token::LBracket => brackets += 1,
+                Token::RParen | Token::RBracket => brackets = brackets.saturating_sub(1),
+                Token::Fn | Token::Struct | Token::Impl => return,
+                Token::Semicolon if depth == 0 => { self.advance(); return; }
+                Token::RBrace | Token::Case | Token::Default if depth == 0 => return,
+                Token::RBrace => {
+                    self.advance();
+                    depth -= 1;
+                    if depth == 0 { return; }
+                    continue;
+                }
+                Token::LBrace => depth += 1,
+                _ => {}
             }
+            self.advance();
         }
+    }
 
-        if self.check(&Token::Struct) {
-            return self.parse_struct(doc_string);
+    fn parse_top_level(&mut self) -> ParseResult<AstNode> {
+        let start = self.current_span();
+
+        let doc_string = self.parse_doc_comments();
+
+        if self.check(&Token::Import) {
+            self.advance();
+            return self.parse_import(start);
         }
 
-        // Function Declaration
-        // S2L: fn Name(...)
-        if self.check(&Token::Fn) {
+        // `pub` makes an item visible to modules that import this one.
+        if !self.check(&Token::Pub) {
+            return self.parse_item(doc_string, start);
+        }
+        let pub_span = self.current_span();
+        self.advance();
+        let mut item = self.parse_item(doc_string, start)?;
+        match &mut item.kind {
+            NodeKind::FunctionDecl { public, .. } | NodeKind::StructDecl { public, .. }
+            | NodeKind::VarDecl { public, .. } | NodeKind::ArrayDecl { public, .. } => *public = true,
+            _ => return Err(Diagnostic::error("`pub` cannot be used on an `impl` block", pub_span)
+                .with_label("not allowed here")
+                .with_hint("methods are visible wherever their struct is; mark the struct `pub` instead")),
+        }
+        Ok(item)
+    }
+
+    /// `import a.b` or `import a.b.{x, y}` (the `import` is consumed).
+    fn parse_import(&mut self, start: Span) -> ParseResult<AstNode> {
+        let hint = "imports are written `import module` or `import module.{name, ...}`";
+        let mut path = vec![self.expect_ident("module name").map_err(|e| e.with_hint(hint))?];
+        let mut items = None;
+        while self.check(&Token::Dot) {
             self.advance();
-            let name = match self.current() {
-                Some(Token::Identifier(s)) => s.clone(),
-                _ => return Err("Expected Function Name".to_string()),
-            };
+            if !self.check(&Token::LBrace) {
+                path.push(self.expect_ident("module name or `{`").map_err(|e| e.with_hint(hint))?);
+                continue;
+            }
             self.advance();
+            let mut names = Vec::new();
+            while !self.check(&Token::RBrace) {
+                names.push(self.expect_ident("name to import").map_err(|e| e.with_hint(hint))?);
+                if self.check(&Token::Comma) { self.advance(); }
+                else if !self.check(&Token::RBrace) { return Err(self.error_expected("`,` or `}`")); }
+            }
+            self.advance();
+            items = Some(names);
+            break;
+        }
+        self.end_statement()?;
+        Ok(self.node(NodeKind::Import { path, items }, start))
+    }
 
-            self.consume(Token::LParen)?;
-            let args = self.parse_args()?;
-            self.consume(Token::RParen)?;
+    fn parse_item(&mut self, doc_string: Option<String>, start: Span) -> ParseResult<AstNode> {
+        if self.check(&Token::Struct) {
+            return self.parse_struct(doc_string, start);
+        }
 
-            let return_type = match self.current() {
-                Some(Token::Identifier(s)) => { let t = s.clone(); self.advance(); t },
-                Some(Token::LBrace) => "void".to_string(),
-                _ => return Err("Expected Return Type".to_string()),
-            };
+        // Module-scope constant: const NAME: type = value;
+        if self.check(&Token::Const) {
+            self.advance();
+            return self.parse_binding(BindingKind::Const, doc_string, start);
+        }
 
-            self.consume(Token::LBrace)?;
-            let body = self.parse_block()?;
+        if self.check(&Token::Impl) {
+            self.advance();
+            return self.parse_impl(start);
+        }
 
-            return Ok(AstNode::FunctionDecl { return_type, name, args, body: Box::new(body), doc_string });
+        // Function Declaration
+        // S2L: fn Name(...)
+        if self.check(&Token::Fn) {
+            self.advance();
+            return self.parse_function(doc_string, start, None);
         }
 
         // Legacy C-Style Function: Type Name(...)
         let type_name = match self.current() {
             Some(Token::Identifier(s)) => s.clone(),
-            _ => return Err("Expected Function or Struct".to_string()),
+            _ => return Err(self.error_expected("`fn`, `struct`, `impl`, `const` or a function declaration")),
         };
         self.advance();
 
-        let name = match self.current() {
-            Some(Token::Identifier(s)) => s.clone(),
-            _ => return Err("Expected Name".to_string()),
-        };
-        self.advance();
+        let name = self.expect_ident("function name")?;
 
         self.consume(Token::LParen)?;
-        let args = self.parse_args()?;
+        let args = self.parse_args(None)?;
         self.consume(Token::RParen)?;
         self.consume(Token::LBrace)?;
         let body = self.parse_block()?;
 
-        Ok(AstNode::FunctionDecl { return_type: type_name, name, args, body: Box::new(body), doc_string })
+        Ok(self.node(NodeKind::FunctionDecl { return_type: type_name, name, args, body: Box::new(body), doc_string, overload: None, public: false }, start))
     }
 
-    fn parse_struct(&mut self, doc_string: Option<String>) -> Result<AstNode, String> {
-        self.consume(Token::Struct)?;
-        let name = match self.current() { Some(Token::Identifier(s)) => s.clone(), _ => return Err("Expected struct name".to_string()) };
-        self.advance();
+    /// The rest of an S2L `fn name(args) return_type { ... }` (the `fn` is consumed).
+    /// Inside an `impl` block, `self_type` is the type a `self` parameter takes.
+    fn parse_function(&mut self, doc_string: Option<String>, start: Span, self_type: Option<&str>) -> ParseResult<AstNode> {
+        let name = self.expect_ident("function name")?;
+
+        self.consume(Token::LParen)?;
+        let args = self.parse_args(self_type)?;
+        self.consume(Token::RParen)?;
+
+        let return_type = match self.current() {
+            Some(Token::Identifier(_)) => self.parse_type("return type")?,
+            Some(Token::LBrace) => "void".to_string(),
+            _ => return Err(self.error_expected("return type or `{`")
+                .with_hint("S2L functions are written `fn name(arg: type) return_type { ... }`")),
+        };
+
         self.consume(Token::LBrace)?;
-        let mut fields = Vec::new();
+        let body = self.parse_block()?;
+
+        Ok(self.node(NodeKind::FunctionDecl { return_type, name, args, body: Box::new(body), doc_string, overload: None, public: false }, start))
+    }
+
+    /// `impl Type { fn method(self, ...) ... }` (the `impl` is consumed).
+    fn parse_impl(&mut self, start: Span) -> ParseResult<AstNode> {
+        let type_name = self.expect_ident("type name after `impl`")?;
+        self.consume(Token::LBrace)?;
+        let mut methods = Vec::new();
         while !self.check(&Token::RBrace) && self.current().is_some() {
-            let type_name = match self.current() { Some(Token::Identifier(s)) => s.clone(), _ => return Err("Expected field type".to_string()) };
-            self.advance();
-            let field_name = match self.current() { Some(Token::Identifier(s)) => s.clone(), _ => return Err("Expected field name".to_string()) };
+            let method_start = self.current_span();
+            let doc_string = self.parse_doc_comments();
+            self.consume(Token::Fn)
+                .map_err(|e| e.with_hint("an `impl` block can only contain methods"))?;
+            let method = self.parse_function(doc_string, method_start, Some(&type_name))?;
+            if let NodeKind::FunctionDecl { name, args, .. } = &method.kind {
+                if args.first().is_none_or(|a| a.name != "self") {
+                    return Err(Diagnostic::error(format!("method `{}` has no `self` parameter", name), method_start)
+                        .with_label("methods take `self` first")
+                        .with_hint(format!("write `fn {}(self, ...)`, or move it out of the `impl` block", name)));
+                }
+            }
+            methods.push(method);
+        }
+        self.consume(Token::RBrace)?;
+        Ok(self.node(NodeKind::ImplBlock { type_name, methods }, start))
+    }
+
+    /// Joins a run of `///` lines into one doc string.
+    fn parse_doc_comments(&mut self) -> Option<String> {
+        let mut lines = Vec::new();
+        while let Some(Token::DocComment(s)) = self.current() {
+            lines.push(s.clone());
             self.advance();
-            self.consume(Token::Semicolon)?;
-            fields.push((type_name, field_name));
+        }
+        if lines.is_empty() { None } else { Some(lines.join("\n")) }
+    }
+
+    fn parse_struct(&mut self, doc_string: Option<String>, start: Span) -> ParseResult<AstNode> {
+        self.consume(Token::Struct)?;
+        let name = self.expect_ident("struct name")?;
+        self.consume(Token::LBrace)?;
+        let mut fields: Vec<Field> = Vec::new();
+        while !self.check(&Token::RBrace) && self.current().is_some() {
+            let doc_string = self.parse_doc_comments();
+            let field_start = self.current_span();
+            let first = self.expect_ident("field name")?;
+            // S2L `name: type`, or C-style `type name` / `type name[N]`
+            let (type_name, name) = if self.check(&Token::Colon) {
+                self.advance();
+                (self.parse_type("field type")?, first)
+            } else {
+                let name = self.expect_ident("field name")
+                    .map_err(|e| e.with_hint("fields are written `name: type`"))?;
+                (self.parse_array_suffix(first)?, name)
+            };
+            if fields.iter().any(|f| f.name == name) {
+                return Err(Diagnostic::error(format!("field `{}` is declared twice", name), field_start.to(self.prev_span()))
+                    .with_label("duplicate field"));
+            }
+            if self.check(&Token::Comma) { self.advance(); } else { self.end_statement()?; }
+            fields.push(Field { type_name, name, doc_string });
         }
         self.consume(Token::RBrace)?;
-        self.consume(Token::Semicolon)?; 
-        Ok(AstNode::StructDecl { name, fields, doc_string })
+        if self.check(&Token::Semicolon) { self.advance(); }
+        Ok(self.node(NodeKind::StructDecl { name, fields, doc_string, public: false }, start))
     }
 
-    fn parse_args(&mut self) -> Result<Vec<(String, String)>, String> {
+    fn parse_args(&mut self, self_type: Option<&str>) -> ParseResult<Vec<Param>> {
         let mut args = Vec::new();
         while !self.check(&Token::RParen) {
-            // Skip qualifiers
-            if let Some(Token::Identifier(s)) = self.current() {
-                if s == "in" || s == "out" || s == "inout" { self.advance(); }
+            let doc_string = self.parse_doc_comments();
+
+            // A method's receiver: bare `self`, typed by the `impl` block.
+            if matches!(self.current(), Some(Token::Identifier(s)) if s == "self") && !self.check_peek(&Token::Colon) {
+                let Some(type_name) = self.current().and(self_type) else {
+                    return Err(Diagnostic::error("`self` parameter outside of an `impl` block", self.current_span())
+                        .with_label("only methods have a `self`")
+                        .with_hint("declare the function inside `impl Type { ... }`, or give the parameter a name and type"));
+                };
+                if !args.is_empty() {
+                    return Err(Diagnostic::error("`self` must be the first parameter", self.current_span())
+                        .with_label("move this to the front"));
+                }
+                args.push(Param { type_name: type_name.to_string(), name: "self".to_string(), qualifier: ParamQualifier::In, doc_string });
+                self.advance();
+                if self.check(&Token::Comma) { self.advance(); }
+                else if !self.check(&Token::RParen) { return Err(self.error_expected("`,` or `)`")); }
+                continue;
             }
 
-            let first = match self.current() { Some(Token::Identifier(s)) => s.clone(), _ => return Err("Expected arg ident".to_string()) };
-            self.advance();
+            // `in` / `out` / `inout`, written before the parameter in either style.
+            let mut qualifier = ParamQualifier::In;
+            if let Some(q) = self.current().and_then(|t| match t {
+                Token::Identifier(s) if !self.check_peek(&Token::Colon) => ParamQualifier::from_keyword(s),
+                _ => None,
+            }) {
+                qualifier = q;
+                self.advance();
+            }
+
+            let first = self.expect_ident("parameter")?;
 
             // S2L: Name : Type
             if self.check(&Token::Colon) {
                 self.advance();
-                let type_name = match self.current() { Some(Token::Identifier(s)) => s.clone(), _ => return Err("Expected type".to_string()) };
-                self.advance();
-                args.push((type_name, first));
+                let type_name = self.parse_type("parameter type")?;
+                args.push(Param { type_name, name: first, qualifier, doc_string });
             } else {
-                // C-Style: Type Name
-                let name = match self.current() { Some(Token::Identifier(s)) => s.clone(), _ => return Err("Expected arg name".to_string()) };
-                self.advance();
-                args.push((first, name));
+                // C-Style: Type Name, or Type Name[N]
+                let name = self.expect_ident("parameter name")
+                    .map_err(|e| e.with_hint("parameters are written `name: type` (or C-style `type name`)"))?;
+                let type_name = self.parse_array_suffix(first)?;
+                args.push(Param { type_name, name, qualifier, doc_string });
             }
             if self.check(&Token::Comma) { self.advance(); }
+            else if !self.check(&Token::RParen) { return Err(self.error_expected("`,` or `)`")); }
         }
         Ok(args)
     }
 
-    fn parse_block(&mut self) -> Result<AstNode, String> {
+    /// Parses statements up to and including the closing `}` (the `{` is already consumed).
+    fn parse_block(&mut self) -> ParseResult<AstNode> {
+        let start = self.prev_span();
+        let statements = self.parse_statements(|t| matches!(t, Token::RBrace));
+        self.consume(Token::RBrace)?;
+        Ok(self.node(NodeKind::Block(statements), start))
+    }
+
+    /// Parses statements until `stop` matches (or EOF), recovering from errors.
+    fn parse_statements(&mut self, stop: fn(&Token) -> bool) -> Vec<AstNode> {
         let mut statements = Vec::new();
-        while !self.check(&Token::RBrace) && self.current().is_some() {
-            statements.push(self.parse_statement()?);
+        // `fn`/`struct` can only start a new item, meaning this block was never
+        // closed; stop so the missing `}` is reported once.
+        while let Some(token) = self.current() {
+            if stop(token) || matches!(token, Token::Fn | Token::Struct | Token::Impl) { break; }
+            let before = self.cursor;
+            match self.parse_statement() {
+                Ok(stmt) => statements.push(stmt),
+                Err(e) => {
+                    self.errors.push(e);
+                    if self.cursor == before { self.advance(); }
+                    self.synchronize_statement();
+                }
+            }
+        }
+        statements
+    }
+
+    /// `switch selector { case 1, 2..=4: ... default: ... }` (the `switch` is consumed);
+    /// the selector may be parenthesized.
+    fn parse_switch(&mut self, start: Span) -> ParseResult<AstNode> {
+        let selector = self.parse_expression()?;
+        self.consume(Token::LBrace)?;
+
+        let mut cases: Vec<SwitchCase> = Vec::new();
+        let mut default = None;
+        let end_of_arm = |t: &Token| matches!(t, Token::Case | Token::Default | Token::RBrace);
+        while !self.check(&Token::RBrace) {
+            let arm_start = self.current_span();
+            if self.check(&Token::Case) {
+                self.advance();
+                let mut values = Vec::new();
+                loop {
+                    let value_start = self.current_span();
+                    for value in self.parse_case_values()? {
+                        if values.contains(&value) || cases.iter().any(|c| c.values.contains(&value)) {
+                            return Err(Diagnostic::error(format!("case value {} is covered more than once", value), value_start.to(self.prev_span()))
+                                .with_label("duplicate case value"));
+                        }
+                        values.push(value);
+                    }
+                    if !self.check(&Token::Comma) { break; }
+                    self.advance();
+                }
+                self.consume(Token::Colon)?;
+                let body_start = self.prev_span();
+                let body = self.parse_statements(end_of_arm);
+                let body = self.node(NodeKind::Block(body), body_start);
+                cases.push(SwitchCase { values, body, span: arm_start.to(self.prev_span()) });
+            } else if self.check(&Token::Default) {
+                if default.is_some() {
+                    return Err(Diagnostic::error("a switch can only have one `default` arm", arm_start)
+                        .with_label("second `default`"));
+                }
+                self.advance();
+                self.consume(Token::Colon)?;
+                let body_start = self.prev_span();
+                let body = self.parse_statements(end_of_arm);
+                default = Some(Box::new(self.node(NodeKind::Block(body), body_start)));
+            } else {
+                return Err(self.error_expected("`case`, `default` or `}`"));
+            }
         }
         self.consume(Token::RBrace)?;
-        Ok(AstNode::Block(statements))
+        Ok(self.node(NodeKind::SwitchStmt { selector: Box::new(selector), cases, default }, start))
+    }
+
+    /// A case label: `3`, `-1`, `2..5` (exclusive) or `2..=5` (inclusive).
+    fn parse_case_values(&mut self) -> ParseResult<Vec<i64>> {
+        let start = self.current_span();
+        let low = self.parse_case_int()?;
+        let high = match self.current() {
+            Some(Token::DotDot) => { self.advance(); self.parse_case_int()? - 1 },
+            Some(Token::DotDotEquals) => { self.advance(); self.parse_case_int()? },
+            _ => return Ok(vec![low]),
+        };
+        let span = start.to(self.prev_span());
+        if high < low {
+            return Err(Diagnostic::error("empty case range", span).with_label("this range covers no values"));
+        }
+        if high - low >= 1024 {
+            return Err(Diagnostic::error("case range is too large", span)
+                .with_label("ranges are expanded to one selector per value")
+                .with_hint("use an `if` comparison for ranges wider than 1024 values"));
+        }
+        Ok((low..=high).collect())
     }
 
-    fn parse_statement(&mut self) -> Result<AstNode, String> {
+    fn parse_case_int(&mut self) -> ParseResult<i64> {
+        let negative = self.check(&Token::Minus);
+        if negative { self.advance(); }
+        let value = match self.current() {
+            Some(Token::Number(n)) => match number_literal(n) {
+                Ok(NodeKind::LiteralInt(i)) => Some(i),
+                Ok(NodeKind::LiteralUint(u)) => Some(u as i64),
+                _ => None,
+            },
+            _ => None,
+        };
+        match value {
+            Some(value) => {
+                self.advance();
+                Ok(if negative { -value } else { value })
+            },
+            _ => Err(self.error_expected("integer case value")
+                .with_hint("case labels must be integer literals")),
+        }
+    }
+
+    fn parse_statement(&mut self) -> ParseResult<AstNode> {
+        let start = self.current_span();
         if self.check(&Token::LBrace) {
             self.advance();
             return self.parse_block();
         }
         if self.check(&Token::If) {
             self.advance();
-            self.consume(Token::LParen)?;
-            let condition = self.parse_expression()?;
-            self.consume(Token::RParen)?;
-            let then_branch = self.parse_statement()?;
+            let (condition, then_branch) = self.parse_conditional()?;
             let mut else_branch = None;
             if self.check(&Token::Else) {
                 self.advance();
                 else_branch = Some(Box::new(self.parse_statement()?));
             }
-            return Ok(AstNode::IfStmt { condition: Box::new(condition), then_branch: Box::new(then_branch), else_branch });
+            return Ok(self.node(NodeKind::IfStmt { condition: Box::new(condition), then_branch: Box::new(then_branch), else_branch }, start));
         }
         if self.check(&Token::Return) {
             self.advance();
             let expr = self.parse_expression()?;
-            self.consume(Token::Semicolon)?;
-            return Ok(AstNode::ReturnStmt(Box::new(expr)));
+            self.end_statement()?;
+            return Ok(self.node(NodeKind::ReturnStmt(Box::new(expr)), start));
         }
         if self.check(&Token::Break) {
             self.advance();
-            self.consume(Token::Semicolon)?;
-            return Ok(AstNode::BreakStmt);
+            self.end_statement()?;
+            return Ok(self.node(NodeKind::BreakStmt, start));
         }
-        if self.check(&Token::For) {
+        if self.check(&Token::Switch) {
+            self.advance();
+            return self.parse_switch(start);
+        }
+        if self.check(&Token::Continue) {
+            self.advance();
+            self.end_statement()?;
+            return Ok(self.node(NodeKind::ContinueStmt, start));
+        }
+        if self.check(&Token::While) {
             self.advance();
-            self.consume(Token::LParen)?;
-            let init = self.parse_statement()?; 
+            let (condition, body) = self.parse_conditional()?;
+            return Ok(self.node(NodeKind::WhileStmt { condition: Box::new(condition), body: Box::new(body) }, start));
+        }
+        if self.check(&Token::Do) {
+            self.advance();
+            let body = self.parse_statement()?;
+            self.consume(Token::While)
+                .map_err(|e| e.with_hint("a `do` body must be followed by `while condition`"))?;
             let condition = self.parse_expression()?;
-            self.consume(Token::Semicolon)?;
-            let increment = self.parse_expression_assignment()?;
-            self.consume(Token::RParen)?;
+            self.end_statement()?;
+            return Ok(self.node(NodeKind::DoWhileStmt { body: Box::new(body), condition: Box::new(condition) }, start));
+        }
+        if self.check(&Token::Loop) {
+            self.advance();
+            self.consume(Token::LBrace)?;
+            let body = self.parse_block()?;
+            return Ok(self.node(NodeKind::LoopStmt(Box::new(body)), start));
+        }
+        if self.check(&Token::For) {
+            self.advance();
+            // `for (init; cond; step) stmt` or paren-free `for init; cond; step { ... }`
+            let parenthesized = self.check(&Token::LParen);
+            if parenthesized { self.advance(); }
+            let header = |p: &mut Self| {
+                let init = p.parse_statement()?;
+                let condition = p.parse_expression()?;
+                p.consume(Token::Semicolon)?;
+                let increment = p.parse_expression_assignment()?;
+                Ok((init, condition, increment))
+            };
+            let (init, condition, increment) = if parenthesized { self.bracketed(header)? } else { header(self)? };
+            if parenthesized {
+                self.consume(Token::RParen)?;
+            } else if !self.check(&Token::LBrace) {
+                return Err(self.error_expected("`{`")
+                    .with_hint("without parentheses around the loop header, the body must be a `{ ... }` block"));
+            }
             let body = self.parse_statement()?;
-            return Ok(AstNode::ForStmt { init: Box::new(init), condition: Box::new(condition), increment: Box::new(increment), body: Box::new(body) });
+            return Ok(self.node(NodeKind::ForStmt { init: Box::new(init), condition: Box::new(condition), increment: Box::new(increment), body: Box::new(body) }, start));
         }
 
         // Variable Declaration
+        if self.check(&Token::Let) || self.check(&Token::Const) {
+            let binding = if self.check(&Token::Let) { BindingKind::Let } else { BindingKind::Const };
+            self.advance();
+            return self.parse_binding(binding, None, start);
+        }
         if let Some(Token::Identifier(id)) = self.current() {
             // S2L: var name : type = val;
             if id == "var" {
                 if let Some(Token::Identifier(_)) = self.peek() {
                     self.advance(); // eat var
-                    let name = match self.current() { Some(Token::Identifier(s)) => s.clone(), _ => return Err("Expected name".to_string()) };
-                    self.advance();
-                    self.consume(Token::Colon)?;
-                    let type_name = match self.current() { Some(Token::Identifier(s)) => s.clone(), _ => return Err("Expected type".to_string()) };
-                    self.advance();
-                    
-                    let mut value = None;
-                    if self.check(&Token::Equals) {
-                        self.advance();
-                        value = Some(Box::new(self.parse_expression()?));
-                    }
-                    self.consume(Token::Semicolon)?;
-                    return Ok(AstNode::VarDecl { type_name, name, value });
+                    return self.parse_binding(BindingKind::Var, None, start);
                 }
             }
-            
+
             // C-Style: Type Name = val;
             if let Some(Token::Identifier(_)) = self.peek() {
-                // If next token is Identifier, assume Type Name pattern
-                // But check it's not a function call or assignment
-                if !self.check_next(&Token::LParen) && !self.check_next(&Token::Equals) && !self.check_next(&Token::Dot) {
-                     let type_name = id.clone();
-                     self.advance();
-                     let name = match self.current() { Some(Token::Identifier(s)) => s.clone(), _ => return Err("Expected name".to_string()) };
-                     self.advance();
-                     
-                     let mut value = None;
-                     if self.check(&Token::Equals) {
-                        self.advance();
-                        value = Some(Box::new(self.parse_expression()?));
-                     }
-                     self.consume(Token::Semicolon)?;
-                     return Ok(AstNode::VarDecl { type_name, name, value });
-                }
+                return self.parse_binding(BindingKind::Var, None, start);
             }
         }
 
         // Fallback: Expr or Assignment
-        let expr = self.parse_expression()?;
+        // `-` starting a line after an unterminated one was likely meant to
+        // continue it, as C allows.
+        let continues_line = self.check(&Token::Minus) && self.at_line_break()
+            && !matches!(self.tokens.get(self.cursor - 1).map(|t| &t.token), Some(Token::Semicolon | Token::LBrace | Token::RBrace));
+        let expr = self.parse_expression_assignment()?;
+        if !matches!(expr.kind, NodeKind::Assignment { .. } | NodeKind::CompoundAssignment { .. } | NodeKind::Call { .. } | NodeKind::MethodCall { .. }) {
+            let error = Diagnostic::error("expression is not a statement", expr.span).with_label("its value is never used");
+            return Err(match continues_line {
+                true => error.with_hint("to continue an expression on the next line, end the previous line with the operator"),
+                false => error.with_hint("only calls and assignments can stand on their own"),
+            });
+        }
+        self.end_statement()?;
+        Ok(expr)
+    }
+
+    /// The condition and body of an `if`/`while`: C-style `(cond) stmt`, or
+    /// paren-free `cond { ... }`, where the body must be a block.
+    fn parse_conditional(&mut self) -> ParseResult<(AstNode, AstNode)> {
+        let parenthesized = self.check(&Token::LParen);
+        let condition = self.parse_expression()?;
+        if !parenthesized && !self.check(&Token::LBrace) {
+            return Err(self.error_expected("`{`")
+                .with_hint("without parentheses around the condition, the body must be a `{ ... }` block"));
+        }
+        let body = self.parse_statement()?;
+        Ok((condition, body))
+    }
+
+    /// The rest of a `var`/`let`/`const` declaration (keyword already consumed):
+    /// S2L `name: type = value;`, `name = value;` (type inferred) or C-style
+    /// `type name = value;`, including arrays
+    /// (`name: array<float, 3> = [..]`, `float name[3] = {..}` or `float name[] = {..}`).
+    fn parse_binding(&mut self, binding: BindingKind, doc_string: Option<String>, start: Span) -> ParseResult<AstNode> {
+        let keyword = binding.keyword();
+        let first = self.expect_ident("name")?;
+        let (mut type_name, name) = if self.check(&Token::Colon) {
+            self.advance();
+            (self.parse_type("type")?, first)
+        } else if let Some(Token::Identifier(_)) = self.current() {
+            (first, self.expect_ident("name")?)
+        } else if self.check(&Token::Equals) {
+            // `let x = value`: name resolution fills in the value's type.
+            (String::new(), first)
+        } else {
+            return Err(self.error_expected("`:` and a type, or `=`")
+                .with_hint(format!("S2L variables are declared `{0} name: type = value;` or `{0} name = value;`", keyword)));
+        };
+        let name_span = self.prev_span();
+
+        // C-style array suffix; an empty `[]` takes its size from the initializer.
+        let mut unsized_array = false;
+        if self.check(&Token::LBracket) {
+            self.advance();
+            if self.check(&Token::RBracket) {
+                unsized_array = true;
+            } else {
+                type_name = array_type(&type_name, self.parse_array_size()?);
+            }
+            self.consume(Token::RBracket)?;
+        }
+
+        let mut value = None;
         if self.check(&Token::Equals) {
             self.advance();
-            let value = self.parse_expression()?;
-            self.consume(Token::Semicolon)?;
-            return Ok(AstNode::Assignment { target: Box::new(expr), value: Box::new(value) });
+            let is_array = unsized_array || split_array_type(&type_name).is_some();
+            value = Some(Box::new(if is_array && self.check(&Token::LBrace) {
+                self.parse_brace_initializer()?
+            } else {
+                self.parse_expression()?
+            }));
+        } else if binding != BindingKind::Var {
+            return Err(Diagnostic::error(format!("`{}` binding `{}` has no value", keyword, name), name_span)
+                .with_label("needs an initializer")
+                .with_hint(format!("write `{} {}: {} = value;`, or use `var` for a mutable variable", keyword, name, type_name)));
+        }
+        self.end_statement()?;
+
+        if unsized_array {
+            match value.as_deref().map(|v| &v.kind) {
+                Some(NodeKind::ArrayLiteral(items)) => type_name = array_type(&type_name, items.len()),
+                _ => return Err(Diagnostic::error(format!("array `{}` has no size", name), name_span)
+                    .with_label("size unknown")
+                    .with_hint("give the size in the brackets, or initialize it with a list of values")),
+            }
+        }
+        if let Some((element, size)) = split_array_type(&type_name) {
+            let type_name = element.to_string();
+            return Ok(self.node(NodeKind::ArrayDecl { binding, type_name, name, size, value, doc_string, public: false }, start));
+        }
+        Ok(self.node(NodeKind::VarDecl { binding, type_name, name, value, doc_string, public: false }, start))
+    }
+
+    /// Call arguments up to and including `)`. Arguments written `name: value`
+    /// come back with their name; they construct a struct.
+    fn parse_call_args(&mut self) -> ParseResult<Vec<(Option<String>, AstNode)>> {
+        let mut args = Vec::new();
+        while !self.check(&Token::RParen) {
+            let mut name = None;
+            if let (Some(Token::Identifier(id)), Some(Token::Colon)) = (self.current(), self.peek()) {
+                name = Some(id.clone());
+                self.advance();
+                self.advance();
+            }
+            args.push((name, self.parse_expression()?));
+            if self.check(&Token::Comma) { self.advance(); }
+            else if !self.check(&Token::RParen) {
+                return Err(self.error_expected("`,` or `)`")
+                    .with_hint("separate call arguments with `,`"));
+            }
+        }
+        self.consume(Token::RParen)?;
+        Ok(args)
+    }
+
+    /// The fields of a struct constructed with named arguments, or `None` for
+    /// a call, whose arguments are left in `args`. Mixing the two is an error.
+    fn named_fields(&self, args: &mut Vec<(Option<String>, AstNode)>, start: Span) -> ParseResult<Option<Vec<(String, AstNode)>>> {
+        match args.iter().filter(|(n, _)| n.is_some()).count() {
+            0 => Ok(None),
+            named if named == args.len() => Ok(Some(std::mem::take(args).into_iter().map(|(n, a)| (n.unwrap_or_default(), a)).collect())),
+            _ => Err(Diagnostic::error("cannot mix named and positional arguments", start.to(self.prev_span()))
+                .with_label("some arguments are named and some are not")
+                .with_hint("name every field, e.g. `Material(color: c, rough: 0.2)`, or none")),
+        }
+    }
+
+    /// A type name: `float`, `vec3`, a struct name, or `array<type, size>`.
+    fn parse_type(&mut self, what: &str) -> ParseResult<String> {
+        let mut name = self.expect_ident(what)?;
+        // A type from an imported module: `sdf.Hit`.
+        while self.check(&Token::Dot) && matches!(self.peek(), Some(Token::Identifier(_))) {
+            self.advance();
+            name = format!("{}.{}", name, self.expect_ident(what)?);
+        }
+        if name != "array" || !self.check(&Token::Less) {
+            return Ok(name);
+        }
+        self.advance();
+        let element = self.parse_type("array element type")?;
+        self.consume(Token::Comma)
+            .map_err(|e| e.with_hint("array types are written `array<type, size>`"))?;
+        let size = self.parse_array_size()?;
+        self.consume(Token::Greater)?;
+        Ok(array_type(&element, size))
+    }
+
+    /// An optional C-style `[size]` after a parameter or field name.
+    fn parse_array_suffix(&mut self, type_name: String) -> ParseResult<String> {
+        if !self.check(&Token::LBracket) {
+            return Ok(type_name);
+        }
+        self.advance();
+        let size = self.parse_array_size()?;
+        self.consume(Token::RBracket)?;
+        Ok(array_type(&type_name, size))
+    }
+
+    fn parse_array_size(&mut self) -> ParseResult<usize> {
+        let size = match self.current() {
+            Some(Token::Number(n)) => match number_literal(n) {
+                Ok(NodeKind::LiteralInt(i)) if i > 0 => Some(i as usize),
+                Ok(NodeKind::LiteralUint(u)) if u > 0 => Some(u as usize),
+                _ => None,
+            },
+            _ => None,
+        };
+        match size {
+            Some(size) => { self.advance(); Ok(size) },
+            None => Err(self.error_expected("array size")
+                .with_hint("array sizes must be positive integer literals")),
         }
-        self.consume(Token::Semicolon)?;
-        Ok(expr)
     }
 
-    fn check_next(&self, token: &Token) -> bool {
-        self.peek() == Some(token)
+    /// A C-style `{a, b, c}` array initializer.
+    fn parse_brace_initializer(&mut self) -> ParseResult<AstNode> {
+        let start = self.current_span();
+        self.consume(Token::LBrace)?;
+        let items = self.bracketed(|p| p.parse_list(Token::RBrace, "array element"))?;
+        Ok(self.node(NodeKind::ArrayLiteral(items), start))
     }
 
-    fn parse_expression_assignment(&mut self) -> Result<AstNode, String> {
+    /// Comma-separated expressions up to and including `close`; a trailing comma is allowed.
+    fn parse_list(&mut self, close: Token, what: &str) -> ParseResult<Vec<AstNode>> {
+        let mut items = Vec::new();
+        while !self.check(&close) {
+            items.push(self.parse_expression()?);
+            if self.check(&Token::Comma) { self.advance(); }
+            else if !self.check(&close) {
+                return Err(self.error_expected(&format!("`,` or {}", close))
+                    .with_hint(format!("separate each {} with `,`", what)));
+            }
+        }
+        self.consume(close)?;
+        Ok(items)
+    }
+
+    /// An expression, optionally followed by `= value` or a compound `op= value`.
+    fn parse_expression_assignment(&mut self) -> ParseResult<AstNode> {
+        let start = self.current_span();
         let expr = self.parse_expression()?;
         if self.check(&Token::Equals) {
             self.advance();
             let value = self.parse_expression()?;
-            return Ok(AstNode::Assignment { target: Box::new(expr), value: Box::new(value) });
+            return Ok(self.node(NodeKind::Assignment { target: Box::new(expr), value: Box::new(value) }, start));
+        }
+        let compound = match self.current() {
+            Some(Token::PlusEquals) => Some(BinaryOperator::Add),
+            Some(Token::MinusEquals) => Some(BinaryOperator::Sub),
+            Some(Token::StarEquals) => Some(BinaryOperator::Mul),
+            Some(Token::SlashEquals) => Some(BinaryOperator::Div),
+            Some(Token::PercentEquals) => Some(BinaryOperator::Mod),
+            Some(Token::AmpersandEquals) => Some(BinaryOperator::BitAnd),
+            Some(Token::PipeEquals) => Some(BinaryOperator::BitOr),
+            Some(Token::CaretEquals) => Some(BinaryOperator::BitXor),
+            Some(Token::ShiftLeftEquals) => Some(BinaryOperator::ShiftLeft),
+            Some(Token::ShiftRightEquals) => Some(BinaryOperator::ShiftRight),
+            _ => None,
+        };
+        if let Some(op) = compound {
+            self.advance();
+            let value = self.parse_expression()?;
+            return Ok(self.node(NodeKind::CompoundAssignment { target: Box::new(expr), op, value: Box::new(value) }, start));
         }
         Ok(expr)
     }
 
-    fn parse_expression(&mut self) -> Result<AstNode, String> { self.parse_comparison() }
-    
-    fn parse_comparison(&mut self) -> Result<AstNode, String> {
-        let mut left = self.parse_math()?;
-        loop {
-            let op = match self.current() {
-                Some(Token::Greater) => BinaryOperator::Greater,
-                Some(Token::Less) => BinaryOperator::Less,
-                Some(Token::DoubleEquals) => BinaryOperator::Equal,
-                Some(Token::LessEqual) => BinaryOperator::LessEqual,       
-                Some(Token::GreaterEqual) => BinaryOperator::GreaterEqual,
-                _ => break,
-            };
-            self.advance();
-            let right = self.parse_math()?;
-            left = AstNode::BinaryOp { left: Box::new(left), op, right: Box::new(right) };
+    fn parse_expression(&mut self) -> ParseResult<AstNode> { self.parse_ternary() }
+
+    /// `cond ? a : b`, binding loosest and associating to the right.
+    fn parse_ternary(&mut self) -> ParseResult<AstNode> {
+        let start = self.current_span();
+        let condition = self.parse_logical_or()?;
+        if !self.check(&Token::Question) || self.at_line_break() {
+            return Ok(condition);
         }
-        Ok(left)
+        self.advance();
+        let then_expr = self.parse_expression()?;
+        self.consume(Token::Colon)
+            .map_err(|e| e.with_hint("a conditional expression needs both branches: `cond ? a : b`"))?;
+        let else_expr = self.parse_ternary()?;
+        Ok(self.node(NodeKind::Ternary { condition: Box::new(condition), then_expr: Box::new(then_expr), else_expr: Box::new(else_expr), result_type: String::new() }, start))
     }
 
-    fn parse_math(&mut self) -> Result<AstNode, String> {
-        let mut left = self.parse_term()?;
-        loop {
-            let op = match self.current() {
-                Some(Token::Plus) => BinaryOperator::Add,
-                Some(Token::Minus) => BinaryOperator::Sub,
-                _ => break,
-            };
+    /// Parses one left-associative precedence level: `next (op next)*`.
+    fn parse_binary_level(
+        &mut self,
+        next: fn(&mut Self) -> ParseResult<AstNode>,
+        op_for: fn(&Token) -> Option<BinaryOperator>,
+    ) -> ParseResult<AstNode> {
+        let start = self.current_span();
+        let mut left = next(self)?;
+        while let Some(op) = self.current().and_then(op_for) {
+            // An operator starting a line begins a new statement; to continue
+            // an expression, end the line with the operator instead.
+            if self.at_line_break() { break; }
             self.advance();
-            let right = self.parse_term()?;
-            left = AstNode::BinaryOp { left: Box::new(left), op, right: Box::new(right) };
+            let right = next(self)?;
+            left = self.node(NodeKind::BinaryOp { left: Box::new(left), op, right: Box::new(right) }, start);
         }
         Ok(left)
     }
 
-    fn parse_term(&mut self) -> Result<AstNode, String> {
-        let mut left = self.parse_unary()?;
-        loop {
-            let op = match self.current() {
-                Some(Token::Star) => BinaryOperator::Mul,
-                Some(Token::Slash) => BinaryOperator::Div,
-                _ => break,
-            };
-            self.advance();
-            let right = self.parse_unary()?;
-            left = AstNode::BinaryOp { left: Box::new(left), op, right: Box::new(right) };
-        }
-        Ok(left)
+    // Precedence, loosest to tightest (C/GLSL order):
+    // `?:`, `||`, `&&`, `|`, `^`, `&`, `== !=`, `< > <= >=`, `<< >>`, `+ -`, `* / %`, unary.
+
+    fn parse_logical_or(&mut self) -> ParseResult<AstNode> {
+        self.parse_binary_level(Self::parse_logical_and, |t| match t {
+            Token::OrOr => Some(BinaryOperator::Or),
+            _ => None,
+        })
+    }
+
+    fn parse_logical_and(&mut self) -> ParseResult<AstNode> {
+        self.parse_binary_level(Self::parse_bit_or, |t| match t {
+            Token::AndAnd => Some(BinaryOperator::And),
+            _ => None,
+        })
+    }
+
+    fn parse_bit_or(&mut self) -> ParseResult<AstNode> {
+        self.parse_binary_level(Self::parse_bit_xor, |t| match t {
+            Token::Pipe => Some(BinaryOperator::BitOr),
+            _ => None,
+        })
+    }
+
+    fn parse_bit_xor(&mut self) -> ParseResult<AstNode> {
+        self.parse_binary_level(Self::parse_bit_and, |t| match t {
+            Token::Caret => Some(BinaryOperator::BitXor),
+            _ => None,
+        })
+    }
+
+    fn parse_bit_and(&mut self) -> ParseResult<AstNode> {
+        self.parse_binary_level(Self::parse_equality, |t| match t {
+            Token::Ampersand => Some(BinaryOperator::BitAnd),
+            _ => None,
+        })
+    }
+
+    fn parse_equality(&mut self) -> ParseResult<AstNode> {
+        self.parse_binary_level(Self::parse_comparison, |t| match t {
+            Token::DoubleEquals => Some(BinaryOperator::Equal),
+            Token::BangEquals => Some(BinaryOperator::NotEqual),
+            _ => None,
+        })
+    }
+
+    fn parse_comparison(&mut self) -> ParseResult<AstNode> {
+        self.parse_binary_level(Self::parse_shift, |t| match t {
+            Token::Greater => Some(BinaryOperator::Greater),
+            Token::Less => Some(BinaryOperator::Less),
+            Token::LessEqual => Some(BinaryOperator::LessEqual),
+            Token::GreaterEqual => Some(BinaryOperator::GreaterEqual),
+            _ => None,
+        })
+    }
+
+    fn parse_shift(&mut self) -> ParseResult<AstNode> {
+        self.parse_binary_level(Self::parse_math, |t| match t {
+            Token::ShiftLeft => Some(BinaryOperator::ShiftLeft),
+            Token::ShiftRight => Some(BinaryOperator::ShiftRight),
+            _ => None,
+        })
+    }
+
+    fn parse_math(&mut self) -> ParseResult<AstNode> {
+        self.parse_binary_level(Self::parse_term, |t| match t {
+            Token::Plus => Some(BinaryOperator::Add),
+            Token::Minus => Some(BinaryOperator::Sub),
+            _ => None,
+        })
     }
 
-    fn parse_unary(&mut self) -> Result<AstNode, String> {
+    fn parse_term(&mut self) -> ParseResult<AstNode> {
+        self.parse_binary_level(Self::parse_unary, |t| match t {
+            Token::Star => Some(BinaryOperator::Mul),
+            Token::Slash => Some(BinaryOperator::Div),
+            Token::Percent => Some(BinaryOperator::Mod),
+            _ => None,
+        })
+    }
+
+    fn parse_unary(&mut self) -> ParseResult<AstNode> {
+        let start = self.current_span();
         if self.check(&Token::Minus) {
             self.advance();
             let right = self.parse_unary()?;
-            return Ok(AstNode::UnaryOp { op: UnaryOperator::Negate, right: Box::new(right) });
+            return Ok(self.node(NodeKind::UnaryOp { op: UnaryOperator::Negate, right: Box::new(right) }, start));
         }
         if self.check(&Token::Bang) {
             self.advance();
             let right = self.parse_unary()?;
-            return Ok(AstNode::UnaryOp { op: UnaryOperator::Not, right: Box::new(right) });
+            return Ok(self.node(NodeKind::UnaryOp { op: UnaryOperator::Not, right: Box::new(right) }, start));
+        }
+        if self.check(&Token::Tilde) {
+            self.advance();
+            let right = self.parse_unary()?;
+            return Ok(self.node(NodeKind::UnaryOp { op: UnaryOperator::BitNot, right: Box::new(right) }, start));
         }
         self.parse_postfix()
     }
 
-    fn parse_postfix(&mut self) -> Result<AstNode, String> {
+    fn parse_postfix(&mut self) -> ParseResult<AstNode> {
+        let start = self.current_span();
         let mut expr = self.parse_primary()?;
         loop {
-            if self.check(&Token::LParen) {
+            // `(` and `[` on a new line start a new statement rather than a call or index.
+            if self.check(&Token::LParen) && !self.at_line_break() {
                 self.advance();
-                let mut args = Vec::new();
-                while !self.check(&Token::RParen) {
-                    args.push(self.parse_expression()?);
-                    if self.check(&Token::Comma) { self.advance(); }
-                }
-                self.consume(Token::RParen)?;
-                if let AstNode::Variable(name) = expr {
-                    expr = AstNode::Call { func_name: name, args };
+                let mut args = self.bracketed(|p| p.parse_call_args())?;
+                if let NodeKind::Variable(name) = expr.kind {
+                    expr = match self.named_fields(&mut args, start)? {
+                        Some(fields) => self.node(NodeKind::StructLiteral { name, fields }, start),
+                        None => {
+                            let args = args.into_iter().map(|(_, a)| a).collect();
+                            self.node(NodeKind::Call { func_name: name, args, overload: None, arg_types: Vec::new() }, start)
+                        },
+                    };
                 } else {
-                    return Err("Expected identifier before call".to_string());
+                    return Err(Diagnostic::error("expected identifier before call", expr.span)
+                        .with_label("this expression cannot be called")
+                        .with_hint("only named functions can be called, e.g. `length(v)`"));
                 }
             } else if self.check(&Token::Dot) {
                 self.advance();
-                let member = match self.current() { Some(Token::Identifier(s)) => s.clone(), _ => return Err("Expected member".to_string()) };
-                self.advance();
-                expr = AstNode::MemberAccess { base: Box::new(expr), member };
-            } else if self.check(&Token::LBracket) {
+                let member = self.expect_ident("member name after `.`")?;
+                if self.check(&Token::LParen) && !self.at_line_break() {
+                    self.advance();
+                    let mut args = self.bracketed(|p| p.parse_call_args())?;
+                    let module = match &expr.kind { NodeKind::Variable(m) => Some(m.clone()), _ => None };
+                    expr = match (self.named_fields(&mut args, start)?, module) {
+                        // `sdf.Hit(d: 1.0)`: a struct from module `sdf`, found when modules are linked.
+                        (Some(fields), Some(module)) => self.node(NodeKind::StructLiteral { name: format!("{}.{}", module, member), fields }, start),
+                        (Some(_), None) => {
+                            return Err(Diagnostic::error(format!("named arguments in a call to method `{}`", member), start.to(self.prev_span()))
+                                .with_label("methods take positional arguments")
+                                .with_hint("named arguments can only be used to construct a struct, e.g. `sdf.Hit(d: 1.0)`"));
+                        },
+                        (None, _) => {
+                            let args = args.into_iter().map(|(_, a)| a).collect();
+                            self.node(NodeKind::MethodCall { receiver: Box::new(expr), method: member, args, receiver_type: String::new() }, start)
+                        },
+                    };
+                } else {
+                    expr = self.node(NodeKind::MemberAccess { base: Box::new(expr), member }, start);
+                }
+            } else if self.check(&Token::LBracket) && !self.at_line_break() {
                 self.advance();
-                let index = self.parse_expression()?;
-                self.consume(Token::RBracket)?;
-                expr = AstNode::SubscriptAccess { base: Box::new(expr), index: Box::new(index) };
+                let index = self.bracketed(|p| {
+                    let index = p.parse_expression()?;
+                    p.consume(Token::RBracket)?;
+                    Ok(index)
+                })?;
+                expr = self.node(NodeKind::SubscriptAccess { base: Box::new(expr), index: Box::new(index) }, start);
             } else {
                 break;
             }
@@ -362,23 +1074,397 @@ impl Parser {
         Ok(expr)
     }
 
-    fn parse_primary(&mut self) -> Result<AstNode, String> {
+    fn parse_primary(&mut self) -> ParseResult<AstNode> {
+        let start = self.current_span();
         match self.current() {
             Some(Token::Number(s)) => {
-                let n = s.clone();
+                let kind = number_literal(s).map_err(|msg| Diagnostic::error(msg, start)
+                    .with_label("invalid literal"))?;
+                self.advance();
+                Ok(self.node(kind, start))
+            },
+            Some(Token::True) => { self.advance(); Ok(self.node(NodeKind::LiteralBool(true), start)) },
+            Some(Token::False) => { self.advance(); Ok(self.node(NodeKind::LiteralBool(false), start)) },
+            Some(Token::Identifier(s)) => { let n = s.clone(); self.advance(); Ok(self.node(NodeKind::Variable(n), start)) },
+            Some(Token::LBracket) => {
                 self.advance();
-                if n.contains('.') { Ok(AstNode::LiteralFloat(n.parse().unwrap_or(0.0))) }
-                else { Ok(AstNode::LiteralInt(n.parse().unwrap_or(0))) }
+                let items = self.bracketed(|p| p.parse_list(Token::RBracket, "array element"))?;
+                Ok(self.node(NodeKind::ArrayLiteral(items), start))
             },
-            Some(Token::Identifier(s)) => { let n = s.clone(); self.advance(); Ok(AstNode::Variable(n)) },
             Some(Token::LParen) => {
                 self.advance();
-                let expr = self.parse_expression()?;
-                self.consume(Token::RParen)?;
-                Ok(expr)
+                self.bracketed(|p| {
+                    let expr = p.parse_expression()?;
+                    p.consume(Token::RParen)?;
+                    Ok(expr)
+                })
+            },
+            Some(t) if self.at_line_break() && BINARY_OPERATORS.contains(t) => Err(self.error_expected("expression")
+                .with_hint("to continue an expression on the next line, end the previous line with the operator")),
+            _ => Err(self.error_expected("expression")),
+        }
+    }
+}
+
+/// Tokens that can only continue an expression, never start one.
+const BINARY_OPERATORS: &[Token] = &[
+    Token::Plus, Token::Star, Token::Slash, Token::Percent, Token::OrOr, Token::AndAnd,
+    Token::Pipe, Token::Caret, Token::Ampersand, Token::DoubleEquals, Token::BangEquals,
+    Token::Less, Token::Greater, Token::LessEqual, Token::GreaterEqual,
+    Token::ShiftLeft, Token::ShiftRight, Token::Question,
+];
+
+/// Turns the text of a `Token::Number` into a typed literal.
+fn number_literal(text: &str) -> Result<NodeKind, String> {
+    let lower = text.to_ascii_lowercase();
+    let is_hex = lower.starts_with("0x");
+    let is_float = !is_hex && (lower.contains('.') || lower.contains('e') || lower.ends_with('f'));
+
+    if is_float {
+        if lower.ends_with('u') {
+            return Err(format!("float literal `{}` cannot have a `u` suffix", text));
+        }
+        let mut digits = lower.trim_end_matches('f').to_string();
+        if digits.starts_with('.') { digits.insert(0, '0'); }
+        if digits.ends_with('.') { digits.push('0'); }
+        if !digits.contains('.') && !digits.contains('e') { digits.push_str(".0"); }
+        let value: f64 = digits.parse().map_err(|_| format!("invalid float literal `{}`", text))?;
+        return Ok(NodeKind::LiteralFloat { value, text: digits });
+    }
+
+    let unsigned = lower.ends_with('u');
+    let digits = lower.trim_end_matches('u');
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => digits.parse::<u64>(),
+    }.map_err(|_| format!("integer literal `{}` is too large", text))?;
+
+    if unsigned {
+        if value > u32::MAX as u64 { return Err(format!("integer literal `{}` does not fit in a uint", text)); }
+        Ok(NodeKind::LiteralUint(value))
+    } else {
+        if value > i32::MAX as u64 { return Err(format!("integer literal `{}` does not fit in an int; add a `u` suffix for uint", text)); }
+        Ok(NodeKind::LiteralInt(value as i64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+    use crate::source_map::SourceMap;
+
+    fn parse_src(src: &str) -> Result<AstNode, Vec<Diagnostic>> {
+        let (tokens, errors) = tokenize(&SourceMap::single("t.sumi", src));
+        assert!(errors.is_empty());
+        Parser::new(tokens).parse()
+    }
+
+    fn first_error(src: &str) -> Diagnostic {
+        parse_src(src).unwrap_err().remove(0)
+    }
+
+    #[test]
+    fn test_node_spans() {
+        let ast = parse_src("fn f() float {\n    return a + b;\n}").unwrap();
+        let NodeKind::Program(items) = &ast.kind else { panic!("Expected Program") };
+        let NodeKind::FunctionDecl { body, .. } = &items[0].kind else { panic!("Expected FunctionDecl") };
+        let NodeKind::Block(stmts) = &body.kind else { panic!("Expected Block") };
+        let NodeKind::ReturnStmt(expr) = &stmts[0].kind else { panic!("Expected ReturnStmt") };
+
+        assert_eq!((stmts[0].span.line, stmts[0].span.column), (2, 5));
+        assert_eq!((expr.span.line, expr.span.column), (2, 12));
+        assert_eq!(expr.span.end - expr.span.start, "a + b".len());
+    }
+
+    #[test]
+    fn test_missing_semicolon_points_after_statement() {
+        let err = first_error("fn f() float {\n    float x = 1.0 return x;\n}");
+        assert_eq!(err.message, "expected `;` or a new line, found `return`");
+        assert_eq!((err.span.line, err.span.column), (2, 18));
+        assert!(err.hint.is_some());
+    }
+
+    #[test]
+    fn test_newline_terminated_statements() {
+        let src = "struct Hit {\n    float t\n    vec3 n\n}\n\
+                   fn f(a: float, b: float) float {\n\
+                       var x: float = a +\n           b\n\
+                       let y: float = max(\n        x,\n        1.0)\n\
+                       if x < y { x = y }\n\
+                       while x > 1.0 {\n        x *= 0.5\n    }\n\
+                       for var i: int = 0; i < 4; i += 1 { x += 1.0 }\n\
+                       if (x > 2.0) x = 2.0;\n\
+                       return x\n\
+                   }";
+        let ast = parse_src(src).expect("paren-free, semicolon-free code should parse");
+        let NodeKind::Program(items) = &ast.kind else { panic!() };
+        let NodeKind::FunctionDecl { body, .. } = &items[1].kind else { panic!() };
+        let NodeKind::Block(stmts) = &body.kind else { panic!() };
+        assert_eq!(stmts.len(), 7);
+        assert_eq!(shape(match &stmts[0].kind { NodeKind::VarDecl { value: Some(v), .. } => v, _ => panic!() }), "(a Add b)");
+        assert!(matches!(&stmts[2].kind, NodeKind::IfStmt { .. }));
+    }
+
+    #[test]
+    fn test_leading_operator_starts_new_statement() {
+        let err = first_error("fn f(a: float) float {\n    var x: float = a\n        + 1.0\n    return x\n}");
+        assert_eq!(err.message, "expected expression, found `+`");
+        assert_eq!(err.span.line, 3);
+        assert!(err.hint.unwrap().contains("end the previous line with the operator"));
+
+        let err = first_error("fn f(a: float, b: float) float {\n    float d = a\n        - b;\n    return d;\n}");
+        assert_eq!(err.message, "expression is not a statement");
+        assert_eq!(err.span.line, 3);
+        assert!(err.hint.unwrap().contains("end the previous line with the operator"));
+        let err = first_error("fn f(a: float) { a; a + 1.0; }");
+        assert_eq!((err.message.as_str(), err.hint.as_deref()), ("expression is not a statement", Some("only calls and assignments can stand on their own")));
+
+        let err = first_error("fn f(a: float) { if a < 1.0 a = 1.0; }");
+        assert_eq!(err.message, "expected `{`, found identifier `a`");
+    }
+
+    #[test]
+    fn test_unexpected_token_message() {
+        let err = first_error("fn f(a: float b: float) {}");
+        assert_eq!(err.message, "expected `,` or `)`, found identifier `b`");
+        assert_eq!((err.span.line, err.span.column), (1, 15));
+    }
+
+    /// Renders an expression fully parenthesized so precedence is visible.
+    fn shape(node: &AstNode) -> String {
+        match &node.kind {
+            NodeKind::BinaryOp { left, op, right } => format!("({} {:?} {})", shape(left), op, shape(right)),
+            NodeKind::UnaryOp { op, right } => format!("({:?} {})", op, shape(right)),
+            NodeKind::Ternary { condition, then_expr, else_expr, .. } => {
+                format!("({} ? {} : {})", shape(condition), shape(then_expr), shape(else_expr))
             },
-            Some(t) => Err(format!("Unexpected token: {:?}", t)),
-            None => Err("Unexpected EOF".to_string()),
+            NodeKind::Variable(n) => n.clone(),
+            NodeKind::LiteralInt(i) => i.to_string(),
+            NodeKind::LiteralFloat { text, .. } => text.clone(),
+            other => format!("{:?}", other),
         }
     }
+
+    fn parse_expr(src: &str) -> AstNode {
+        let (tokens, _) = tokenize(&SourceMap::single("t.sumi", src));
+        Parser::new(tokens).parse_expression().unwrap()
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        assert_eq!(shape(&parse_expr("a || b && c != d")), "(a Or (b And (c NotEqual d)))");
+        assert_eq!(shape(&parse_expr("a | b ^ c & d")), "(a BitOr (b BitXor (c BitAnd d)))");
+        assert_eq!(shape(&parse_expr("a < b << 1 + c % 2")), "(a Less (b ShiftLeft (1 Add (c Mod 2))))");
+        assert_eq!(shape(&parse_expr("~a & -b")), "((BitNot a) BitAnd (Negate b))");
+        assert_eq!(shape(&parse_expr("a < b ? c : d ? e : f")), "((a Less b) ? c : (d ? e : f))");
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let ast = parse_src("fn f() { t += d * 2.0; }").unwrap();
+        let NodeKind::Program(items) = &ast.kind else { panic!("Expected Program") };
+        let NodeKind::FunctionDecl { body, .. } = &items[0].kind else { panic!("Expected FunctionDecl") };
+        let NodeKind::Block(stmts) = &body.kind else { panic!("Expected Block") };
+        assert!(matches!(&stmts[0].kind, NodeKind::CompoundAssignment { op: BinaryOperator::Add, .. }));
+    }
+
+    #[test]
+    fn test_loop_statements() {
+        let ast = parse_src("fn f() {\n\
+                             while (t < 1.0) { t += 0.1; continue; }\n\
+                             do { t -= 0.1; } while (t > 0.0);\n\
+                             loop { break; }\n}").unwrap();
+        let NodeKind::Program(items) = &ast.kind else { panic!("Expected Program") };
+        let NodeKind::FunctionDecl { body, .. } = &items[0].kind else { panic!("Expected FunctionDecl") };
+        let NodeKind::Block(stmts) = &body.kind else { panic!("Expected Block") };
+        assert!(matches!(&stmts[0].kind, NodeKind::WhileStmt { .. }));
+        assert!(matches!(&stmts[1].kind, NodeKind::DoWhileStmt { .. }));
+        assert!(matches!(&stmts[2].kind, NodeKind::LoopStmt(_)));
+    }
+
+    #[test]
+    fn test_typed_literals() {
+        let float = |text: &str, value: f64| NodeKind::LiteralFloat { value, text: text.to_string() };
+        assert_eq!(parse_expr("1e-3").kind, float("1e-3", 1e-3));
+        assert_eq!(parse_expr(".5").kind, float("0.5", 0.5));
+        assert_eq!(parse_expr("1.").kind, float("1.0", 1.0));
+        assert_eq!(parse_expr("1.0f").kind, float("1.0", 1.0));
+        assert_eq!(parse_expr("2F").kind, float("2.0", 2.0));
+        assert_eq!(parse_expr("0xFF").kind, NodeKind::LiteralInt(255));
+        assert_eq!(parse_expr("3u").kind, NodeKind::LiteralUint(3));
+        assert_eq!(parse_expr("false").kind, NodeKind::LiteralBool(false));
+
+        assert_eq!(first_error("fn f() { x = 1.5u; }").message, "float literal `1.5u` cannot have a `u` suffix");
+        assert!(first_error("fn f() { x = 4294967296; }").message.contains("does not fit in an int"));
+    }
+
+    #[test]
+    fn test_let_and_const_bindings() {
+        let ast = parse_src("const PI: float = 3.14159;\n\
+                             const float TAU = 6.28318;\n\
+                             fn f() float { let r: float = PI; const k: float = 2.0; var t: float; return r * k; }").unwrap();
+        let NodeKind::Program(items) = &ast.kind else { panic!("Expected Program") };
+        assert!(matches!(&items[0].kind, NodeKind::VarDecl { binding: BindingKind::Const, name, type_name, .. } if name == "PI" && type_name == "float"));
+        assert!(matches!(&items[1].kind, NodeKind::VarDecl { binding: BindingKind::Const, name, type_name, .. } if name == "TAU" && type_name == "float"));
+
+        let NodeKind::FunctionDecl { body, .. } = &items[2].kind else { panic!("Expected FunctionDecl") };
+        let NodeKind::Block(stmts) = &body.kind else { panic!("Expected Block") };
+        let bindings: Vec<_> = stmts.iter().filter_map(|s| match &s.kind {
+            NodeKind::VarDecl { binding, .. } => Some(*binding),
+            _ => None,
+        }).collect();
+        assert_eq!(bindings, vec![BindingKind::Let, BindingKind::Const, BindingKind::Var]);
+    }
+
+    #[test]
+    fn test_array_declarations() {
+        let ast = parse_src("const W: array<float, 3> = [1.0, 2.0, 3.0];\n\
+                             fn f(float taps[2]) array<int, 2> { int k[] = {1, 2,}; var z: array<vec2, 4>; return k; }").unwrap();
+        let NodeKind::Program(items) = &ast.kind else { panic!() };
+        assert!(matches!(&items[0].kind, NodeKind::ArrayDecl { binding: BindingKind::Const, type_name, size: 3, value: Some(_), .. } if type_name == "float"));
+        let NodeKind::FunctionDecl { args, return_type, body, .. } = &items[1].kind else { panic!() };
+        assert_eq!(args[0].type_name, "array<float, 2>");
+        assert_eq!(return_type, "array<int, 2>");
+        let NodeKind::Block(stmts) = &body.kind else { panic!() };
+        assert!(matches!(&stmts[0].kind, NodeKind::ArrayDecl { size: 2, value: Some(v), .. } if matches!(&v.kind, NodeKind::ArrayLiteral(items) if items.len() == 2)));
+        assert!(matches!(&stmts[1].kind, NodeKind::ArrayDecl { size: 4, value: None, .. }));
+
+        assert_eq!(first_error("fn f() { float k[]; }").message, "array `k` has no size");
+        assert_eq!(first_error("fn f() { var k: array<float, 0>; }").message, "expected array size, found number `0`");
+    }
+
+    #[test]
+    fn test_structs_and_constructors() {
+        let src = "struct Material {\n    color: vec3\n    rough: float;\n    float ior\n}\n\
+                   fn f(c: vec3) {\n\
+                       let a: Material = Material(rough: 0.2, ior: 1.5, color: c)\n\
+                       let b: Material = Material(c, 0.5, 1.0)\n\
+                   }";
+        let ast = parse_src(src).unwrap();
+        let literals: Vec<Vec<String>> = {
+            let mut found = Vec::new();
+            ast.walk(&mut |n| if let NodeKind::StructLiteral { fields, .. } = &n.kind {
+                found.push(fields.iter().map(|(f, v)| format!("{}={}", f, shape(v))).collect());
+            });
+            found
+        };
+        assert_eq!(literals, vec![vec!["color=c", "rough=0.2", "ior=1.5"], vec!["color=c", "rough=0.5", "ior=1.0"]]);
+
+        let errors = parse_src("struct S { a: float, b: float }\n\
+                                fn f() { x = S(a: 1.0); y = S(1.0); z = S(a: 1.0, c: 2.0, b: 0.0); w = T(a: 1.0); }").unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "missing `b` in `S`",
+            "`S` has 2 fields but 1 value was given",
+            "struct `S` has no field `c`",
+            "unknown struct `T`",
+        ]);
+    }
+
+    #[test]
+    fn test_impl_blocks_and_method_calls() {
+        let ast = parse_src("struct Ray { o: vec3, d: vec3 }\n\
+                             impl Ray {\n    /// Point along the ray.\n    fn at(self, t: float) vec3 { return self.o + self.d * t }\n}\n\
+                             fn f(r: Ray) float { return r.at(1.0).x }").unwrap();
+        let NodeKind::Program(items) = &ast.kind else { panic!() };
+        let NodeKind::ImplBlock { type_name, methods } = &items[1].kind else { panic!() };
+        assert_eq!(type_name, "Ray");
+        let NodeKind::FunctionDecl { args, doc_string, .. } = &methods[0].kind else { panic!() };
+        assert_eq!((args[0].type_name.as_str(), args[0].name.as_str()), ("Ray", "self"));
+        assert_eq!(doc_string.as_deref(), Some("Point along the ray."));
+        let mut calls = Vec::new();
+        items[2].walk(&mut |n| if let NodeKind::MethodCall { method, receiver_type, .. } = &n.kind {
+            calls.push(format!("{}.{}", receiver_type, method));
+        });
+        assert_eq!(calls, vec!["Ray.at"]);
+
+        let messages = |src: &str| parse_src(src).unwrap_err().into_iter().map(|e| e.message).collect::<Vec<_>>();
+        assert_eq!(messages("fn f(self) {}"), vec!["`self` parameter outside of an `impl` block"]);
+        assert_eq!(messages("struct S { a: float }\nimpl S { fn g(t: float) {} }"), vec!["method `g` has no `self` parameter"]);
+        assert_eq!(messages("struct S { a: float }\nfn f(s: S) { s.go(); x.go(); }"),
+                   vec!["no method `go` on type `S`", "cannot tell which type's `go` method to call"]);
+    }
+
+    #[test]
+    fn test_imports_and_pub() {
+        let ast = parse_src("import noise\nimport shapes.sdf.{sdBox, Hit}\npub fn f(h: sdf.Hit) float { return 1.0 }\npub const K = 1.0\nstruct S { a: float }").unwrap();
+        let NodeKind::Program(items) = &ast.kind else { panic!() };
+        assert_eq!(items[0].kind, NodeKind::Import { path: vec!["noise".into()], items: None });
+        assert_eq!(items[1].kind, NodeKind::Import { path: vec!["shapes".into(), "sdf".into()], items: Some(vec!["sdBox".into(), "Hit".into()]) });
+        let NodeKind::FunctionDecl { args, public: true, .. } = &items[2].kind else { panic!("{:?}", items[2]) };
+        assert_eq!(args[0].type_name, "sdf.Hit");
+        assert!(matches!(items[3].kind, NodeKind::VarDecl { public: true, .. }));
+        assert!(matches!(items[4].kind, NodeKind::StructDecl { public: false, .. }));
+        assert_eq!(first_error("struct S { a: float }\npub impl S { fn g(self) {} }").message, "`pub` cannot be used on an `impl` block");
+    }
+
+    #[test]
+    fn test_param_qualifiers() {
+        let ast = parse_src("fn f(x: float, out n: vec3, inout float t, in vec2 uv, out: int) {}").unwrap();
+        let NodeKind::Program(items) = &ast.kind else { panic!() };
+        let NodeKind::FunctionDecl { args, .. } = &items[0].kind else { panic!() };
+        let params: Vec<_> = args.iter().map(|a| (a.name.as_str(), a.qualifier)).collect();
+        assert_eq!(params, vec![("x", ParamQualifier::In), ("n", ParamQualifier::Out), ("t", ParamQualifier::InOut),
+                                ("uv", ParamQualifier::In), ("out", ParamQualifier::In)]);
+    }
+
+    #[test]
+    fn test_let_requires_value() {
+        let err = first_error("fn f() { let x: float; }");
+        assert_eq!(err.message, "`let` binding `x` has no value");
+    }
+
+    #[test]
+    fn test_switch_cases_and_ranges() {
+        let ast = parse_src("fn f(id: int) {\n\
+                             switch (id) {\n\
+                                 case 0: x = 1.0;\n\
+                                 case 1, 3..5: x = 2.0; y = 1.0;\n\
+                                 case -2..=-1: { x = 3.0; }\n\
+                                 default: x = 0.0;\n\
+                             }\n}").unwrap();
+        let NodeKind::Program(items) = &ast.kind else { panic!("Expected Program") };
+        let NodeKind::FunctionDecl { body, .. } = &items[0].kind else { panic!("Expected FunctionDecl") };
+        let NodeKind::Block(stmts) = &body.kind else { panic!("Expected Block") };
+        let NodeKind::SwitchStmt { cases, default, .. } = &stmts[0].kind else { panic!("Expected SwitchStmt") };
+
+        let values: Vec<_> = cases.iter().map(|c| c.values.clone()).collect();
+        assert_eq!(values, vec![vec![0], vec![1, 3, 4], vec![-2, -1]]);
+        assert!(matches!(&cases[1].body.kind, NodeKind::Block(b) if b.len() == 2));
+        assert!(default.is_some());
+    }
+
+    #[test]
+    fn test_switch_duplicate_case() {
+        let err = first_error("fn f(id: int) { switch (id) { case 0..3: break; case 2: break; } }");
+        assert_eq!(err.message, "case value 2 is covered more than once");
+    }
+
+    #[test]
+    fn test_reports_every_error_and_keeps_partial_ast() {
+        let src = "fn a() float {\n    float x = ;\n    return x;\n}\n\
+                   fn b( { }\n\
+                   fn c() float {\n    if (x { y = 1.0; }\n    return 2.0 +;\n}\n\
+                   fn d() float { return 1.0; }";
+        let (tokens, _) = tokenize(&SourceMap::single("t.sumi", src));
+        let (ast, errors) = Parser::new(tokens).parse_recovering();
+
+        let lines: Vec<_> = errors.iter().map(|e| e.span.line).collect();
+        assert_eq!(lines, vec![2, 5, 7, 8]);
+
+        let NodeKind::Program(items) = &ast.kind else { panic!("Expected Program") };
+        let names: Vec<_> = items.iter().filter_map(|i| match &i.kind {
+            NodeKind::FunctionDecl { name, .. } => Some(name.as_str()),
+            _ => None,
+        }).collect();
+        assert_eq!(names, vec!["a", "c", "d"]);
+    }
+
+    #[test]
+    fn test_unclosed_block_stops_at_next_fn() {
+        let errors = parse_src("fn a() {\n    x = 1.0;\nfn b() {}").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "expected `}`, found `fn`");
+    }
 }
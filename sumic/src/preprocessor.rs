@@ -1,65 +1,604 @@
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-use anyhow::{Context, Result};
-use regex::Regex;
+use anyhow::{bail, Context, Result};
 
+use crate::source_map::{FileId, SourceFile, SourceMap};
+
+/// A `#define`d macro.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Macro {
+    /// The parameters of a function-like macro, `None` for an object-like one.
+    pub params: Option<Vec<String>>,
+    pub body: String,
+}
+
+/// One level of `#if`/`#ifdef`/`#ifndef` nesting.
+struct Condition {
+    /// Whether lines in the current branch are kept.
+    active: bool,
+    /// Whether an earlier branch was already taken.
+    taken: bool,
+    /// Whether the enclosing branch is kept; nothing inside is otherwise.
+    enclosing: bool,
+    seen_else: bool,
+    line: usize,
+}
+
+/// Handles `#include`, `#define`/`#undef`, conditional compilation and
+/// `#pragma once`, producing the text the lexer sees.
+#[derive(Default, Clone)]
 pub struct Preprocessor {
-    included_files: Vec<PathBuf>,
+    /// Directories searched for `#include`s after the including file's own.
+    include_dirs: Vec<PathBuf>,
+    macros: HashMap<String, Macro>,
+    /// Files marked `#pragma once` that have been included already.
+    once_files: HashSet<PathBuf>,
 }
 
 impl Preprocessor {
     pub fn new() -> Self {
-        Self { included_files: Vec::new() }
+        Self::default()
+    }
+
+    /// Adds a directory to search for `#include`s (`-I dir`).
+    pub fn include_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.include_dirs.push(dir.into());
+    }
+
+    /// Defines a macro from the command line: `NAME`, `NAME=VALUE` or
+    /// `NAME(a, b)=VALUE`, as `-D` does. A bare `NAME` is defined as `1`.
+    pub fn define(&mut self, definition: &str) -> Result<()> {
+        let (head, value) = definition.split_once('=').unwrap_or((definition, "1"));
+        self.define_directive(&format!("{} {}", head, value))
+            .with_context(|| format!("invalid definition `{}`", definition))
     }
 
-    pub fn process(&mut self, file_path: &Path) -> Result<String> {
-        self.process_recursive(file_path, 0)
+    /// Resolves `#include`s starting at `file_path`, returning the merged program
+    /// together with the mapping back to each original file.
+    pub fn process(&mut self, file_path: &Path) -> Result<SourceMap> {
+        self.process_into(file_path, SourceMap::new())
     }
 
-    fn process_recursive(&mut self, file_path: &Path, depth: usize) -> Result<String> {
+    /// Like `process`, but adds the files to `map`, which may already hold
+    /// files of other units (see `SourceMap::next_unit`).
+    pub fn process_into(&mut self, file_path: &Path, mut map: SourceMap) -> Result<SourceMap> {
+        self.process_recursive(file_path, 0, &mut map)?;
+        Ok(map)
+    }
+
+    fn process_recursive(&mut self, file_path: &Path, depth: usize, map: &mut SourceMap) -> Result<()> {
         if depth > 10 {
-            anyhow::bail!("Include depth limit exceeded (cycle detected?): {:?}", file_path);
+            bail!("Include depth limit exceeded (cycle detected?): {:?}; add `#pragma once` to files included more than once", file_path);
         }
 
         let canonical = fs::canonicalize(file_path)
             .with_context(|| format!("Failed to resolve path: {:?}", file_path))?;
-
-        // Simple cycle detection
-        if self.included_files.contains(&canonical) {
-             // Already included, usually we'd skip or error. 
-             // For simple headers, skipping avoids duplication.
-             return Ok(String::new()); 
+        if self.once_files.contains(&canonical) {
+            return Ok(());
         }
-        self.included_files.push(canonical.clone());
 
         let content = fs::read_to_string(&canonical)
             .with_context(|| format!("Failed to read file: {:?}", canonical))?;
+        let file_id = map.add_file(SourceFile::new(file_path.display().to_string(), content.clone()));
+
+        // Resolve relative to the path as written so diagnostics show short names.
+        let base_dir = file_path.parent().unwrap_or(Path::new(""));
+        let mut conditions: Vec<Condition> = Vec::new();
+        let mut lines = content.split_inclusive('\n').scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line))
+        });
+
+        while let Some((start, line)) = lines.next() {
+            let active = conditions.last().is_none_or(|c| c.active);
+            let Some(directive) = line.trim_start().strip_prefix('#') else {
+                if active { self.push_line(map, file_id, start, line); }
+                continue;
+            };
+
+            // A directive continues onto the next line after a trailing `\`.
+            let mut directive = directive.trim_end().to_string();
+            while directive.ends_with('\\') {
+                directive.pop();
+                match lines.next() {
+                    Some((_, next)) => directive.push_str(next.trim_end()),
+                    None => break,
+                }
+            }
+            let line_no = map.file(file_id).location(start).0;
+            let at = format!("{}:{}", file_path.display(), line_no);
+            let directive = directive.trim();
+            let (keyword, rest) = directive.split_once(char::is_whitespace).unwrap_or((directive, ""));
+            let rest = rest.trim();
+
+            match keyword {
+                "if" | "ifdef" | "ifndef" => {
+                    let value = active && match keyword {
+                        "ifdef" => self.macros.contains_key(rest),
+                        "ifndef" => !self.macros.contains_key(rest),
+                        _ => self.evaluate(rest).with_context(|| format!("{}: in `#if {}`", at, rest))? != 0,
+                    };
+                    conditions.push(Condition { active: value, taken: value, enclosing: active, seen_else: false, line: line_no });
+                },
+                "elif" | "else" => {
+                    let Some(c) = conditions.last() else { bail!("{}: `#{}` without `#if`", at, keyword) };
+                    if c.seen_else { bail!("{}: `#{}` after `#else`", at, keyword); }
+                    let value = c.enclosing && !c.taken && match keyword {
+                        "elif" => self.evaluate(rest).with_context(|| format!("{}: in `#elif {}`", at, rest))? != 0,
+                        _ => true,
+                    };
+                    let c = conditions.last_mut().unwrap();
+                    c.active = value;
+                    c.taken |= value;
+                    c.seen_else = keyword == "else";
+                },
+                "endif" => {
+                    if conditions.pop().is_none() { bail!("{}: `#endif` without `#if`", at); }
+                },
+                // Everything else only counts in branches that are kept.
+                _ if !active => {},
+                "" => {},
+                "define" => self.define_directive(rest).with_context(|| format!("{}: invalid `#define`", at))?,
+                "undef" => { self.macros.remove(rest); },
+                "include" => {
+                    let target = self.find_include(base_dir, rest).with_context(|| format!("{}: invalid `#include`", at))?;
+                    println!("  🔗 Including: {}", rest);
+                    self.process_recursive(&target, depth + 1, map)
+                        .with_context(|| format!("included from {}", at))?;
+                },
+                // Other pragmas are meant for other compilers.
+                "pragma" => if rest == "once" { self.once_files.insert(canonical.clone()); },
+                "error" => bail!("{}: #error {}", at, rest),
+                _ => bail!("{}: unknown directive `#{}`", at, keyword),
+            }
+        }
+
+        if let Some(c) = conditions.last() {
+            bail!("{}:{}: `#if` without `#endif`", file_path.display(), c.line);
+        }
+        Ok(())
+    }
+
+    /// Finds the file named by `#include "file"` (next to the including file,
+    /// then in the include directories) or `#include <file>` (only in those).
+    fn find_include(&self, base_dir: &Path, target: &str) -> Result<PathBuf> {
+        let (name, local) = if let Some(name) = target.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+            (name, true)
+        } else if let Some(name) = target.strip_prefix('<').and_then(|t| t.strip_suffix('>')) {
+            (name, false)
+        } else {
+            bail!("expected `\"file\"` or `<file>`, found `{}`", target);
+        };
+        let local_dir = local.then_some(base_dir);
+        let mut dirs = local_dir.into_iter().chain(self.include_dirs.iter().map(|d| d.as_path()));
+        match dirs.find(|d| d.join(name).is_file()) {
+            Some(dir) => Ok(dir.join(name)),
+            // Let reading it report the file as missing.
+            None if local => Ok(base_dir.join(name)),
+            None => bail!("cannot find `{}` in the include directories", name),
+        }
+    }
+
+    /// `NAME body` or `NAME(a, b) body`: the text after `#define`.
+    fn define_directive(&mut self, text: &str) -> Result<()> {
+        let name_len = text.find(|c: char| !is_ident_char(c)).unwrap_or(text.len());
+        let name = &text[..name_len];
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            bail!("expected a macro name, found `{}`", text);
+        }
+        let mut rest = &text[name_len..];
+        // Only a `(` right after the name makes a function-like macro.
+        let params = match rest.strip_prefix('(') {
+            Some(after) => {
+                let Some((list, body)) = after.split_once(')') else { bail!("missing `)` in the parameters of `{}`", name) };
+                rest = body;
+                let params: Vec<String> = list.split(',').map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect();
+                if let Some(bad) = params.iter().find(|p| !p.chars().all(is_ident_char)) {
+                    bail!("invalid parameter `{}` of `{}`", bad, name);
+                }
+                Some(params)
+            },
+            None => None,
+        };
+        let body = rest.split("//").next().unwrap_or_default().trim().to_string();
+        self.macros.insert(name.to_string(), Macro { params, body });
+        Ok(())
+    }
+
+    /// Appends a line of source, with any macros in it expanded.
+    fn push_line(&self, map: &mut SourceMap, file: FileId, start: usize, line: &str) {
+        let mut last = 0;
+        for (range, expansion) in self.invocations(line, &[]) {
+            map.push_text(file, start + last, &line[last..range.start]);
+            map.push_expansion(file, start + range.start, &expansion);
+            last = range.end;
+        }
+        map.push_text(file, start + last, &line[last..]);
+    }
+
+    /// `text` with every macro in it expanded.
+    fn expand(&self, text: &str, disabled: &[&str]) -> String {
+        let mut out = String::new();
+        let mut last = 0;
+        for (range, expansion) in self.invocations(text, disabled) {
+            out.push_str(&text[last..range.start]);
+            out.push_str(&expansion);
+            last = range.end;
+        }
+        out.push_str(&text[last..]);
+        out
+    }
+
+    /// The macro uses in `text`, with their expansions. A macro is not
+    /// expanded again inside its own expansion (`disabled`), so recursive
+    /// definitions terminate.
+    fn invocations(&self, text: &str, disabled: &[&str]) -> Vec<(Range<usize>, String)> {
+        let mut found = Vec::new();
+        if self.macros.is_empty() { return found; }
+        let mut i = 0;
+        while i < text.len() {
+            let rest = &text[i..];
+            if rest.starts_with("//") { break; }
+            let c = rest.chars().next().unwrap();
+            if !is_ident_char(c) {
+                i += c.len_utf8();
+                continue;
+            }
+            // Numbers like `1e5` are skipped whole, so `e5` is not taken for a name.
+            let len = rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len());
+            let word = &rest[..len];
+            let start = i;
+            i += len;
+            if c.is_ascii_digit() || disabled.contains(&word) { continue; }
+            let Some(m) = self.macros.get(word) else { continue };
+            let mut inner = disabled.to_vec();
+            inner.push(word);
+
+            let Some(params) = &m.params else {
+                found.push((start..i, self.expand(&m.body, &inner)));
+                continue;
+            };
+            // A function-like macro without arguments is just a name.
+            let Some((args, end)) = call_arguments(text, i) else { continue };
+            let args: Vec<String> = args.iter().map(|a| self.expand(a.trim(), disabled)).collect();
+            let mut body = String::new();
+            let mut last = 0;
+            for (range, word) in words(&m.body) {
+                let Some(index) = params.iter().position(|p| p == word) else { continue };
+                body.push_str(&m.body[last..range.start]);
+                body.push_str(args.get(index).map(|a| a.as_str()).unwrap_or_default());
+                last = range.end;
+            }
+            body.push_str(&m.body[last..]);
+            found.push((start..end, self.expand(&body, &inner)));
+            i = end;
+        }
+        found
+    }
+
+    /// The value of an `#if` condition: `defined(NAME)` is 1 or 0, macros are
+    /// expanded, and any name left over counts as 0.
+    fn evaluate(&self, condition: &str) -> Result<i64> {
+        let mut text = String::new();
+        let mut last = 0;
+        let ranges = words(condition).collect::<Vec<_>>();
+        let mut skip_until = 0;
+        for (range, word) in &ranges {
+            if range.start < skip_until || *word != "defined" { continue; }
+            let after = condition[range.end..].trim_start();
+            let (name, end) = match after.strip_prefix('(') {
+                Some(inner) => {
+                    let Some(close) = inner.find(')') else { bail!("missing `)` after `defined(`") };
+                    (inner[..close].trim(), condition.len() - inner.len() + close + 1)
+                },
+                None => {
+                    let len = after.find(|c: char| !is_ident_char(c)).unwrap_or(after.len());
+                    (&after[..len], condition.len() - after.len() + len)
+                },
+            };
+            if name.is_empty() { bail!("expected a macro name after `defined`"); }
+            text.push_str(&condition[last..range.start]);
+            text.push_str(if self.macros.contains_key(name) { " 1 " } else { " 0 " });
+            last = end;
+            skip_until = end;
+        }
+        text.push_str(&condition[last..]);
+
+        let expanded = self.expand(&text, &[]);
+        let mut parser = ConditionParser { tokens: condition_tokens(&expanded)?, pos: 0 };
+        let value = parser.ternary()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            bail!("unexpected `{}`", token);
+        }
+        Ok(value)
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// The identifiers in `text`, with their byte ranges.
+fn words(text: &str) -> impl Iterator<Item = (Range<usize>, &str)> {
+    let mut i = 0;
+    std::iter::from_fn(move || {
+        while i < text.len() {
+            let rest = &text[i..];
+            let c = rest.chars().next()?;
+            if !is_ident_char(c) {
+                i += c.len_utf8();
+                continue;
+            }
+            let len = rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len());
+            let start = i;
+            i += len;
+            if !c.is_ascii_digit() {
+                return Some((start..i, &text[start..i]));
+            }
+        }
+        None
+    })
+}
+
+/// The arguments of a macro call whose name ends at `at`, split at top-level
+/// commas, and the offset just past the `)`. `None` if no `(` follows, or it
+/// is not closed on the same line.
+fn call_arguments(text: &str, at: usize) -> Option<(Vec<&str>, usize)> {
+    let open = at + text[at..].len() - text[at..].trim_start().len();
+    if !text[open..].starts_with('(') { return None; }
+    let mut depth = 0;
+    let mut args = Vec::new();
+    let mut arg_start = open + 1;
+    for (i, c) in text[open..].char_indices() {
+        let i = open + i;
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 1 => {
+                args.push(&text[arg_start..i]);
+                // `F()` takes no arguments rather than one empty one.
+                if args.len() == 1 && args[0].trim().is_empty() { args.clear(); }
+                return Some((args, i + 1));
+            },
+            ')' => depth -= 1,
+            ',' if depth == 1 => {
+                args.push(&text[arg_start..i]);
+                arg_start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Splits an `#if` condition into numbers, leftover names and operators.
+fn condition_tokens(text: &str) -> Result<Vec<String>> {
+    const OPERATORS: &[&str] = &["<<", ">>", "<=", ">=", "==", "!=", "&&", "||",
+        "(", ")", "!", "~", "*", "/", "%", "+", "-", "<", ">", "&", "^", "|", "?", ":"];
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if is_ident_char(c) {
+            rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len())
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            op.len()
+        } else {
+            bail!("unexpected `{}`", c);
+        };
+        tokens.push(rest[..len].to_string());
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Evaluates `#if` conditions with C's integer operators and precedence.
+struct ConditionParser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl ConditionParser {
+    fn next(&mut self) -> Option<&str> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<()> {
+        match self.next() {
+            Some(t) if t == expected => Ok(()),
+            Some(t) => bail!("expected `{}`, found `{}`", expected, t),
+            None => bail!("expected `{}`, found end of condition", expected),
+        }
+    }
+
+    fn ternary(&mut self) -> Result<i64> {
+        let condition = self.binary(1)?;
+        if self.peek() != Some("?") { return Ok(condition); }
+        self.pos += 1;
+        let then = self.ternary()?;
+        self.expect(":")?;
+        let otherwise = self.ternary()?;
+        Ok(if condition != 0 { then } else { otherwise })
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<i64> {
+        let mut left = self.unary()?;
+        loop {
+            let precedence = match self.peek() {
+                Some("*" | "/" | "%") => 10,
+                Some("+" | "-") => 9,
+                Some("<<" | ">>") => 8,
+                Some("<" | ">" | "<=" | ">=") => 7,
+                Some("==" | "!=") => 6,
+                Some("&") => 5,
+                Some("^") => 4,
+                Some("|") => 3,
+                Some("&&") => 2,
+                Some("||") => 1,
+                _ => return Ok(left),
+            };
+            if precedence < min_precedence { return Ok(left); }
+            let op = self.next().unwrap_or_default().to_string();
+            let right = self.binary(precedence + 1)?;
+            left = match op.as_str() {
+                "*" => left.wrapping_mul(right),
+                "/" | "%" if right == 0 => bail!("division by zero"),
+                "/" => left / right,
+                "%" => left % right,
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "<" => (left < right) as i64,
+                ">" => (left > right) as i64,
+                "<=" => (left <= right) as i64,
+                ">=" => (left >= right) as i64,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "&" => left & right,
+                "^" => left ^ right,
+                "|" => left | right,
+                "&&" => (left != 0 && right != 0) as i64,
+                _ => (left != 0 || right != 0) as i64,
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<i64> {
+        let Some(token) = self.next().map(|t| t.to_string()) else { bail!("expected a value, found end of condition") };
+        match token.as_str() {
+            "!" => Ok((self.unary()? == 0) as i64),
+            "~" => Ok(!self.unary()?),
+            "-" => Ok(self.unary()?.wrapping_neg()),
+            "+" => self.unary(),
+            "(" => {
+                let value = self.ternary()?;
+                self.expect(")")?;
+                Ok(value)
+            },
+            t if t.starts_with(|c: char| c.is_ascii_digit()) => parse_int(t),
+            t if t.starts_with(is_ident_char) => Ok(0),
+            t => bail!("expected a value, found `{}`", t),
+        }
+    }
+}
+
+fn parse_int(text: &str) -> Result<i64> {
+    let digits = text.trim_end_matches(['u', 'U', 'l', 'L']);
+    let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    };
+    value.with_context(|| format!("`{}` is not an integer", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sumic_pp_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn preprocess(preprocessor: &mut Preprocessor, name: &str, text: &str) -> Result<String> {
+        let dir = temp_dir(name);
+        fs::write(dir.join("main.sumi"), text).unwrap();
+        let result = preprocessor.process(&dir.join("main.sumi")).map(|map| map.text().to_string());
+        fs::remove_dir_all(&dir).ok();
+        result
+    }
+
+    #[test]
+    fn test_included_text_maps_back_to_its_file() {
+        let dir = temp_dir("include");
+        fs::write(dir.join("lib.sumi"), "fn lib() {}\n").unwrap();
+        fs::write(dir.join("main.sumi"), "#include \"lib.sumi\"\nfn main() {}\n").unwrap();
+
+        let map = Preprocessor::new().process(&dir.join("main.sumi")).unwrap();
+        assert_eq!(map.text(), "fn lib() {}\nfn main() {}\n");
+
+        let lib = map.span(3, 6);
+        assert!(map.file(lib.file).name.ends_with("lib.sumi"));
+        assert_eq!((lib.line, lib.column), (1, 4));
+
+        let main_fn = map.span(15, 19);
+        assert!(map.file(main_fn.file).name.ends_with("main.sumi"));
+        assert_eq!((main_fn.line, main_fn.column), (2, 4));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_macros() {
+        let mut pp = Preprocessor::new();
+        let text = preprocess(&mut pp, "macros", "#define AA 2\n#define SQ(x) ((x) * (x))\n#define LONG a + \\\n    b\n\
+                                                  let n = AA * SQ(p.x + AA) // AA\nlet m = SQ(SQ(1.0)) + LONG + SQ + 1e5\n").unwrap();
+        assert_eq!(text, "let n = 2 * ((p.x + 2) * (p.x + 2)) // AA\nlet m = ((((1.0) * (1.0))) * (((1.0) * (1.0)))) + a +     b + SQ + 1e5\n");
+
+        // A macro is not expanded inside itself.
+        let text = preprocess(&mut Preprocessor::new(), "recursive", "#define x x + 1\nlet y = x\n").unwrap();
+        assert_eq!(text, "let y = x + 1\n");
+    }
+
+    #[test]
+    fn test_expansions_map_to_the_macro_use() {
+        let dir = temp_dir("spans");
+        fs::write(dir.join("main.sumi"), "#define SCALE 2.0 * 3.0\nlet k = SCALE + 1.0\n").unwrap();
+        let map = Preprocessor::new().process(&dir.join("main.sumi")).unwrap();
+        assert_eq!(map.text(), "let k = 2.0 * 3.0 + 1.0\n");
+        let three = map.span(14, 17);
+        assert_eq!((three.line, three.column), (2, 9));
+        let one = map.span(20, 23);
+        assert_eq!((one.line, one.column), (2, 17));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let src = "#ifdef HW_PERFORMANCE\nfast\n#else\nslow\n#endif\n\
+                   #if AA > 1 && !defined(NO_AA)\naa\n#elif defined AA\nno_aa\n#else\nnothing\n#endif\n\
+                   #if 0\n#if 1\nhidden\n#endif\n#error not reached\n#endif\n";
+        assert_eq!(preprocess(&mut Preprocessor::new(), "cond1", src).unwrap(), "slow\nnothing\n");
+
+        let mut pp = Preprocessor::new();
+        pp.define("HW_PERFORMANCE").unwrap();
+        pp.define("AA=(1 + 1)").unwrap();
+        assert_eq!(preprocess(&mut pp, "cond2", src).unwrap(), "fast\naa\n");
+
+        let mut pp = Preprocessor::new();
+        pp.define("AA=1").unwrap();
+        pp.define("NO_AA").unwrap();
+        assert_eq!(preprocess(&mut pp, "cond3", src).unwrap(), "slow\nno_aa\n");
+
+        let error = |src: &str| preprocess(&mut Preprocessor::new(), "cond_err", src).unwrap_err().root_cause().to_string();
+        assert!(error("#if 1\nx\n").ends_with(":1: `#if` without `#endif`"));
+        assert!(error("#endif\n").ends_with(":1: `#endif` without `#if`"));
+        assert!(error("#version 300 es\n").ends_with(":1: unknown directive `#version`"));
+        assert_eq!(error("#if 1 +\n#endif\n"), "expected a value, found end of condition");
+    }
+
+    #[test]
+    fn test_pragma_once_and_include_dirs() {
+        let dir = temp_dir("once");
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("lib/common.sumi"), "#pragma once\ncommon\n").unwrap();
+        fs::write(dir.join("lib/twice.sumi"), "twice\n").unwrap();
+        fs::write(dir.join("main.sumi"), "#include <common.sumi>\n#include \"common.sumi\"\n#include <twice.sumi>\n#include <twice.sumi>\n").unwrap();
 
-        let base_dir = canonical.parent().unwrap_or(Path::new("."));
-        let include_regex = Regex::new(r#"#include\s+"([^"]+)""#).unwrap();
-
-        let mut final_output = String::new();
-        let mut last_pos = 0;
-
-        for cap in include_regex.captures_iter(&content) {
-            let match_str = cap.get(0).unwrap();
-            let rel_path = cap.get(1).unwrap().as_str();
-            
-            // Append text before the #include
-            final_output.push_str(&content[last_pos..match_str.start()]);
-            
-            // Resolve and process the included file
-            let target_path = base_dir.join(rel_path);
-            println!("  🔗 Including: {:?}", rel_path);
-            let included_content = self.process_recursive(&target_path, depth + 1)?;
-            
-            final_output.push_str(&included_content);
-            last_pos = match_str.end();
-        }
-
-        // Append remaining text
-        final_output.push_str(&content[last_pos..]);
-        
-        Ok(final_output)
+        assert!(Preprocessor::new().process(&dir.join("main.sumi")).is_err());
+        let mut pp = Preprocessor::new();
+        pp.include_dir(dir.join("lib"));
+        let map = pp.process(&dir.join("main.sumi")).unwrap();
+        assert_eq!(map.text(), "common\ntwice\ntwice\n");
+        fs::remove_dir_all(&dir).ok();
     }
 }
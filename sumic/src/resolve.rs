@@ -0,0 +1,533 @@
+use std::collections::HashMap;
+
+use crate::ast::{array_type, method_name, split_array_type, AstNode, BinaryOperator, Field, NodeKind, ParamQualifier};
+use crate::builtins;
+use crate::diagnostics::Diagnostic;
+use crate::types::{self, adapt_literals, is_int_literal, Scalar, Type};
+
+/// Name resolution run on the freshly parsed program: constructor calls are
+/// matched against struct declarations, and method calls against `impl` blocks.
+pub fn resolve(program: &mut AstNode) -> Vec<Diagnostic> {
+    let mut resolver = Resolver::default();
+    resolver.collect(program);
+    resolver.visit(program);
+    resolver.errors
+}
+
+/// Values the host provides to every shader.
+pub const UNIFORMS: &[(&str, &str)] = &[("iTime", "float"), ("iResolution", "vec3"), ("iMouse", "vec4")];
+
+/// Functions from the host's standard library, prepended to every shader,
+/// with their parameter names. They all take and return `float`s.
+pub const HOST_FUNCTIONS: &[(&str, &[&str])] = &[
+    ("opUnion", &["d1", "d2"]),
+    ("opSubtraction", &["d1", "d2"]),
+    ("opIntersection", &["d1", "d2"]),
+    ("opSmoothUnion", &["d1", "d2", "k"]),
+];
+
+/// One declaration of a user function (or method).
+#[derive(Clone)]
+struct Signature {
+    params: Vec<String>,
+    qualifiers: Vec<ParamQualifier>,
+    return_type: String,
+    /// Set when the name is overloaded.
+    symbol: Option<String>,
+}
+
+/// The symbol of one overload: the name followed by the parameter types,
+/// e.g. `mix_vec2_vec2_vec2`.
+fn overload_symbol(name: &str, params: &[String]) -> String {
+    if params.is_empty() {
+        return format!("{}_void", name);
+    }
+    let types = params.join("_");
+    let mut symbol = format!("{}_", name);
+    for c in types.chars() {
+        if c.is_ascii_alphanumeric() { symbol.push(c); }
+        else if !symbol.ends_with('_') { symbol.push('_'); }
+    }
+    symbol.trim_end_matches('_').to_string()
+}
+
+#[derive(Default)]
+struct Resolver {
+    structs: HashMap<String, Vec<Field>>,
+    /// Every declaration of each user function; methods are keyed by their
+    /// lowered name (`Ray_at`).
+    functions: HashMap<String, Vec<Signature>>,
+    /// Variable types; `None` where an inferred type could not be worked out.
+    scopes: Vec<HashMap<String, Option<Type>>>,
+    /// The return type of the function being resolved.
+    return_type: Option<Type>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Resolver {
+    /// Gathers structs and function signatures, and gives each overload of an
+    /// overloaded function its own symbol.
+    fn collect(&mut self, program: &mut AstNode) {
+        let NodeKind::Program(items) = &mut program.kind else { return };
+        for item in items.iter() {
+            match &item.kind {
+                NodeKind::StructDecl { name, fields, .. } => { self.structs.insert(name.clone(), fields.clone()); },
+                NodeKind::FunctionDecl { name, args, return_type, .. } => {
+                    let params: Vec<_> = args.iter().map(|a| a.type_name.clone()).collect();
+                    let existing = self.functions.entry(name.clone()).or_default();
+                    if existing.iter().any(|s| s.params == params) {
+                        self.errors.push(Diagnostic::error(format!("function `{}` is already defined with parameters ({})", name, params.join(", ")), item.span)
+                            .with_label("duplicate definition")
+                            .with_hint("overloads must differ in their parameter types"));
+                        continue;
+                    }
+                    let qualifiers = args.iter().map(|a| a.qualifier).collect();
+                    existing.push(Signature { params, qualifiers, return_type: return_type.clone(), symbol: None });
+                },
+                NodeKind::ImplBlock { type_name, methods } => {
+                    for method in methods {
+                        if let NodeKind::FunctionDecl { name, args, return_type, .. } = &method.kind {
+                            if self.functions.contains_key(&method_name(type_name, name)) {
+                                self.errors.push(Diagnostic::error(format!("method `{}` is already defined for `{}`", name, type_name), method.span)
+                                    .with_label("duplicate definition")
+                                    .with_hint("methods cannot be overloaded; give one of them another name"));
+                                continue;
+                            }
+                            let params = args.iter().map(|a| a.type_name.clone()).collect();
+                            let qualifiers = args.iter().map(|a| a.qualifier).collect();
+                            let signature = Signature { params, qualifiers, return_type: return_type.clone(), symbol: None };
+                            self.functions.insert(method_name(type_name, name), vec![signature]);
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        for (name, params) in HOST_FUNCTIONS {
+            let host = Signature { params: vec!["float".to_string(); params.len()], qualifiers: vec![ParamQualifier::In; params.len()], return_type: "float".into(), symbol: None };
+            self.functions.entry(name.to_string()).or_insert_with(|| vec![host]);
+        }
+
+        for (name, signatures) in self.functions.iter_mut() {
+            if signatures.len() > 1 || builtins::is_builtin(name) {
+                signatures.iter_mut().for_each(|s| s.symbol = Some(overload_symbol(name, &s.params)));
+            }
+        }
+        for item in items.iter_mut() {
+            if let NodeKind::FunctionDecl { name, args, overload, .. } = &mut item.kind {
+                let params: Vec<_> = args.iter().map(|a| a.type_name.clone()).collect();
+                *overload = self.functions[name.as_str()].iter().find(|s| s.params == params).and_then(|s| s.symbol.clone());
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &str, type_name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), (!type_name.is_empty()).then(|| Type::parse(type_name)));
+        }
+    }
+
+    fn scoped(&mut self, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashMap::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    fn visit(&mut self, node: &mut AstNode) {
+        match &mut node.kind {
+            NodeKind::Program(_) | NodeKind::Block(_) | NodeKind::ForStmt { .. } => self.scoped(|r| {
+                node.children_mut().into_iter().for_each(|child| r.visit(child));
+            }),
+            NodeKind::FunctionDecl { args, body, return_type, .. } => {
+                self.return_type = Some(Type::parse(return_type));
+                let params: Vec<_> = args.iter().map(|a| (a.name.clone(), a.type_name.clone())).collect();
+                self.scoped(|r| {
+                    params.into_iter().for_each(|(name, t)| r.declare(&name, &t));
+                    r.visit(body);
+                });
+            },
+            NodeKind::VarDecl { binding, type_name, name, value, doc_string, public } => {
+                if let Some(v) = value { self.visit(v); }
+                // `let x = value` takes the value's type; the checker reports
+                // why when it cannot be worked out.
+                if type_name.is_empty() {
+                    if let Some(t) = value.as_deref().and_then(|v| self.type_of(v)) {
+                        *type_name = t.to_string();
+                    }
+                }
+                if let Some(v) = value { self.convert(v, &Type::parse(type_name), false); }
+                self.declare(name, type_name);
+                if let Some((element, size)) = split_array_type(type_name) {
+                    let type_name = element.to_string();
+                    node.kind = NodeKind::ArrayDecl { binding: *binding, type_name, name: std::mem::take(name), size, value: value.take(), doc_string: doc_string.take(), public: *public };
+                }
+            },
+            NodeKind::ArrayDecl { type_name, name, size, value, .. } => {
+                let element = Type::parse(type_name);
+                let (type_name, name) = (array_type(type_name, *size), name.clone());
+                if let Some(v) = value {
+                    self.visit(v);
+                    if let NodeKind::ArrayLiteral(items) = &mut v.kind {
+                        items.iter_mut().for_each(|item| self.convert(item, &element, false));
+                    }
+                }
+                self.declare(&name, &type_name);
+            },
+            _ => {
+                node.children_mut().into_iter().for_each(|child| self.visit(child));
+                self.resolve_expression(node);
+                self.promote(node);
+            },
+        }
+    }
+
+    fn resolve_expression(&mut self, node: &mut AstNode) {
+        let span = node.span;
+        match &mut node.kind {
+            NodeKind::Call { func_name, args, .. } if self.structs.contains_key(func_name.as_str()) => {
+                let declared: Vec<_> = self.structs[func_name.as_str()].iter().map(|f| f.name.clone()).collect();
+                // `Material()` is the zero value.
+                if !args.is_empty() && args.len() != declared.len() {
+                    let given = if args.len() == 1 { "1 value was".to_string() } else { format!("{} values were", args.len()) };
+                    self.errors.push(Diagnostic::error(format!("`{}` has {} fields but {} given", func_name, declared.len(), given), span)
+                        .with_label(format!("expected {} values", declared.len()))
+                        .with_hint(format!("fields in order: {}", declared.join(", "))));
+                    return;
+                }
+                let fields = declared.into_iter().zip(std::mem::take(args)).collect();
+                node.kind = NodeKind::StructLiteral { name: std::mem::take(func_name), fields };
+            },
+            NodeKind::StructLiteral { name, fields } => {
+                let Some(declared) = self.structs.get(name.as_str()) else {
+                    self.errors.push(Diagnostic::error(format!("unknown struct `{}`", name), span)
+                        .with_label("not a struct")
+                        .with_hint("named arguments can only be used to construct a struct"));
+                    return;
+                };
+                let declared: Vec<_> = declared.iter().map(|f| f.name.clone()).collect();
+                let mut given = std::mem::take(fields);
+                for (i, (field, value)) in given.iter().enumerate() {
+                    if !declared.contains(field) {
+                        self.errors.push(Diagnostic::error(format!("struct `{}` has no field `{}`", name, field), value.span)
+                            .with_label("unknown field")
+                            .with_hint(format!("`{}` has fields: {}", name, declared.join(", "))));
+                    } else if given[..i].iter().any(|(f, _)| f == field) {
+                        self.errors.push(Diagnostic::error(format!("field `{}` is given twice", field), value.span)
+                            .with_label("duplicate field"));
+                    }
+                }
+                let missing: Vec<_> = declared.iter().filter(|d| !given.iter().any(|(f, _)| f == *d)).map(|d| format!("`{}`", d)).collect();
+                if !missing.is_empty() {
+                    self.errors.push(Diagnostic::error(format!("missing {} in `{}`", missing.join(", "), name), span)
+                        .with_label("every field needs a value"));
+                }
+                for field in &declared {
+                    if let Some(i) = given.iter().position(|(f, _)| f == field) {
+                        fields.push(given.remove(i));
+                    }
+                }
+            },
+            NodeKind::Call { func_name, args, overload, .. } => {
+                let Some(signatures) = self.functions.get(func_name.as_str()) else { return };
+                if signatures.iter().all(|s| s.symbol.is_none()) { return; }
+                let arg_types: Vec<_> = args.iter().map(|a| self.type_of(a)).collect();
+                // Unknown argument types match anything.
+                // Exact matches win over ones that need integers promoted.
+                let matching = |promote: bool| signatures.iter().filter(|s| {
+                    s.params.len() == args.len() && s.params.iter().zip(&arg_types).all(|(p, a)| a.as_ref().is_none_or(|a| {
+                        let p = Type::parse(p);
+                        *a == p || (promote && types::promotes(a, &p))
+                    }))
+                }).collect::<Vec<_>>();
+                let mut candidates = matching(false);
+                if candidates.is_empty() {
+                    candidates = matching(true);
+                }
+                let described = arg_types.iter().map(|t| t.as_ref().map_or("_".to_string(), Type::to_string)).collect::<Vec<_>>().join(", ");
+                match candidates.as_slice() {
+                    [chosen] => *overload = chosen.symbol.clone(),
+                    // Not one of the user's overloads: the built-in itself.
+                    [] if builtins::is_builtin(func_name) => {},
+                    [] => {
+                        let declared = signatures.iter().map(|s| format!("`{}({})`", func_name, s.params.join(", "))).collect::<Vec<_>>();
+                        self.errors.push(Diagnostic::error(format!("no overload of `{}` takes ({})", func_name, described), span)
+                            .with_label("no matching overload")
+                            .with_hint(format!("declared overloads: {}", declared.join(", "))));
+                    },
+                    _ => {
+                        self.errors.push(Diagnostic::error(format!("call to overloaded `{}` is ambiguous", func_name), span)
+                            .with_label(format!("arguments ({}) match more than one overload", described))
+                            .with_hint("give the arguments explicit types, e.g. through a typed `let`"));
+                    },
+                }
+            },
+            NodeKind::MemberAccess { base, member } if matches!(self.type_of(base), Some(Type::Vector(..))) && types::swizzle_indices(member).is_some() => {
+                let base = std::mem::replace(base, Box::new(AstNode::new(NodeKind::BreakStmt, span)));
+                node.kind = NodeKind::Swizzle { base, components: std::mem::take(member) };
+            },
+            NodeKind::MethodCall { receiver, method, receiver_type, .. } => {
+                let Some(type_name) = self.type_of(receiver).map(|t| t.to_string()) else {
+                    self.errors.push(Diagnostic::error(format!("cannot tell which type's `{}` method to call", method), receiver.span)
+                        .with_label("type of this value is unknown")
+                        .with_hint("methods can be called on variables, fields and values of a struct type"));
+                    return;
+                };
+                if !self.functions.contains_key(&method_name(&type_name, method)) {
+                    self.errors.push(Diagnostic::error(format!("no method `{}` on type `{}`", method, type_name), span)
+                        .with_label("method not found")
+                        .with_hint(format!("methods are declared in `impl {} {{ ... }}`", type_name)));
+                    return;
+                }
+                *receiver_type = type_name;
+            },
+            _ => {}
+        }
+    }
+
+    /// Makes the implicit conversions of S2L explicit, so that every backend
+    /// sees operands and arguments of exactly the types they need: integers
+    /// mixed with floats become floats, and scalars given to built-ins like
+    /// `clamp(v, 0.0, 1.0)` become vectors.
+    fn promote(&mut self, node: &mut AstNode) {
+        match &mut node.kind {
+            NodeKind::BinaryOp { left, op, right } => {
+                use BinaryOperator::*;
+                if matches!(op, Add | Sub | Mul | Div | Less | Greater | LessEqual | GreaterEqual | Equal | NotEqual) {
+                    self.promote_pair(left, right);
+                }
+                if *op == Mul && self.is_matrix_product(left, right) {
+                    *op = MatrixMul;
+                }
+            },
+            NodeKind::Ternary { then_expr, else_expr, result_type, .. } => {
+                self.promote_pair(then_expr, else_expr);
+                *result_type = self.type_of_branches(then_expr, else_expr).map_or_else(String::new, |t| t.to_string());
+            },
+            NodeKind::Call { func_name, args, overload, arg_types } => {
+                if let Some(signature) = self.signature(func_name, overload).cloned() {
+                    self.convert_args(args, &signature.params, &signature.qualifiers);
+                    return;
+                }
+                let Some(types) = args.iter().map(|a| self.type_of(a)).collect::<Option<Vec<_>>>() else { return };
+                let (targets, splat) = match Type::parse(func_name) {
+                    Type::Struct(_) | Type::Void if builtins::is_builtin(func_name) => (builtins::params(func_name, &types), true),
+                    Type::Struct(_) | Type::Void => return,
+                    target => (Some(types::constructor_params(&target, &types)), false),
+                };
+                let targets = targets.unwrap_or(types);
+                for (arg, target) in args.iter_mut().zip(&targets) {
+                    self.convert(arg, target, splat);
+                }
+                if builtins::is_builtin(func_name) || matches!(Type::parse(func_name), Type::Matrix(_)) {
+                    *arg_types = targets.iter().map(Type::to_string).collect();
+                }
+            },
+            NodeKind::MethodCall { method, args, receiver_type, .. } => {
+                let Some(signature) = self.functions.get(&method_name(receiver_type, method)).and_then(|s| s.first()).cloned() else { return };
+                self.convert_args(args, signature.params.get(1..).unwrap_or_default(), signature.qualifiers.get(1..).unwrap_or_default());
+            },
+            NodeKind::ArrayLiteral(items) => {
+                if let Some(Type::Array(element, _)) = self.type_of_elements(items) {
+                    items.iter_mut().for_each(|item| self.convert(item, &element, false));
+                }
+            },
+            NodeKind::StructLiteral { name, fields } => {
+                let Some(declared) = self.structs.get(name.as_str()) else { return };
+                let declared: Vec<_> = declared.iter().map(|f| Type::parse(&f.type_name)).collect();
+                fields.iter_mut().zip(&declared).for_each(|((_, value), t)| self.convert(value, t, false));
+            },
+            NodeKind::Assignment { target, value } => {
+                if let Some(t) = self.type_of(target) { self.convert(value, &t, false); }
+            },
+            NodeKind::CompoundAssignment { target, op, value } => {
+                if let (Some(t), Some(v)) = (self.type_of(target), self.type_of(value)) {
+                    if let Some(element) = t.scalar() { self.convert(value, &v.with_element(element), false); }
+                }
+                if *op == BinaryOperator::Mul && self.is_matrix_product(target, value) {
+                    *op = BinaryOperator::MatrixMul;
+                }
+            },
+            NodeKind::ReturnStmt(value) => {
+                if let Some(t) = self.return_type.clone() { self.convert(value, &t, false); }
+            },
+            _ => {}
+        }
+    }
+
+    /// Converts the arguments of a call to a user function to its parameter
+    /// types. `out` and `inout` arguments are left alone: they must already
+    /// be variables of the right type.
+    fn convert_args(&self, args: &mut [AstNode], params: &[String], qualifiers: &[ParamQualifier]) {
+        for ((arg, param), qualifier) in args.iter_mut().zip(params).zip(qualifiers) {
+            if !qualifier.is_reference() {
+                self.convert(arg, &Type::parse(param), false);
+            }
+        }
+    }
+
+    /// Promotes the integer side of two values mixed with a float one.
+    fn promote_pair(&self, a: &mut AstNode, b: &mut AstNode) {
+        let (Some(ta), Some(tb)) = (self.type_of(a), self.type_of(b)) else { return };
+        self.convert(a, &ta.with_element(tb.scalar().unwrap_or(Scalar::Bool)), false);
+        self.convert(b, &tb.with_element(ta.scalar().unwrap_or(Scalar::Bool)), false);
+    }
+
+    /// Wraps `node` in a conversion to `to` if its type promotes to it (or,
+    /// with `splat`, is a scalar to be repeated into the vector `to`).
+    /// Integer literals where a float is expected are written as floats
+    /// instead: GLSL ES converts nothing implicitly, not even `1` to `1.0`.
+    fn convert(&self, node: &mut AstNode, to: &Type, splat: bool) {
+        let Some(from) = self.type_of(node) else { return };
+        if from == *to {
+            return;
+        }
+        if *to == Type::FLOAT && is_int_literal(node) {
+            float_literal(node);
+            return;
+        }
+        let splatted = splat && matches!((&from, to), (Type::Scalar(_), Type::Vector(..)));
+        if splatted {
+            // `vec3(i)` needs `i` to be a float already.
+            self.convert(node, &Type::Scalar(to.scalar().unwrap_or(Scalar::Float)), false);
+        } else if !types::promotes(&from, to) {
+            return;
+        }
+        let span = node.span;
+        let value = std::mem::replace(node, AstNode::new(NodeKind::BreakStmt, span));
+        *node = AstNode::new(NodeKind::Call { func_name: to.to_string(), args: vec![value], overload: None, arg_types: Vec::new() }, span);
+    }
+
+    /// Whether `a * b` is a matrix product rather than a component-wise or
+    /// scalar one.
+    fn is_matrix_product(&self, a: &AstNode, b: &AstNode) -> bool {
+        matches!((self.type_of(a), self.type_of(b)),
+            (Some(Type::Matrix(_)), Some(Type::Matrix(_) | Type::Vector(..))) | (Some(Type::Vector(..)), Some(Type::Matrix(_))))
+    }
+
+    /// The signature a call to a user function resolves to, if any.
+    fn signature(&self, name: &str, overload: &Option<String>) -> Option<&Signature> {
+        let signatures = self.functions.get(name)?;
+        match overload {
+            Some(_) => signatures.iter().find(|s| &s.symbol == overload),
+            None if signatures.len() == 1 && !builtins::is_builtin(name) => signatures.first(),
+            None => None,
+        }
+    }
+
+    /// The type of an array literal: its elements must agree once integers
+    /// mixed with floats count as floats.
+    fn type_of_elements(&self, items: &[AstNode]) -> Option<Type> {
+        let types = items.iter().map(|i| self.type_of(i)).collect::<Option<Vec<_>>>()?;
+        let first = types.first()?.clone();
+        let element = match types.iter().find(|t| t.scalar() == Some(Scalar::Float)) {
+            Some(float) if types.iter().all(|t| *t == first || types::promotes(t, float)) => float.clone(),
+            _ if types.iter().all(|t| *t == first) => first,
+            _ => return None,
+        };
+        Some(Type::Array(Box::new(element), items.len()))
+    }
+
+    /// The type of a `?:` choosing between `a` and `b`, which must agree.
+    fn type_of_branches(&self, a: &AstNode, b: &AstNode) -> Option<Type> {
+        let mut branches = [self.type_of(a)?, self.type_of(b)?];
+        adapt_literals(&[a, b], &mut branches);
+        (branches[0] == branches[1]).then(|| branches[0].clone())
+    }
+
+    /// The type of an expression, where it can be worked out from declarations.
+    fn type_of(&self, expr: &AstNode) -> Option<Type> {
+        match &expr.kind {
+            NodeKind::LiteralFloat { .. } => Some(Type::FLOAT),
+            NodeKind::LiteralInt(_) => Some(Type::INT),
+            NodeKind::LiteralUint(_) => Some(Type::UINT),
+            NodeKind::LiteralBool(_) => Some(Type::BOOL),
+            NodeKind::Variable(name) => self.scopes.iter().rev().find_map(|s| s.get(name)).cloned().flatten()
+                .or_else(|| UNIFORMS.iter().find(|(u, _)| u == name).map(|(_, t)| Type::parse(t))),
+            NodeKind::StructLiteral { name, .. } => Some(Type::Struct(name.clone())),
+            NodeKind::ArrayLiteral(items) => self.type_of_elements(items),
+            NodeKind::Call { func_name, args, overload, .. } => match self.signature(func_name, overload) {
+                Some(signature) => Some(Type::parse(&signature.return_type)),
+                None => {
+                    let mut types = args.iter().map(|a| self.type_of(a)).collect::<Option<Vec<_>>>()?;
+                    match Type::parse(func_name) {
+                        Type::Struct(_) | Type::Void => {
+                            adapt_literals(&args.iter().collect::<Vec<_>>(), &mut types);
+                            builtins::result(func_name, &types).ok()
+                        },
+                        target => types::constructor_result(&target, &types).ok(),
+                    }
+                },
+            },
+            NodeKind::MethodCall { method, receiver_type, .. } => {
+                self.functions.get(&method_name(receiver_type, method)).map(|s| Type::parse(&s[0].return_type))
+            },
+            NodeKind::MemberAccess { base, member } => match self.type_of(base)? {
+                Type::Struct(name) => {
+                    self.structs.get(&name)?.iter().find(|f| &f.name == member).map(|f| Type::parse(&f.type_name))
+                },
+                base => types::swizzle_result(&base, member),
+            },
+            NodeKind::Swizzle { base, components } => types::swizzle_result(&self.type_of(base)?, components),
+            NodeKind::SubscriptAccess { base, .. } => types::index_result(&self.type_of(base)?),
+            NodeKind::Ternary { then_expr, else_expr, .. } => self.type_of_branches(then_expr, else_expr),
+            NodeKind::UnaryOp { op, right } => types::unary_result(op, &self.type_of(right)?),
+            NodeKind::BinaryOp { left, op, right } => {
+                let mut operands = [self.type_of(left)?, self.type_of(right)?];
+                adapt_literals(&[left, right], &mut operands);
+                types::binary_result(op, &operands[0], &operands[1])
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Rewrites the integer literal `3` or `-3` as `3.0` or `-3.0`.
+fn float_literal(node: &mut AstNode) {
+    match &mut node.kind {
+        NodeKind::LiteralInt(i) => {
+            let i = *i;
+            node.kind = NodeKind::LiteralFloat { value: i as f64, text: format!("{}.0", i) };
+        },
+        NodeKind::UnaryOp { right, .. } => float_literal(right),
+        _ => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::tokenize;
+    use crate::parser::Parser;
+    use crate::source_map::SourceMap;
+
+    fn errors(src: &str) -> Vec<String> {
+        let (tokens, _) = tokenize(&SourceMap::single("t.sumi", src));
+        Parser::new(tokens).parse().err().unwrap_or_default().into_iter().map(|e| e.message).collect()
+    }
+
+    #[test]
+    fn test_overload_resolution_errors() {
+        let src = "fn sq(x: float) float { return x * x; }\n\
+                   fn sq(v: vec2) float { return dot(v, v); }\n\
+                   fn sq(y: float) float { return y; }\n\
+                   fn f(p: vec3) float { return sq(p) + sq(1.0); }";
+        assert_eq!(errors(src), vec![
+            "function `sq` is already defined with parameters (float)",
+            "no overload of `sq` takes (vec3)",
+        ]);
+    }
+
+    #[test]
+    fn test_duplicate_methods() {
+        let src = "struct Ray { o: vec3, d: vec3 }\n\
+                   impl Ray { fn at(self, t: float) vec3 { return self.o + self.d * t; } }\n\
+                   impl Ray { fn at(self, t: vec3) vec3 { return self.o + t; } fn len(self) float { return length(self.d); } }";
+        assert_eq!(errors(src), vec!["method `at` is already defined for `Ray`"]);
+    }
+
+    #[test]
+    fn test_overload_symbols() {
+        assert_eq!(super::overload_symbol("blur", &["array<float, 3>".into(), "int".into()]), "blur_array_float_3_int");
+        assert_eq!(super::overload_symbol("seed", &[]), "seed_void");
+    }
+}
@@ -0,0 +1,151 @@
+use crate::diagnostics::Span;
+
+/// Index of a file inside a [`SourceMap`].
+pub type FileId = usize;
+
+/// A named piece of source text with a line index for diagnostics.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, text: impl Into<String>) -> Self {
+        let text = text.into();
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        Self { name: name.into(), text, line_starts }
+    }
+
+    /// 1-based (line, column) of a byte offset.
+    pub fn location(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&s| s <= offset) - 1;
+        let column = self.text[self.line_starts[line]..offset].chars().count() + 1;
+        (line + 1, column)
+    }
+
+    /// Text of a 1-based line, without its newline.
+    pub fn line_text(&self, line: usize) -> &str {
+        let Some(&start) = self.line_starts.get(line.saturating_sub(1)) else { return "" };
+        let end = self.line_starts.get(line).map(|e| e - 1).unwrap_or(self.text.len());
+        self.text[start..end].trim_end_matches('\r')
+    }
+}
+
+/// A run of merged text copied verbatim from one original file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    /// Offset of the run in the merged text.
+    pub start: usize,
+    pub file: FileId,
+    /// Offset of the run in the original file.
+    pub file_offset: usize,
+    /// Output of a macro: all of it maps to `file_offset`, where the macro was used.
+    pub expansion: bool,
+}
+
+/// The preprocessed program: the merged text handed to the lexer, plus
+/// enough bookkeeping to map any merged offset back to its original file.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+    segments: Vec<Segment>,
+    text: String,
+}
+
+impl SourceMap {
+    pub fn new() -> Self { Self::default() }
+
+    /// A map holding a single file, with no includes.
+    pub fn single(name: impl Into<String>, text: impl Into<String>) -> Self {
+        let mut map = Self::new();
+        let file = map.add_file(SourceFile::new(name, text));
+        let text = map.files[file].text.clone();
+        map.push_text(file, 0, &text);
+        map
+    }
+
+    /// An empty map for the next separately lexed unit (an imported module),
+    /// keeping this map's files so that spans from either resolve in the new one.
+    pub fn next_unit(&self) -> Self {
+        Self { files: self.files.clone(), ..Self::default() }
+    }
+
+    pub fn add_file(&mut self, file: SourceFile) -> FileId {
+        self.files.push(file);
+        self.files.len() - 1
+    }
+
+    /// Appends `text`, which starts at `file_offset` in `file`, to the merged output.
+    pub fn push_text(&mut self, file: FileId, file_offset: usize, text: &str) {
+        if text.is_empty() { return; }
+        self.segments.push(Segment { start: self.text.len(), file, file_offset, expansion: false });
+        self.text.push_str(text);
+    }
+
+    /// Appends the expansion of a macro used at `file_offset` in `file`.
+    pub fn push_expansion(&mut self, file: FileId, file_offset: usize, text: &str) {
+        if text.is_empty() { return; }
+        self.segments.push(Segment { start: self.text.len(), file, file_offset, expansion: true });
+        self.text.push_str(text);
+    }
+
+    /// The merged text, as seen by the lexer.
+    pub fn text(&self) -> &str { &self.text }
+
+    pub fn file(&self, id: FileId) -> &SourceFile { &self.files[id] }
+
+    pub fn segments(&self) -> &[Segment] { &self.segments }
+
+    /// Maps a merged byte offset to `(file, offset in that file)`.
+    pub fn resolve(&self, offset: usize) -> (FileId, usize) {
+        match self.segments.partition_point(|s| s.start <= offset).checked_sub(1) {
+            Some(i) => {
+                let seg = &self.segments[i];
+                if seg.expansion { return (seg.file, seg.file_offset); }
+                (seg.file, seg.file_offset + (offset - seg.start))
+            }
+            None => (0, offset),
+        }
+    }
+
+    /// Builds a span for the merged range `start..end`, expressed in the original file.
+    pub fn span(&self, start: usize, end: usize) -> Span {
+        let (file, file_start) = self.resolve(start);
+        let (line, column) = self.files.get(file).map(|f| f.location(file_start)).unwrap_or((1, 1));
+        Span { file, start: file_start, end: file_start + (end - start), line, column }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_location() {
+        let src = SourceFile::new("t.sumi", "fn a() {\n    return 1.0\n}");
+        assert_eq!(src.location(0), (1, 1));
+        assert_eq!(src.location(13), (2, 5));
+        assert_eq!(src.line_text(2), "    return 1.0");
+    }
+
+    #[test]
+    fn test_spans_resolve_to_original_file() {
+        // main.sumi: "A\n#include \"lib.sumi\"\nB" with lib.sumi = "x\ny"
+        let mut map = SourceMap::new();
+        let main = map.add_file(SourceFile::new("main.sumi", "A\n#include \"lib.sumi\"\nB"));
+        let lib = map.add_file(SourceFile::new("lib.sumi", "x\ny"));
+        map.push_text(main, 0, "A\n");
+        map.push_text(lib, 0, "x\ny");
+        map.push_text(main, 21, "\nB");
+        assert_eq!(map.text(), "A\nx\ny\nB");
+
+        let y = map.span(4, 5);
+        assert_eq!((y.file, y.line, y.column), (lib, 2, 1));
+        let b = map.span(6, 7);
+        assert_eq!((b.file, b.start, b.line, b.column), (main, 22, 3, 1));
+    }
+}
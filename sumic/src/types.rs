@@ -0,0 +1,341 @@
+use std::fmt;
+
+use crate::ast::{split_array_type, AstNode, BinaryOperator, NodeKind, UnaryOperator};
+
+/// The element type of scalars, vectors and matrices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scalar {
+    Float,
+    Int,
+    Uint,
+    Bool,
+}
+
+impl Scalar {
+    pub fn is_numeric(self) -> bool {
+        self != Scalar::Bool
+    }
+
+    pub fn is_integer(self) -> bool {
+        matches!(self, Scalar::Int | Scalar::Uint)
+    }
+
+    fn name(self) -> &'static str {
+        match self { Scalar::Float => "float", Scalar::Int => "int", Scalar::Uint => "uint", Scalar::Bool => "bool" }
+    }
+
+    /// `vec3`, `ivec3`, `uvec3`, `bvec3`.
+    fn vector_prefix(self) -> &'static str {
+        match self { Scalar::Float => "", Scalar::Int => "i", Scalar::Uint => "u", Scalar::Bool => "b" }
+    }
+}
+
+/// The type of an S2L value. Written back out with `Display`, so error
+/// messages use the S2L spelling.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Void,
+    Scalar(Scalar),
+    Vector(Scalar, usize),
+    /// A square float matrix, `mat2` to `mat4`.
+    Matrix(usize),
+    Struct(String),
+    Array(Box<Type>, usize),
+}
+
+impl Type {
+    pub const FLOAT: Type = Type::Scalar(Scalar::Float);
+    pub const INT: Type = Type::Scalar(Scalar::Int);
+    pub const UINT: Type = Type::Scalar(Scalar::Uint);
+    pub const BOOL: Type = Type::Scalar(Scalar::Bool);
+
+    /// Reads a type as written in a declaration. The WGSL scalar names
+    /// (`f32`, `i32`, `u32`) are accepted too; anything unrecognised is taken
+    /// to be a struct name.
+    pub fn parse(name: &str) -> Type {
+        let name = name.trim();
+        match name {
+            "" | "void" => return Type::Void,
+            "float" | "f32" => return Type::FLOAT,
+            "int" | "i32" => return Type::INT,
+            "uint" | "u32" => return Type::UINT,
+            "bool" => return Type::BOOL,
+            _ => {},
+        }
+        if let Some((element, size)) = split_array_type(name) {
+            return Type::Array(Box::new(Type::parse(element)), size);
+        }
+        let width = |n: &str| match n { "2" => Some(2), "3" => Some(3), "4" => Some(4), _ => None };
+        if let Some(n) = name.strip_prefix("mat").and_then(width) {
+            return Type::Matrix(n);
+        }
+        for scalar in [Scalar::Float, Scalar::Int, Scalar::Uint, Scalar::Bool] {
+            let prefix = format!("{}vec", scalar.vector_prefix());
+            if let Some(n) = name.strip_prefix(prefix.as_str()).and_then(width) {
+                return Type::Vector(scalar, n);
+            }
+        }
+        Type::Struct(name.to_string())
+    }
+
+    /// The element type of a scalar, vector or matrix.
+    pub fn scalar(&self) -> Option<Scalar> {
+        match self {
+            Type::Scalar(s) | Type::Vector(s, _) => Some(*s),
+            Type::Matrix(_) => Some(Scalar::Float),
+            _ => None,
+        }
+    }
+
+    /// A float scalar or float vector: what most built-ins work on.
+    pub fn is_float_like(&self) -> bool {
+        matches!(self, Type::Scalar(Scalar::Float) | Type::Vector(Scalar::Float, _))
+    }
+
+    /// A scalar or vector of the same width with `element` as its element type.
+    pub fn with_element(&self, element: Scalar) -> Type {
+        match self {
+            Type::Vector(_, n) => Type::Vector(element, *n),
+            _ => Type::Scalar(element),
+        }
+    }
+
+    /// How many scalars this contributes to a vector constructor.
+    fn components(&self) -> Option<usize> {
+        match self {
+            Type::Scalar(_) => Some(1),
+            Type::Vector(_, n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Void => write!(f, "void"),
+            Type::Scalar(s) => write!(f, "{}", s.name()),
+            Type::Vector(s, n) => write!(f, "{}vec{}", s.vector_prefix(), n),
+            Type::Matrix(n) => write!(f, "mat{}", n),
+            Type::Struct(name) => write!(f, "{}", name),
+            Type::Array(element, n) => write!(f, "array<{}, {}>", element, n),
+        }
+    }
+}
+
+/// The type of `left op right`, or `None` if the operator does not apply.
+/// Scalars combine with vectors of the same element type component-wise;
+/// there are no implicit conversions between element types.
+pub fn binary_result(op: &BinaryOperator, left: &Type, right: &Type) -> Option<Type> {
+    use BinaryOperator::*;
+    match op {
+        Add | Sub | Mul | MatrixMul | Div | Mod => arithmetic_result(op, left, right),
+        Less | Greater | LessEqual | GreaterEqual => match (left, right) {
+            (Type::Scalar(a), Type::Scalar(b)) if a == b && a.is_numeric() => Some(Type::BOOL),
+            _ => None,
+        },
+        Equal | NotEqual => match (left, right) {
+            (Type::Scalar(a), Type::Scalar(b)) if a == b => Some(Type::BOOL),
+            _ => None,
+        },
+        And | Or => (*left == Type::BOOL && *right == Type::BOOL).then_some(Type::BOOL),
+        BitAnd | BitOr | BitXor => {
+            let element = left.scalar().filter(|s| s.is_integer())?;
+            component_wise(left, right).filter(|_| right.scalar() == Some(element) && !matches!(left, Type::Matrix(_)))
+        },
+        ShiftLeft | ShiftRight => {
+            let shifts = matches!(left, Type::Scalar(_) | Type::Vector(..)) && left.scalar().is_some_and(|s| s.is_integer());
+            let counts = right.scalar().is_some_and(|s| s.is_integer())
+                && (matches!(right, Type::Scalar(_)) || matches!((left, right), (Type::Vector(_, n), Type::Vector(_, m)) if n == m));
+            (shifts && counts).then(|| left.clone())
+        },
+    }
+}
+
+fn arithmetic_result(op: &BinaryOperator, left: &Type, right: &Type) -> Option<Type> {
+    let is_mul = matches!(op, BinaryOperator::Mul | BinaryOperator::MatrixMul);
+    match (left, right) {
+        (Type::Matrix(n), Type::Matrix(m)) if n == m && (is_mul || matches!(op, BinaryOperator::Add | BinaryOperator::Sub)) => Some(left.clone()),
+        (Type::Matrix(_), Type::Scalar(Scalar::Float)) | (Type::Scalar(Scalar::Float), Type::Matrix(_)) if is_mul => {
+            Some(if matches!(left, Type::Matrix(_)) { left.clone() } else { right.clone() })
+        },
+        (Type::Matrix(n), Type::Vector(Scalar::Float, m)) | (Type::Vector(Scalar::Float, m), Type::Matrix(n)) if is_mul && n == m => {
+            Some(Type::Vector(Scalar::Float, *n))
+        },
+        _ => {
+            let element = left.scalar().filter(|s| s.is_numeric())?;
+            if *op == BinaryOperator::Mod && !element.is_integer() {
+                return None;
+            }
+            component_wise(left, right).filter(|_| right.scalar() == Some(element))
+        },
+    }
+}
+
+/// Same-width vectors, or a scalar mixed with a vector (or scalar).
+fn component_wise(left: &Type, right: &Type) -> Option<Type> {
+    match (left, right) {
+        (Type::Scalar(_), Type::Scalar(_)) => Some(left.clone()),
+        (Type::Vector(_, n), Type::Vector(_, m)) if n == m => Some(left.clone()),
+        (Type::Vector(..), Type::Scalar(_)) => Some(left.clone()),
+        (Type::Scalar(_), Type::Vector(..)) => Some(right.clone()),
+        _ => None,
+    }
+}
+
+/// The type of `op operand`, or `None` if the operator does not apply.
+pub fn unary_result(op: &UnaryOperator, operand: &Type) -> Option<Type> {
+    let ok = match op {
+        UnaryOperator::Negate => matches!(operand.scalar(), Some(Scalar::Float | Scalar::Int)),
+        UnaryOperator::Not => matches!(operand, Type::Scalar(Scalar::Bool) | Type::Vector(Scalar::Bool, _)),
+        UnaryOperator::BitNot => operand.scalar().is_some_and(|s| s.is_integer()) && !matches!(operand, Type::Matrix(_)),
+    };
+    ok.then(|| operand.clone())
+}
+
+/// The type of `base[i]`.
+pub fn index_result(base: &Type) -> Option<Type> {
+    match base {
+        Type::Array(element, _) => Some(element.as_ref().clone()),
+        Type::Vector(s, _) => Some(Type::Scalar(*s)),
+        Type::Matrix(n) => Some(Type::Vector(Scalar::Float, *n)),
+        _ => None,
+    }
+}
+
+/// The component indices of a swizzle like `xzy` or `rgb`: 1 to 4 letters,
+/// all from one of the sets `xyzw`, `rgba` or `stpq`.
+pub fn swizzle_indices(components: &str) -> Option<Vec<usize>> {
+    if !(1..=4).contains(&components.len()) {
+        return None;
+    }
+    ["xyzw", "rgba", "stpq"].iter().find_map(|set| components.chars().map(|c| set.find(c)).collect())
+}
+
+/// The type of a swizzle of `base`, or `None` if it is not a vector wide
+/// enough for every component.
+pub fn swizzle_result(base: &Type, components: &str) -> Option<Type> {
+    let Type::Vector(s, width) = base else { return None };
+    let indices = swizzle_indices(components)?;
+    if indices.iter().any(|i| i >= width) {
+        return None;
+    }
+    Some(if indices.len() == 1 { Type::Scalar(*s) } else { Type::Vector(*s, indices.len()) })
+}
+
+/// `3` or `-3`: accepted where a float is expected, as in WGSL.
+pub fn is_int_literal(node: &AstNode) -> bool {
+    match &node.kind {
+        NodeKind::LiteralInt(_) => true,
+        NodeKind::UnaryOp { op: UnaryOperator::Negate, right } => is_int_literal(right),
+        _ => false,
+    }
+}
+
+/// Gives integer literals mixed with floats the float type, so `x * 2` and
+/// `clamp(v, 0, 1)` check like their WGSL counterparts.
+pub fn adapt_literals(nodes: &[&AstNode], types: &mut [Type]) {
+    if types.iter().any(|t| t.scalar() == Some(Scalar::Float)) {
+        for (node, t) in nodes.iter().zip(types.iter_mut()) {
+            if is_int_literal(node) { *t = Type::FLOAT; }
+        }
+    }
+}
+
+/// Checks a call to a type's constructor (`float(i)`, `vec3(v.xy, 1.0)`,
+/// `mat2(c, -s, s, c)`) and returns the constructed type.
+pub fn constructor_result(target: &Type, args: &[Type]) -> Result<Type, String> {
+    match target {
+        Type::Scalar(_) => match args {
+            [Type::Scalar(_)] => Ok(target.clone()),
+            _ => Err(format!("`{}(..)` converts a single scalar", target)),
+        },
+        Type::Vector(_, n) => {
+            // `vec3()` is zero, `vec3(x)` repeats `x`.
+            if args.is_empty() || matches!(args, [Type::Scalar(_)]) {
+                return Ok(target.clone());
+            }
+            let given: Option<usize> = args.iter().map(|a| a.components()).sum();
+            match given {
+                Some(given) if given == *n => Ok(target.clone()),
+                Some(given) => Err(format!("`{}` needs {} components but {} were given", target, n, given)),
+                None => Err(format!("`{}` is built from scalars and vectors", target)),
+            }
+        },
+        Type::Matrix(n) => {
+            let columns = args.len() == *n && args.iter().all(|a| *a == Type::Vector(Scalar::Float, *n));
+            let scalars = args.len() == n * n && args.iter().all(|a| matches!(a, Type::Scalar(s) if s.is_numeric()));
+            // `mat3(1.0)` is the identity scaled by 1.0; `mat3(m4)` takes the
+            // upper-left of a larger matrix or extends a smaller one.
+            let single = matches!(args, [Type::Matrix(_)]) || matches!(args, [Type::Scalar(s)] if s.is_numeric());
+            if columns || scalars || single {
+                Ok(target.clone())
+            } else {
+                Err(format!("`{}` is built from {} `vec{}` columns, {} scalars, one scalar or another matrix", target, n, n, n * n))
+            }
+        },
+        _ => Err(format!("`{}` has no constructor", target)),
+    }
+}
+
+/// Whether `from` converts implicitly to `to`: integers promote to floats,
+/// element by element.
+pub fn promotes(from: &Type, to: &Type) -> bool {
+    match (from, to) {
+        (Type::Scalar(a), Type::Scalar(Scalar::Float)) => a.is_integer(),
+        (Type::Vector(a, n), Type::Vector(Scalar::Float, m)) => a.is_integer() && n == m,
+        _ => false,
+    }
+}
+
+/// The types to convert a constructor's arguments to: each component takes
+/// the constructed type's element type.
+pub fn constructor_params(target: &Type, args: &[Type]) -> Vec<Type> {
+    let Some(element) = target.scalar() else { return args.to_vec() };
+    args.iter().map(|a| match a {
+        Type::Scalar(s) | Type::Vector(s, _) if s.is_numeric() && element.is_numeric() && matches!(target, Type::Vector(..) | Type::Matrix(_)) => a.with_element(element),
+        _ => a.clone(),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        for name in ["float", "vec3", "ivec2", "bvec4", "mat3", "array<vec2, 4>", "Ray"] {
+            assert_eq!(Type::parse(name).to_string(), name);
+        }
+        assert_eq!(Type::parse("i32"), Type::INT);
+        assert_eq!(Type::parse("array<float, 3>"), Type::Array(Box::new(Type::FLOAT), 3));
+    }
+
+    #[test]
+    fn test_operators() {
+        let (vec2, vec3) = (Type::parse("vec2"), Type::parse("vec3"));
+        assert_eq!(binary_result(&BinaryOperator::Add, &vec3, &vec2), None);
+        assert_eq!(binary_result(&BinaryOperator::Mul, &Type::FLOAT, &vec3), Some(vec3.clone()));
+        assert_eq!(binary_result(&BinaryOperator::Mul, &Type::Matrix(3), &vec3), Some(vec3.clone()));
+        assert_eq!(binary_result(&BinaryOperator::Mul, &Type::INT, &Type::FLOAT), None);
+        assert_eq!(binary_result(&BinaryOperator::Mod, &Type::FLOAT, &Type::FLOAT), None);
+        assert_eq!(binary_result(&BinaryOperator::Less, &Type::FLOAT, &Type::FLOAT), Some(Type::BOOL));
+        assert_eq!(swizzle_result(&vec3, "xzy"), Some(vec3.clone()));
+        assert_eq!(swizzle_result(&vec3, "xg"), None);
+        assert_eq!(swizzle_result(&Type::parse("vec2"), "xz"), None);
+        assert_eq!(swizzle_indices("bgr"), Some(vec![2, 1, 0]));
+    }
+
+    #[test]
+    fn test_constructors() {
+        let vec4 = Type::parse("vec4");
+        let vec2 = Type::parse("vec2");
+        assert!(constructor_result(&vec4, &[vec2.clone(), Type::FLOAT, Type::FLOAT]).is_ok());
+        assert!(constructor_result(&vec4, &[Type::FLOAT]).is_ok());
+        assert_eq!(constructor_result(&vec4, &[vec2.clone(), vec2.clone(), Type::FLOAT]).unwrap_err(), "`vec4` needs 4 components but 5 were given");
+        assert!(constructor_result(&Type::Matrix(2), &[vec2.clone(), vec2.clone()]).is_ok());
+        assert!(constructor_result(&Type::Matrix(3), &[Type::FLOAT]).is_ok());
+        assert!(constructor_result(&Type::Matrix(3), &[Type::Matrix(4)]).is_ok());
+        assert!(constructor_result(&Type::Matrix(3), &[vec2]).is_err());
+    }
+}